@@ -0,0 +1,265 @@
+// Run with `cargo bench --features bench`.
+#[macro_use]
+extern crate criterion;
+extern crate cql;
+
+use criterion::{black_box, Criterion};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use cql::bench;
+use cql::{
+    BatchQuery, BatchType, ClientBuilder, ColumnType, Consistency, CqlColDescr, ResponseBody, Value,
+};
+
+fn short_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn bytes(buf: &mut Vec<u8>, b: &[u8]) {
+    buf.extend_from_slice(&(b.len() as i32).to_be_bytes());
+    buf.extend_from_slice(b);
+}
+
+fn ten_values() -> Vec<Value> {
+    vec![
+        Value::from(1),
+        Value::from(2i64),
+        Value::from("a short string"),
+        Value::from(1.5f32),
+        Value::from(2.5f64),
+        Value::from(true),
+        Value::from(vec![0u8; 64]),
+        Value::from([7u8; 16]),
+        Value::CqlList(vec![Value::from(1), Value::from(2), Value::from(3)]),
+        Value::CqlNull,
+    ]
+}
+
+// A RESULT frame of `rows` rows of (id int, name varchar, tags list<text>).
+fn select_frame(rows: i32) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&2i32.to_be_bytes());
+    body.extend_from_slice(&1i32.to_be_bytes());
+    body.extend_from_slice(&3i32.to_be_bytes());
+    short_string(&mut body, "rust");
+    short_string(&mut body, "test");
+    short_string(&mut body, "id");
+    body.extend_from_slice(&[0, 0x09]);
+    short_string(&mut body, "name");
+    body.extend_from_slice(&[0, 0x0D]);
+    short_string(&mut body, "tags");
+    body.extend_from_slice(&[0, 0x20, 0, 0x0D]);
+    body.extend_from_slice(&rows.to_be_bytes());
+    for i in 0..rows {
+        bytes(&mut body, &i.to_be_bytes());
+        bytes(&mut body, format!("name {}", i).as_bytes());
+        let mut tags = Vec::new();
+        tags.extend_from_slice(&3i32.to_be_bytes());
+        for tag in &["red", "green", "blue"] {
+            bytes(&mut tags, tag.as_bytes());
+        }
+        bytes(&mut body, &tags);
+    }
+
+    let mut frame = vec![0x83, 0, 0, 0, 0x08];
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+// A RESULT frame of one row of `columns` int columns, c0 = 0, c1 = 1 and so
+// on.
+fn wide_frame(columns: i32) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&2i32.to_be_bytes());
+    body.extend_from_slice(&1i32.to_be_bytes());
+    body.extend_from_slice(&columns.to_be_bytes());
+    short_string(&mut body, "rust");
+    short_string(&mut body, "wide");
+    for i in 0..columns {
+        short_string(&mut body, &format!("c{}", i));
+        body.extend_from_slice(&[0, 0x09]);
+    }
+    body.extend_from_slice(&1i32.to_be_bytes());
+    for i in 0..columns {
+        bytes(&mut body, &i.to_be_bytes());
+    }
+
+    let mut frame = vec![0x83, 0, 0, 0, 0x08];
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+// A server on a local port that answers STARTUP with READY and every other
+// request with a void RESULT, flushing once it has no more requests to read.
+fn void_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let socket = socket.unwrap();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(socket.try_clone().unwrap());
+                let mut writer = BufWriter::new(socket);
+                let mut header = [0u8; 9];
+                while reader.read_exact(&mut header).is_ok() {
+                    let len = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+                    let mut body = vec![0; len as usize];
+                    reader.read_exact(&mut body).unwrap();
+                    let mut resp = vec![0x80 | header[0], 0, header[2], header[3]];
+                    if header[4] == 0x01 {
+                        resp.extend_from_slice(&[0x02, 0, 0, 0, 0]);
+                    } else {
+                        resp.extend_from_slice(&[0x08, 0, 0, 0, 4, 0, 0, 0, 1]);
+                    }
+                    writer.write_all(&resp).unwrap();
+                    if reader.buffer().is_empty() {
+                        writer.flush().unwrap();
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn serialize_query(c: &mut Criterion) {
+    let values = ten_values();
+    c.bench_function("serialize query with 10 values", |b| {
+        b.iter(|| {
+            bench::serialize_query(
+                black_box("insert into t (a, b, c, d, e, f, g, h, i, j) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"),
+                Consistency::Quorum,
+                values.clone(),
+            )
+            .unwrap()
+        })
+    });
+}
+
+fn serialize_batch(c: &mut Criterion) {
+    let queries: Vec<_> = (0..1000)
+        .map(|i| {
+            let values = vec![Value::from(i), Value::from(format!("row {}", i))];
+            BatchQuery::Prepared(vec![0xab; 16], values)
+        })
+        .collect();
+    c.bench_function("serialize batch of 1000 rows", |b| {
+        b.iter(|| {
+            bench::serialize_batch(BatchType::Unlogged, queries.clone(), Consistency::One).unwrap()
+        })
+    });
+}
+
+fn parse_select(c: &mut Criterion) {
+    let frame = select_frame(5000);
+    c.bench_function("parse select of 5000 rows", |b| {
+        b.iter(|| bench::parse_response(black_box(&frame)).unwrap())
+    });
+}
+
+fn column_lookup(c: &mut Criterion) {
+    let rows = match bench::parse_response(&wide_frame(50)).unwrap().into_body() {
+        ResponseBody::Result(result) => result.rows().unwrap(),
+        body => panic!("unexpected body: {:?}", body),
+    };
+    let row = &rows.rows()[0];
+    c.bench_function("get_column of 50 columns", |b| {
+        b.iter(|| {
+            (
+                row.get_column(black_box("c0")),
+                row.get_column(black_box("c49")),
+            )
+        })
+    });
+    c.bench_function("get of 50 columns", |b| {
+        b.iter(|| {
+            row.get::<i32>(black_box("c0")).unwrap() + row.get::<i32>(black_box("c49")).unwrap()
+        })
+    });
+}
+
+// Small requests sent back to back, with and without the socket buffers.
+fn pipelined_queries(c: &mut Criterion) {
+    let addr = void_server();
+    let query = bench::serialize_query(
+        "update t set a = 1 where k = 0",
+        Consistency::One,
+        Vec::new(),
+    )
+    .unwrap();
+    for &(capacity, name) in &[
+        (0, "100 pipelined queries, unbuffered"),
+        (8 * 1024, "100 pipelined queries, 8 KiB buffers"),
+    ] {
+        let mut client = ClientBuilder::new(&addr)
+            .buffer_capacity(capacity)
+            .connect()
+            .unwrap();
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let streams: Vec<_> = (0..100)
+                    .map(|_| client.send_frame(&query).unwrap())
+                    .collect();
+                client.flush().unwrap();
+                for stream in streams {
+                    client.recv_frame(stream).unwrap();
+                }
+            })
+        });
+    }
+}
+
+fn decode_values(c: &mut Criterion) {
+    c.bench_function("parse_varint", |b| {
+        b.iter(|| {
+            bench::parse_varint(black_box(&[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]))
+                .unwrap()
+                + bench::parse_varint(black_box(&[0x80, 0x00])).unwrap()
+                + bench::parse_varint(black_box(&[0x01])).unwrap()
+        })
+    });
+
+    let mut list = Vec::new();
+    list.extend_from_slice(&1000i32.to_be_bytes());
+    for i in 0..1000i32 {
+        bytes(&mut list, &i.to_be_bytes());
+    }
+    let mut value = Vec::new();
+    bytes(&mut value, &list);
+    let list_int = CqlColDescr::List(Box::new(CqlColDescr::Single(ColumnType::Int)));
+    c.bench_function("decode list<int> of 1000", |b| {
+        b.iter(|| bench::decode_value(black_box(&value), &list_int).unwrap())
+    });
+
+    let mut map = Vec::new();
+    map.extend_from_slice(&100i32.to_be_bytes());
+    for i in 0..100i32 {
+        bytes(&mut map, format!("key {}", i).as_bytes());
+        bytes(&mut map, &i.to_be_bytes());
+    }
+    let mut value = Vec::new();
+    bytes(&mut value, &map);
+    let map_text_int = CqlColDescr::Map(Box::new((
+        CqlColDescr::Single(ColumnType::VarChar),
+        CqlColDescr::Single(ColumnType::Int),
+    )));
+    c.bench_function("decode map<text, int> of 100", |b| {
+        b.iter(|| bench::decode_value(black_box(&value), &map_text_int).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    serialize_query,
+    serialize_batch,
+    parse_select,
+    column_lookup,
+    pipelined_queries,
+    decode_values
+);
+criterion_main!(benches);
@@ -23,12 +23,12 @@ fn run() -> cql::Result<()> {
     )?;
     println!("create table: {:?}", res);
 
-    let prepare_id =
+    let statement =
         client.prepare("insert into rust.test (v1, v2, v3, v4) values ('asdf', ?, ?, ?)")?;
-    println!("prepare: {:?}", prepare_id);
+    println!("prepare: {:?}", statement.id());
 
     let res = client.execute(
-        prepare_id,
+        &statement,
         cql::Consistency::One,
         vec![
             cql::Value::CqlFloat(1.2345),
@@ -0,0 +1,34 @@
+// The serialization and decoding internals, for benches/serialization.rs.
+// Only built with the "bench" feature and not part of the API.
+
+use super::{
+    batch, parse_varint as varint, query, BatchQuery, BatchType, Consistency, CqlColDescr,
+    CqlReader, CqlSerializable, Response, Result, Value,
+};
+
+pub fn serialize_query(query_str: &str, con: Consistency, values: Vec<Value>) -> Result<Vec<u8>> {
+    query(0, query_str, con, values).to_vec()
+}
+
+pub fn serialize_batch(
+    batch_type: BatchType,
+    queries: Vec<BatchQuery>,
+    con: Consistency,
+) -> Result<Vec<u8>> {
+    batch(0, batch_type, queries, con).to_vec()
+}
+
+// `frame` is a whole response frame, read at the version in its header.
+pub fn parse_response(mut frame: &[u8]) -> Result<Response> {
+    let version = frame[0] & 0x7f;
+    frame.read_cql_response(version)
+}
+
+pub fn parse_varint(v: &[u8]) -> Result<i64> {
+    varint(v)
+}
+
+// `bytes` is a [bytes] value, length first.
+pub fn decode_value(mut bytes: &[u8], col_type: &CqlColDescr) -> Result<Value> {
+    bytes.read_cql_col(col_type)
+}
@@ -0,0 +1,505 @@
+use std::convert::TryFrom;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+
+use super::{
+    options, Client, ClientBuilder, Consistency, CqlReader, CqlSerializable, Error, ErrorCode,
+    Response, ResponseBody, Result, Row, Rows, StringMultiMap, Value, CQL_VERSION,
+};
+
+// Protocol versions this crate can speak, lowest first.
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u8] = &[0x03, 0x04];
+
+pub struct Cluster {
+    nodes: Vec<(String, Client)>,
+    protocol_version: u8,
+}
+
+impl Cluster {
+    pub fn connect(addrs: &[&str]) -> Result<Cluster> {
+        let mut reachable = Vec::with_capacity(addrs.len());
+        let mut advertised = Vec::with_capacity(addrs.len());
+        let mut last_err = None;
+        for addr in addrs {
+            match probe(addr) {
+                Ok(supported) => {
+                    reachable.push(*addr);
+                    advertised.push(protocol_versions(&supported));
+                }
+                Err(e) => {
+                    warn!("failed to probe {}: {:?}", addr, e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        if reachable.is_empty() {
+            return Err(last_err.unwrap_or(Error::Protocol));
+        }
+
+        let protocol_version = match pick_protocol_version(&advertised) {
+            Some(version) => version,
+            None => return Err(Error::Protocol),
+        };
+
+        let mut nodes = Vec::with_capacity(reachable.len());
+        for addr in reachable {
+            let client = ClientBuilder::new(addr)
+                .protocol_version(protocol_version)
+                .connect()?;
+            nodes.push((addr.to_owned(), client));
+        }
+
+        Ok(Cluster {
+            nodes,
+            protocol_version,
+        })
+    }
+
+    // Uses every address `host` resolves to as a contact point, for a DNS
+    // name covering all the seed nodes.
+    pub fn connect_host(host: &str, port: u16) -> Result<Cluster> {
+        Cluster::connect_resolved((host, port))
+    }
+
+    fn connect_resolved<A: ToSocketAddrs>(addrs: A) -> Result<Cluster> {
+        let mut resolved: Vec<String> = Vec::new();
+        for addr in addrs.to_socket_addrs()? {
+            let addr = addr.to_string();
+            if !resolved.contains(&addr) {
+                resolved.push(addr);
+            }
+        }
+        let resolved: Vec<&str> = resolved.iter().map(|addr| addr.as_str()).collect();
+        Cluster::connect(&resolved)
+    }
+
+    pub fn protocol_version(&self) -> u8 {
+        self.protocol_version
+    }
+
+    pub fn nodes(&self) -> Vec<&str> {
+        self.nodes.iter().map(|node| node.0.as_str()).collect()
+    }
+
+    // Connects to the peers the first node knows of which aren't connected
+    // yet, on the port they give or else the first node's. Peers which
+    // can't be reached are skipped.
+    pub fn refresh_topology(&mut self) -> Result<()> {
+        let (port, peers) = match self.nodes.first_mut() {
+            Some(node) => {
+                let port = match node.0.rsplit(':').next().map(|port| port.parse::<u16>()) {
+                    Some(Ok(port)) => port,
+                    _ => return Err(Error::Protocol),
+                };
+                (port, read_peers(&mut node.1)?)
+            }
+            None => return Ok(()),
+        };
+
+        for row in peers.as_ref().map_or(&[][..], |rows| rows.rows()) {
+            let addr = match peer_address(row) {
+                Some(addr) => SocketAddr::new(addr, peer_port(row).unwrap_or(port)).to_string(),
+                None => {
+                    warn!("no address for peer: {:?}", row);
+                    continue;
+                }
+            };
+            if self.nodes.iter().any(|node| node.0 == addr) {
+                continue;
+            }
+            let client = ClientBuilder::new(&addr)
+                .protocol_version(self.protocol_version)
+                .connect();
+            match client {
+                Ok(client) => self.nodes.push((addr, client)),
+                Err(e) => warn!("failed to connect to peer {}: {:?}", addr, e),
+            }
+        }
+        Ok(())
+    }
+
+    // The connection to one node, as named in `nodes`, for requests which
+    // must go to that node, like reads of its system tables.
+    pub fn on_node(&mut self, addr: &str) -> Option<&mut Client> {
+        self.nodes
+            .iter_mut()
+            .find(|node| node.0 == addr)
+            .map(|node| &mut node.1)
+    }
+
+    pub fn query_on(
+        &mut self,
+        addr: &str,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Response> {
+        match self.on_node(addr) {
+            Some(client) => client.query(query_str, con, values),
+            None => Err(Error::UnknownNode(addr.to_owned())),
+        }
+    }
+}
+
+// Sends OPTIONS on a throwaway connection, before any STARTUP, so the node
+// can tell which protocol versions it accepts.
+fn probe(addr: &str) -> Result<StringMultiMap> {
+    let mut socket = TcpStream::connect(addr)?;
+    socket.write_all(&options().to_vec()?)?;
+    match socket.read_cql_response(CQL_VERSION)?.body {
+        ResponseBody::Supported(supported) => Ok(supported),
+        _ => Err(Error::Protocol),
+    }
+}
+
+// Parses the PROTOCOL_VERSIONS entry of a SUPPORTED response, e.g.
+// `["3/v3", "4/v4", "5/v5-beta"]`. Beta versions are skipped. Nodes which
+// predate the entry are assumed to speak the default version only.
+fn protocol_versions(supported: &StringMultiMap) -> Vec<u8> {
+    let values = match supported.iter().find(|e| e.0 == "PROTOCOL_VERSIONS") {
+        Some(entry) => &entry.1,
+        None => return vec![CQL_VERSION],
+    };
+    values
+        .iter()
+        .filter(|v| !v.ends_with("-beta"))
+        .filter_map(|v| v.split('/').next().and_then(|n| n.parse().ok()))
+        .collect()
+}
+
+// system.peers_v2, from Cassandra 4.0 on, gives each peer's native port
+// as well as its address. Older servers reject it as Invalid and only
+// have system.peers.
+fn read_peers(client: &mut Client) -> Result<Option<Rows>> {
+    match client.rows("select * from system.peers_v2") {
+        Err(ref e) if e.error_code() == Some(ErrorCode::Invalid) => {
+            client.rows("select * from system.peers")
+        }
+        peers => peers,
+    }
+}
+
+// The address a client should use for a row of system.peers or
+// system.peers_v2. `peer` is the address the node uses within the
+// cluster, which clients may not be able to reach; `rpc_address`
+// (`native_address` in system.peers_v2) is the one it serves clients on,
+// unless it's 0.0.0.0, which means it listens on every interface and
+// `preferred_ip`, if set, or `peer` is as good as any.
+fn peer_address(row: &Row) -> Option<IpAddr> {
+    for name in &["native_address", "rpc_address", "preferred_ip"] {
+        match row.get_column(name) {
+            Some(Value::CqlInet(addr)) if !addr.is_unspecified() => return Some(addr),
+            _ => (),
+        }
+    }
+    match row.get_column("peer") {
+        Some(Value::CqlInet(addr)) => Some(addr),
+        _ => None,
+    }
+}
+
+// Only system.peers_v2 has the port; peers listed in system.peers are
+// taken to use the same one as the node that lists them.
+fn peer_port(row: &Row) -> Option<u16> {
+    match row.get_column("native_port") {
+        Some(Value::CqlInt(port)) => u16::try_from(port).ok(),
+        _ => None,
+    }
+}
+
+fn pick_protocol_version(advertised: &[Vec<u8>]) -> Option<u8> {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .rev()
+        .find(|version| advertised.iter().all(|node| node.contains(version)))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, WriteBytesExt};
+    use std::io;
+    use std::net::{SocketAddr, TcpListener};
+    use std::vec;
+    use tests::{frame, frame_stream, mock_server, respond};
+    use Opcode;
+
+    fn supported(versions: &[&str]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.write_u16::<BigEndian>(1).unwrap();
+        body.write_u16::<BigEndian>(17).unwrap();
+        body.extend_from_slice(b"PROTOCOL_VERSIONS");
+        body.write_u16::<BigEndian>(versions.len() as u16).unwrap();
+        for v in versions {
+            body.write_u16::<BigEndian>(v.len() as u16).unwrap();
+            body.extend_from_slice(v.as_bytes());
+        }
+        body
+    }
+
+    #[test]
+    fn test_protocol_versions() {
+        let supported = vec![(
+            "PROTOCOL_VERSIONS".to_owned(),
+            vec!["3/v3".to_owned(), "4/v4".to_owned(), "5/v5-beta".to_owned()],
+        )];
+        assert_eq!(vec![3, 4], protocol_versions(&supported));
+        assert_eq!(vec![3], protocol_versions(&Vec::new()));
+    }
+
+    #[test]
+    fn test_pick_protocol_version() {
+        assert_eq!(Some(3), pick_protocol_version(&[vec![3, 4], vec![3]]));
+        assert_eq!(Some(4), pick_protocol_version(&[vec![3, 4], vec![3, 4, 5]]));
+        assert_eq!(None, pick_protocol_version(&[vec![4, 5], vec![3]]));
+    }
+
+    #[test]
+    fn connect_picks_common_version() {
+        let mut servers = Vec::new();
+        for versions in [&["3/v3", "4/v4", "5/v5-beta"][..], &["3/v3"][..]].iter() {
+            let body = supported(versions);
+            servers.push(mock_server(2, move |req| {
+                frame(frame_stream(req), Opcode::Supported, &body)
+            }));
+        }
+        let addrs: Vec<&str> = servers.iter().map(|s| s.0.as_str()).collect();
+
+        let cluster = Cluster::connect(&addrs).unwrap();
+        assert_eq!(3, cluster.protocol_version());
+        assert_eq!(addrs, cluster.nodes());
+        drop(cluster);
+
+        for (_, server) in servers {
+            let frames = server.join().unwrap();
+            assert_eq!(Opcode::Opts as u8, frames[0][4]);
+            assert_eq!(Opcode::Startup as u8, frames[1][4]);
+            assert_eq!(3, frames[1][0]);
+        }
+    }
+
+    // Resolves to a fixed list of addresses, like a DNS name with several
+    // A records.
+    struct Resolved(io::Result<Vec<SocketAddr>>);
+
+    impl ToSocketAddrs for Resolved {
+        type Iter = vec::IntoIter<SocketAddr>;
+
+        fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+            match self.0 {
+                Ok(ref addrs) => Ok(addrs.clone().into_iter()),
+                Err(ref e) => Err(io::Error::new(e.kind(), e.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn connect_resolved() {
+        let servers: Vec<_> = (0..2)
+            .map(|_| {
+                mock_server(2, |req| {
+                    frame(frame_stream(req), Opcode::Supported, &supported(&["3/v3"]))
+                })
+            })
+            .collect();
+        // A third address where nothing listens.
+        let down = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut addrs: Vec<SocketAddr> = servers.iter().map(|s| s.0.parse().unwrap()).collect();
+        addrs.push(addrs[0]);
+        addrs.push(down);
+
+        let cluster = Cluster::connect_resolved(Resolved(Ok(addrs))).unwrap();
+        assert_eq!(
+            vec![servers[0].0.as_str(), servers[1].0.as_str()],
+            cluster.nodes()
+        );
+        drop(cluster);
+        for (_, server) in servers {
+            server.join().unwrap();
+        }
+
+        let failed = Resolved(Err(io::Error::new(io::ErrorKind::NotFound, "no such host")));
+        match Cluster::connect_resolved(failed) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => (),
+            res => panic!("unexpected result: {:?}", res.map(|c| c.nodes().len())),
+        }
+    }
+
+    #[test]
+    fn query_on_node() {
+        let servers: Vec<_> = (0..2)
+            .map(|_| {
+                mock_server(2, |req| {
+                    if req[4] == Opcode::Opts as u8 {
+                        return frame(frame_stream(req), Opcode::Supported, &supported(&["3/v3"]));
+                    }
+                    respond(req, &frame(0, Opcode::Result, &[0, 0, 0, 1]))
+                })
+            })
+            .collect();
+        let addrs: Vec<&str> = servers.iter().map(|s| s.0.as_str()).collect();
+
+        let mut cluster = Cluster::connect(&addrs).unwrap();
+        cluster
+            .query_on(
+                addrs[1],
+                "select * from system.local",
+                Consistency::One,
+                Vec::new(),
+            )
+            .unwrap();
+        assert!(cluster.on_node(addrs[0]).is_some());
+        match cluster.query_on(
+            "127.0.0.1:1",
+            "select * from system.local",
+            Consistency::One,
+            Vec::new(),
+        ) {
+            Err(Error::UnknownNode(ref addr)) => assert_eq!("127.0.0.1:1", addr),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(cluster);
+
+        let frames: Vec<_> = servers.into_iter().map(|s| s.1.join().unwrap()).collect();
+        // OPTIONS, STARTUP, then the query only on the second node.
+        assert_eq!(2, frames[0].len());
+        assert_eq!(3, frames[1].len());
+        assert_eq!(Opcode::Query as u8, frames[1][2][4]);
+    }
+
+    // A system.peers result with the given (peer, rpc_address) rows.
+    fn peers(rows: &[([u8; 4], [u8; 4])]) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2];
+        for s in &["system", "peers", "peer"] {
+            body.write_u16::<BigEndian>(s.len() as u16).unwrap();
+            body.extend_from_slice(s.as_bytes());
+        }
+        body.extend_from_slice(&[0, 0x10, 0, 11]);
+        body.extend_from_slice(b"rpc_address");
+        body.extend_from_slice(&[0, 0x10]);
+        body.write_u32::<BigEndian>(rows.len() as u32).unwrap();
+        for &(peer, rpc_address) in rows {
+            for addr in &[peer, rpc_address] {
+                body.extend_from_slice(&[0, 0, 0, 4]);
+                body.extend_from_slice(addr);
+            }
+        }
+        body
+    }
+
+    // A system.peers_v2 result with the given (peer, native_address,
+    // native_port) rows.
+    fn peers_v2(rows: &[([u8; 4], [u8; 4], i32)]) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 3];
+        for s in &["system", "peers_v2", "peer"] {
+            body.write_u16::<BigEndian>(s.len() as u16).unwrap();
+            body.extend_from_slice(s.as_bytes());
+        }
+        body.extend_from_slice(&[0, 0x10, 0, 14]);
+        body.extend_from_slice(b"native_address");
+        body.extend_from_slice(&[0, 0x10, 0, 11]);
+        body.extend_from_slice(b"native_port");
+        body.extend_from_slice(&[0, 0x09]);
+        body.write_u32::<BigEndian>(rows.len() as u32).unwrap();
+        for &(peer, native_address, native_port) in rows {
+            for addr in &[peer, native_address] {
+                body.extend_from_slice(&[0, 0, 0, 4]);
+                body.extend_from_slice(addr);
+            }
+            body.extend_from_slice(&[0, 0, 0, 4]);
+            body.write_i32::<BigEndian>(native_port).unwrap();
+        }
+        body
+    }
+
+    // What a server without system.peers_v2 answers a query of it with.
+    fn no_peers_v2(req: &[u8]) -> Option<Vec<u8>> {
+        if !String::from_utf8_lossy(req).contains("system.peers_v2") {
+            return None;
+        }
+        let mut body = vec![0, 0, 0x22, 0, 0, 13];
+        body.extend_from_slice(b"unknown table");
+        Some(frame(frame_stream(req), Opcode::Error, &body))
+    }
+
+    #[test]
+    fn test_peer_address() {
+        let mut body: &[u8] = &peers(&[([10, 0, 0, 2], [192, 168, 0, 2]), ([10, 0, 0, 3], [0; 4])]);
+        let rows = match body
+            .read_cql_body(Opcode::Result, CQL_VERSION, None)
+            .unwrap()
+        {
+            ResponseBody::Result(result) => result.rows().unwrap(),
+            body => panic!("unexpected body: {:?}", body),
+        };
+        let addr = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        assert_eq!(addr("192.168.0.2"), peer_address(&rows.rows()[0]));
+        assert_eq!(addr("10.0.0.3"), peer_address(&rows.rows()[1]));
+    }
+
+    // Peers in system.peers are reached on the seed's port, so the peer
+    // needs an address of its own: 127.0.0.2, which only Linux routes to
+    // loopback unasked.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn refresh_topology() {
+        use tests::mock_server_on;
+
+        let (seed, seed_server) = mock_server(2, |req| match req[4] {
+            op if op == Opcode::Opts as u8 => {
+                frame(frame_stream(req), Opcode::Supported, &supported(&["3/v3"]))
+            }
+            _ if no_peers_v2(req).is_some() => no_peers_v2(req).unwrap(),
+            // Another node, known inside the cluster as 10.9.9.9, and the
+            // seed itself.
+            _ => frame(
+                frame_stream(req),
+                Opcode::Result,
+                &peers(&[([10, 9, 9, 9], [127, 0, 0, 2]), ([127, 0, 0, 1], [0; 4])]),
+            ),
+        });
+        let port = seed.rsplit(':').next().unwrap();
+        let (peer, peer_server) =
+            mock_server_on(&format!("127.0.0.2:{}", port), 1, |_| unreachable!());
+
+        let mut cluster = Cluster::connect(&[&seed]).unwrap();
+        cluster.refresh_topology().unwrap();
+        assert_eq!(vec![seed.as_str(), peer.as_str()], cluster.nodes());
+        drop(cluster);
+        let frames = seed_server.join().unwrap();
+        assert!(String::from_utf8_lossy(&frames[3]).contains("system.peers\0"));
+        peer_server.join().unwrap();
+    }
+
+    #[test]
+    fn refresh_topology_peers_v2() {
+        let (peer, peer_server) = mock_server(1, |_| unreachable!());
+        let peer_port: i32 = peer.rsplit(':').next().unwrap().parse().unwrap();
+        let (seed, seed_server) = mock_server(2, move |req| match req[4] {
+            op if op == Opcode::Opts as u8 => {
+                frame(frame_stream(req), Opcode::Supported, &supported(&["3/v3"]))
+            }
+            // Another node, on a port of its own, and one that can't be
+            // reached at its peer address.
+            _ => frame(
+                frame_stream(req),
+                Opcode::Result,
+                &peers_v2(&[
+                    ([10, 9, 9, 9], [127, 0, 0, 1], peer_port),
+                    ([127, 0, 0, 1], [0; 4], 1),
+                ]),
+            ),
+        });
+
+        let mut cluster = Cluster::connect(&[&seed]).unwrap();
+        cluster.refresh_topology().unwrap();
+        assert_eq!(vec![seed.as_str(), peer.as_str()], cluster.nodes());
+        drop(cluster);
+        seed_server.join().unwrap();
+        peer_server.join().unwrap();
+    }
+}
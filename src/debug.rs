@@ -0,0 +1,50 @@
+use std::fmt::Write;
+
+// Formats `bytes` like `hexdump -C`: an offset, sixteen bytes in hex split
+// into two groups of eight, and the printable ASCII characters.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() / 16 + 1) * 79);
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}  ", i * 16);
+        for j in 0..16 {
+            if j == 8 {
+                out.push(' ');
+            }
+            match line.get(j) {
+                Some(b) => {
+                    let _ = write!(out, "{:02x} ", b);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str(" |");
+        for &b in line {
+            out.push(if b == b' ' || b.is_ascii_graphic() {
+                b as char
+            } else {
+                '.'
+            });
+        }
+        out.push_str("|\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump() {
+        assert_eq!("", hexdump(&[]));
+        assert_eq!(
+            "00000000  83 00 00 01 02 00 00 00  00                       |.........|\n",
+            hexdump(&[131, 0, 0, 1, 2, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            "00000000  00 04 72 75 73 74 00 04  74 65 73 74 00 02 69 64  |..rust..test..id|\n\
+             00000010  7e 7f                                             |~.|\n",
+            hexdump(b"\x00\x04rust\x00\x04test\x00\x02id~\x7f")
+        );
+    }
+}
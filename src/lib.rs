@@ -1,16 +1,43 @@
 extern crate byteorder;
+#[cfg(feature = "chrono")]
+extern crate chrono;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
+#[cfg(feature = "json")]
+extern crate serde_json;
+extern crate socket2;
+#[cfg(feature = "uuid")]
+extern crate uuid;
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+mod cluster;
+pub mod debug;
+mod raw;
+mod stream;
+
+pub use cluster::{Cluster, SUPPORTED_PROTOCOL_VERSIONS};
+pub use raw::RawRows;
+pub use stream::{RowIterator, RowStream, STREAM_PAGE_SIZE};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::intrinsics::transmute;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
 use std::io;
-use std::io::Write;
-use std::net::TcpStream;
-use std::rc::Rc;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream};
 use std::string::FromUtf8Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub static CQL_VERSION: u8 = 0x03;
+// Tried first when no version is configured; servers that refuse it get
+// CQL_VERSION instead.
+pub static CQL_VERSION_V4: u8 = 0x04;
 
 #[derive(Clone, Copy, Debug)]
 enum Opcode {
@@ -22,6 +49,8 @@ enum Opcode {
     Prepare = 0x09,
     Execute = 0x0A,
     Register = 0x0B,
+    Batch = 0x0D,
+    AuthResponse = 0x0F,
 
     // resp
     Error = 0x00,
@@ -30,6 +59,8 @@ enum Opcode {
     Supported = 0x06,
     Result = 0x08,
     Event = 0x0C,
+    AuthChallenge = 0x0E,
+    AuthSuccess = 0x10,
 }
 
 fn opcode(val: u8) -> Opcode {
@@ -43,6 +74,8 @@ fn opcode(val: u8) -> Opcode {
         0x09 => Prepare,
         0x0A => Execute,
         0x0B => Register,
+        0x0D => Batch,
+        0x0F => AuthResponse,
 
         // resp
         0x00 => Error,
@@ -51,11 +84,13 @@ fn opcode(val: u8) -> Opcode {
         0x06 => Supported,
         0x08 => Result,
         0x0C => Event,
+        0x0E => AuthChallenge,
+        0x10 => AuthSuccess,
         _ => Error,
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Consistency {
     Any = 0x0000,
     One = 0x0001,
@@ -65,6 +100,15 @@ pub enum Consistency {
     All = 0x0005,
     LocalQuorum = 0x0006,
     EachQuorum = 0x0007,
+    // Serial and LocalSerial are only valid for reads, and as the serial
+    // consistency of a conditional write; the server rejects them
+    // otherwise. A serial read passes one as its consistency, so that it
+    // sees the result of any in-progress Paxos round. A conditional write
+    // keeps a normal consistency for its commit and sets the serial one
+    // separately, see `Client::query_with_serial`.
+    Serial = 0x0008,
+    LocalSerial = 0x0009,
+    LocalOne = 0x000A,
     Unknown,
 }
 
@@ -79,11 +123,69 @@ pub fn consistency(val: u16) -> Consistency {
         5 => All,
         6 => LocalQuorum,
         7 => EachQuorum,
+        8 => Serial,
+        9 => LocalSerial,
+        10 => LocalOne,
         _ => Unknown,
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Consistency {
+    pub fn is_serial(&self) -> bool {
+        matches!(*self, Consistency::Serial | Consistency::LocalSerial)
+    }
+}
+
+// Whether a statement writes, going by its first word, to warn about a
+// serial consistency given for one.
+fn is_write(query_str: &str) -> bool {
+    let verb = query_str.split_whitespace().next().unwrap_or("");
+    ["insert", "update", "delete", "begin"]
+        .iter()
+        .any(|w| verb.eq_ignore_ascii_case(w))
+}
+
+// Only a SELECT returns rows, so it's the only statement worth paging.
+fn is_select(query_str: &str) -> bool {
+    let verb = query_str.split_whitespace().next().unwrap_or("");
+    verb.eq_ignore_ascii_case("select")
+}
+
+// Counts the `?` bind markers of a query, skipping string literals, quoted
+// identifiers and comments. A doubled quote inside a literal reads as the
+// end of one literal and the start of the next, which counts the same.
+fn count_placeholders(query_str: &str) -> usize {
+    // Bytes up to and including `end`, or all of them if it's missing.
+    fn skip_past(s: &[u8], end: &[u8]) -> usize {
+        s.windows(end.len())
+            .position(|w| w == end)
+            .map_or(s.len(), |pos| pos + end.len())
+    }
+
+    let s = query_str.as_bytes();
+    let mut count = 0;
+    let mut i = 0;
+    while i < s.len() {
+        let rest = &s[i..];
+        i += if rest.starts_with(b"--") || rest.starts_with(b"//") {
+            2 + skip_past(&rest[2..], b"\n")
+        } else if rest.starts_with(b"/*") {
+            2 + skip_past(&rest[2..], b"*/")
+        } else if rest.starts_with(b"$$") {
+            2 + skip_past(&rest[2..], b"$$")
+        } else if rest[0] == b'\'' || rest[0] == b'"' {
+            1 + skip_past(&rest[1..], &rest[..1])
+        } else {
+            if rest[0] == b'?' {
+                count += 1;
+            }
+            1
+        };
+    }
+    count
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ColumnType {
     Custom = 0x0000,
     Ascii = 0x0001,
@@ -102,6 +204,13 @@ pub enum ColumnType {
     VarInt = 0x000E,
     TimeUUID = 0x000F,
     Inet = 0x0010,
+    // v4 only.
+    Date = 0x0011,
+    Time = 0x0012,
+    SmallInt = 0x0013,
+    TinyInt = 0x0014,
+    // v5, though v4 servers send it too.
+    Duration = 0x0015,
     List = 0x0020,
     Map = 0x0021,
     Set = 0x0022,
@@ -110,6 +219,45 @@ pub enum ColumnType {
     Unknown = 0xffff,
 }
 
+// The type's name in CQL.
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ColumnType::*;
+
+        let name = match *self {
+            Custom => "custom",
+            Ascii => "ascii",
+            Bigint => "bigint",
+            Blob => "blob",
+            Boolean => "boolean",
+            Counter => "counter",
+            Decimal => "decimal",
+            Double => "double",
+            Float => "float",
+            Int => "int",
+            Text => "text",
+            Timestamp => "timestamp",
+            UUID => "uuid",
+            VarChar => "varchar",
+            VarInt => "varint",
+            TimeUUID => "timeuuid",
+            Inet => "inet",
+            Date => "date",
+            Time => "time",
+            SmallInt => "smallint",
+            TinyInt => "tinyint",
+            Duration => "duration",
+            List => "list",
+            Map => "map",
+            Set => "set",
+            UDT => "udt",
+            Tuple => "tuple",
+            Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
 fn column_type(val: u16) -> ColumnType {
     use ColumnType::*;
 
@@ -131,6 +279,11 @@ fn column_type(val: u16) -> ColumnType {
         0x000E => VarInt,
         0x000F => TimeUUID,
         0x0010 => Inet,
+        0x0011 => Date,
+        0x0012 => Time,
+        0x0013 => SmallInt,
+        0x0014 => TinyInt,
+        0x0015 => Duration,
         0x0020 => List,
         0x0021 => Map,
         0x0022 => Set,
@@ -140,6 +293,84 @@ fn column_type(val: u16) -> ColumnType {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    ServerError = 0x0000,
+    ProtocolError = 0x000A,
+    BadCredentials = 0x0100,
+    Unavailable = 0x1000,
+    Overloaded = 0x1001,
+    IsBootstrapping = 0x1002,
+    TruncateError = 0x1003,
+    WriteTimeout = 0x1100,
+    ReadTimeout = 0x1200,
+    // v4 only.
+    ReadFailure = 0x1300,
+    FunctionFailure = 0x1400,
+    WriteFailure = 0x1500,
+    SyntaxError = 0x2000,
+    Unauthorized = 0x2100,
+    Invalid = 0x2200,
+    ConfigError = 0x2300,
+    AlreadyExists = 0x2400,
+    Unprepared = 0x2500,
+    Unknown,
+}
+
+pub fn error_code(val: u32) -> ErrorCode {
+    use ErrorCode::*;
+    match val {
+        0x0000 => ServerError,
+        0x000A => ProtocolError,
+        0x0100 => BadCredentials,
+        0x1000 => Unavailable,
+        0x1001 => Overloaded,
+        0x1002 => IsBootstrapping,
+        0x1003 => TruncateError,
+        0x1100 => WriteTimeout,
+        0x1200 => ReadTimeout,
+        0x1300 => ReadFailure,
+        0x1400 => FunctionFailure,
+        0x1500 => WriteFailure,
+        0x2000 => SyntaxError,
+        0x2100 => Unauthorized,
+        0x2200 => Invalid,
+        0x2300 => ConfigError,
+        0x2400 => AlreadyExists,
+        0x2500 => Unprepared,
+        _ => Unknown,
+    }
+}
+
+impl ErrorCode {
+    // Whether another node may well succeed where this one failed.
+    pub fn is_retryable_on_next_host(&self) -> bool {
+        use ErrorCode::*;
+        match *self {
+            // The node is busy, still joining the ring, or hit an internal
+            // error; none of it says anything about the request itself.
+            Overloaded | IsBootstrapping | ServerError => true,
+            // Truncation needs every replica up, which a coordinator in
+            // another part of the ring may see differently.
+            TruncateError => true,
+            // Replica liveness is as seen by this coordinator.
+            Unavailable => true,
+            // The request is wrong, or not allowed, wherever it is sent.
+            SyntaxError | Invalid | Unauthorized | ConfigError | AlreadyExists => false,
+            BadCredentials | ProtocolError => false,
+            // Fixed by preparing again on the same node instead.
+            Unprepared => false,
+            // Replicas were reached and may have applied a write, so
+            // retrying is a decision for the caller.
+            WriteTimeout | ReadTimeout => false,
+            // Replicas answered with a failure, e.g. too many tombstones,
+            // which will be the same from any coordinator.
+            ReadFailure | WriteFailure | FunctionFailure => false,
+            Unknown => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Protocol,
@@ -147,6 +378,48 @@ pub enum Error {
     UnexpectedEOF,
     Io(io::Error),
     Utf8(FromUtf8Error),
+    Cql {
+        code: u32,
+        message: String,
+        details: ErrorDetail,
+    },
+    ProtocolVersion {
+        expected: u8,
+        actual: u8,
+    },
+    TypeMismatch,
+    BindCount {
+        expected: usize,
+        actual: usize,
+    },
+    ColumnNotFound(String),
+    UnknownNode(String),
+    // The server requires authentication, with this authenticator, but no
+    // credentials were given.
+    AuthRequired(String),
+    EmptyBatch,
+    MorePages,
+    // As many requests as allowed are awaiting responses, or every stream id
+    // is taken by one or by a response kept for `recv_frame`.
+    InFlightLimit(usize),
+    // A STARTUP option the protocol requires was left out.
+    MissingStartupOption(String),
+    // A bind marker name was given more than one value.
+    DuplicateName(String),
+    // A UDT field, by name, couldn't be serialized, and why.
+    UdtField(String, Box<Error>),
+    // The COMPRESSION startup option names an algorithm this build lacks.
+    UnsupportedCompression(String),
+}
+
+impl Error {
+    // The server error code, for errors the server sent back.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        match *self {
+            Error::Cql { code, .. } => Some(error_code(code)),
+            _ => None,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
@@ -163,14 +436,100 @@ impl From<FromUtf8Error> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-fn parse_varint(v: &[u8]) -> i64 {
+// Values longer than 8 bytes don't fit an i64 and are rejected, as are
+// empty ones.
+fn parse_varint(v: &[u8]) -> Result<i64> {
+    if v.is_empty() || v.len() > 8 {
+        return Err(Error::Protocol);
+    }
     let start = 8 - v.len();
     let is_positive = (v[0] & 0x80) == 0;
     let mut buf = if is_positive { [0u8; 8] } else { [255u8; 8] };
 
     buf[start..].copy_from_slice(v);
-    let mut slice: &[u8] = &buf;
-    slice.read_i64::<BigEndian>().unwrap()
+    Ok(i64::from_be_bytes(buf))
+}
+
+// Length of the shortest two's-complement form of `v`: leading bytes are
+// dropped while the next byte still carries the sign.
+fn varint_len(v: i64) -> usize {
+    let bytes = v.to_be_bytes();
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if !(b == 0 && next & 0x80 == 0 || b == 0xff && next & 0x80 != 0) {
+            break;
+        }
+        start += 1;
+    }
+    8 - start
+}
+
+fn write_varint<T: io::Write>(buf: &mut T, v: i64) -> Result<()> {
+    buf.write_all(&v.to_be_bytes()[8 - varint_len(v)..])?;
+    Ok(())
+}
+
+const NANOS_PER_DAY: i64 = 86_400_000_000_000;
+
+// chrono counts days from 0001-01-01, which is day 1.
+#[cfg(feature = "chrono")]
+const DAYS_FROM_CE_TO_EPOCH: i64 = 719_163;
+
+// The [vint] of the v5 spec: as many leading one bits in the first byte as
+// bytes follow it, with the value in the remaining bits, big-endian. Signed
+// values are zigzag-encoded so small negative ones stay short.
+fn read_vint(reader: &mut &[u8]) -> Result<i64> {
+    let first = reader.read_u8()?;
+    let extra = first.leading_ones() as usize;
+    let mut v = if extra < 8 {
+        u64::from(first & (0xff >> extra))
+    } else {
+        0
+    };
+    if reader.len() < extra {
+        return Err(Error::Protocol);
+    }
+    for &b in &reader[..extra] {
+        v = v << 8 | u64::from(b);
+    }
+    *reader = &reader[extra..];
+    Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+}
+
+fn vint_len(v: i64) -> usize {
+    let zigzag = ((v << 1) ^ (v >> 63)) as u64;
+    let bits = 64 - (zigzag | 1).leading_zeros() as usize;
+    // Each extra byte adds 7 bits: 8 from the byte, less the marker bit.
+    if bits > 56 {
+        9
+    } else {
+        1 + (bits - 1) / 7
+    }
+}
+
+fn write_vint<T: io::Write>(buf: &mut T, v: i64) -> Result<()> {
+    let zigzag = ((v << 1) ^ (v >> 63)) as u64;
+    let len = vint_len(v);
+    let mut bytes = zigzag.to_be_bytes().to_vec();
+    if len == 9 {
+        buf.write_u8(0xff)?;
+    } else {
+        bytes.drain(..8 - len);
+        bytes[0] |= !(0xffu8 >> (len - 1));
+    }
+    buf.write_all(&bytes)?;
+    Ok(())
+}
+
+// A count or length written as a [short], which fails rather than wrap.
+fn short_len(len: usize) -> Result<u16> {
+    u16::try_from(len).map_err(|_| Error::Protocol)
+}
+
+// A count or length written as an [int].
+fn int_len(len: usize) -> Result<i32> {
+    i32::try_from(len).map_err(|_| Error::Protocol)
 }
 
 trait CqlSerializable {
@@ -202,6 +561,15 @@ trait CqlReader: io::Read {
         Ok(vec)
     }
 
+    // A [bytes], which is None when null.
+    fn read_cql_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.read_int()? {
+            -1 => Ok(None),
+            len if len < 0 => Err(Error::Protocol),
+            len => Ok(Some(self.read_bytes(len as usize)?)),
+        }
+    }
+
     fn read_short(&mut self) -> Result<u16> {
         let val = self.read_u16::<BigEndian>()?;
         Ok(val)
@@ -212,6 +580,13 @@ trait CqlReader: io::Read {
         Ok(val)
     }
 
+    fn read_cql_consistency(&mut self) -> Result<Consistency> {
+        let val = self.read_short()?;
+        Ok(consistency(val))
+    }
+
+    // String::from_utf8 takes over the buffer, so this is one allocation
+    // and one copy per string.
     fn read_cql_str_len(&mut self, len: usize) -> Result<String> {
         let bytes = self.read_bytes(len)?;
         Ok(String::from_utf8(bytes)?)
@@ -219,9 +594,7 @@ trait CqlReader: io::Read {
 
     fn read_cql_str(&mut self) -> Result<String> {
         let len = self.read_short()?;
-        let bytes = self.read_bytes(usize::from(len))?;
-        let s = String::from_utf8(bytes)?;
-        Ok(s)
+        self.read_cql_str_len(usize::from(len))
     }
 
     fn read_cql_string_list(&mut self) -> Result<Vec<String>> {
@@ -233,6 +606,36 @@ trait CqlReader: io::Read {
         Ok(v)
     }
 
+    // The value of an `inet` column: the bare 4 or 16 address bytes, their
+    // count taken from the value's [bytes] length.
+    fn read_cql_inet_column(&mut self, len: usize) -> Result<IpAddr> {
+        match len {
+            4 => {
+                let mut v = [0u8; 4];
+                self.read_full(&mut v)?;
+                Ok(IpAddr::V4(v.into()))
+            }
+            16 => {
+                let mut v = [0u8; 16];
+                self.read_full(&mut v)?;
+                Ok(IpAddr::V6(v.into()))
+            }
+            _len => Err(Error::Protocol),
+        }
+    }
+
+    // The [inet] notation of the protocol, used for nodes in events: a
+    // one-byte address length, the address, then the port as an [int].
+    fn read_cql_inet_node(&mut self) -> Result<SocketAddr> {
+        let len = self.read_u8()?;
+        let addr = self.read_cql_inet_column(usize::from(len))?;
+        let port = self.read_int()?;
+        if !(0..=0xffff).contains(&port) {
+            return Err(Error::Protocol);
+        }
+        Ok(SocketAddr::new(addr, port as u16))
+    }
+
     fn read_cql_string_multimap(&mut self) -> Result<StringMultiMap> {
         let len = self.read_short()?;
         let mut v = Vec::with_capacity(usize::from(len));
@@ -272,13 +675,28 @@ trait CqlReader: io::Read {
                 }
                 CqlColDescr::Tuple(ty_list.into())
             }
+            ColumnType::UDT => {
+                let keyspace = self.read_cql_str()?;
+                let name = self.read_cql_str()?;
+                let n = self.read_short()?;
+                let mut fields = Vec::with_capacity(usize::from(n));
+                for _ in 0..n {
+                    let field_name = self.read_cql_str()?;
+                    fields.push((field_name, self.read_cql_col_type()?));
+                }
+                CqlColDescr::Udt {
+                    keyspace,
+                    name,
+                    fields,
+                }
+            }
             ty => CqlColDescr::Single(ty),
         };
         Ok(ty)
     }
 
     fn read_cql_col_metadata(&mut self, flags: u32) -> Result<CqlColMetadata> {
-        let (keyspace, table) = if flags == 0x0001 {
+        let (keyspace, table) = if flags & GLOBAL_TABLE_SPEC != 0 {
             (None, None)
         } else {
             let keyspace_str = self.read_cql_str()?;
@@ -299,7 +717,44 @@ trait CqlReader: io::Read {
     fn read_cql_metadata(&mut self) -> Result<Metadata> {
         let flags = self.read_u32::<BigEndian>()?;
         let column_count = self.read_u32::<BigEndian>()?;
-        let (keyspace, table) = if flags == 0x0001 {
+        self.read_cql_metadata_columns(flags, column_count)
+    }
+
+    // The bind marker metadata of a PREPARED result, which from v4 on
+    // lists the partition key markers, skipped here, before the columns.
+    fn read_cql_prepared_metadata(&mut self, version: u8) -> Result<Metadata> {
+        let flags = self.read_u32::<BigEndian>()?;
+        let column_count = self.read_u32::<BigEndian>()?;
+        if version >= CQL_VERSION_V4 {
+            let pk_count = self.read_u32::<BigEndian>()?;
+            for _ in 0..pk_count {
+                self.read_short()?;
+            }
+        }
+        self.read_cql_metadata_columns(flags, column_count)
+    }
+
+    fn read_cql_metadata_columns(&mut self, flags: u32, column_count: u32) -> Result<Metadata> {
+        // A null state means there are no more pages, despite the flag; an
+        // empty one is still a state to send back.
+        let paging_state = if flags & HAS_MORE_PAGES != 0 {
+            self.read_cql_bytes()?
+        } else {
+            None
+        };
+        if flags & NO_METADATA != 0 {
+            return Ok(Metadata {
+                flags,
+                column_count,
+                keyspace: None,
+                table: None,
+                paging_state,
+                row_metadata: Vec::new(),
+                column_indexes: HashMap::new(),
+            });
+        }
+
+        let (keyspace, table) = if flags & GLOBAL_TABLE_SPEC != 0 {
             let keyspace_str = self.read_cql_str()?;
             let table_str = self.read_cql_str()?;
             (Some(keyspace_str), Some(table_str))
@@ -313,16 +768,31 @@ trait CqlReader: io::Read {
         }
 
         Ok(Metadata {
-            flags: flags,
-            column_count: column_count,
-            keyspace: keyspace,
-            table: table,
-            row_metadata: row_metadata,
+            flags,
+            column_count,
+            keyspace,
+            table,
+            paging_state,
+            column_indexes: index_columns(&row_metadata),
+            row_metadata,
         })
     }
 
-    fn read_cql_rows(&mut self) -> Result<Rows> {
-        let metadata = Rc::new(self.read_cql_metadata()?);
+    // Rows sent with NO_METADATA, as asked for by `skip_metadata`, are read
+    // with the columns of `result_metadata`, the prepared statement's.
+    fn read_cql_rows(&mut self, result_metadata: Option<&Metadata>) -> Result<Rows> {
+        let mut metadata = self.read_cql_metadata()?;
+        if metadata.flags & NO_METADATA != 0 && metadata.column_count > 0 {
+            let known = match result_metadata {
+                Some(known) if known.column_count == metadata.column_count => known,
+                _ => return Err(Error::Protocol),
+            };
+            metadata.keyspace = known.keyspace.clone();
+            metadata.table = known.table.clone();
+            metadata.row_metadata = known.row_metadata.clone();
+            metadata.column_indexes = known.column_indexes.clone();
+        }
+        let metadata = Arc::new(metadata);
         let rows_count = self.read_u32::<BigEndian>()?;
         let col_count = metadata.row_metadata.len();
 
@@ -340,19 +810,20 @@ trait CqlReader: io::Read {
             });
         }
 
-        Ok(Rows {
-            metadata: metadata,
-            rows: rows,
-        })
+        Ok(Rows { metadata, rows })
     }
 
-    fn read_cql_result(&mut self) -> Result<ResponseResult> {
+    fn read_cql_result(
+        &mut self,
+        version: u8,
+        result_metadata: Option<&Metadata>,
+    ) -> Result<ResponseResult> {
         use ResponseResult::*;
 
         let code = self.read_u32::<BigEndian>()?;
         let res = match code {
             0x0001 => Void,
-            0x0002 => Rows(self.read_cql_rows()?),
+            0x0002 => Rows(self.read_cql_rows(result_metadata)?),
             0x0003 => {
                 let msg = self.read_cql_str()?;
                 Keyspace(msg)
@@ -360,56 +831,252 @@ trait CqlReader: io::Read {
             0x0004 => {
                 let len = self.read_short()?;
                 let id = self.read_bytes(usize::from(len))?;
-                let metadata = self.read_cql_metadata()?;
-                Prepared(id, metadata)
+                let metadata = self.read_cql_prepared_metadata(version)?;
+                let result_metadata = self.read_cql_metadata()?;
+                Prepared(id, metadata, result_metadata)
             }
             0x0005 => {
-                let change_type = self.read_cql_str()?;
-                let target = self.read_cql_str()?;
-                let ks_name = self.read_cql_str()?;
-
-                let name = match target.as_str() {
-                    "KEYSPACE" => None,
-                    "TABLE" | "TYPE" => {
-                        let target_name = self.read_cql_str()?;
-                        Some(target_name)
-                    }
-                    _ => {
-                        return Err(Error::Protocol);
-                    }
-                };
-                SchemaChange(change_type, target, ks_name, name)
+                let (change_type, target, ks_name, name, arg_types) =
+                    self.read_cql_schema_change()?.ok_or(Error::Protocol)?;
+                SchemaChange(change_type, target, ks_name, name, arg_types)
             }
             _ => return Err(Error::Protocol),
         };
         Ok(res)
     }
 
-    fn read_cql_body(&mut self, opcode: Opcode) -> Result<ResponseBody> {
+    // The change type, target, keyspace and, unless the keyspace itself
+    // changed, the table, type, function or aggregate name. Functions and
+    // aggregates, which are v4 only, also carry their argument types, since
+    // they can be overloaded. Shared by RESULT and EVENT. None for a
+    // target this client doesn't know, whose remaining fields it can't
+    // read.
+    fn read_cql_schema_change(&mut self) -> Result<Option<SchemaChangeFields>> {
+        let change_type = self.read_cql_str()?;
+        let target = self.read_cql_str()?;
+        let ks_name = self.read_cql_str()?;
+
+        let (name, arg_types) = match target.as_str() {
+            "KEYSPACE" => (None, Vec::new()),
+            "TABLE" | "TYPE" => {
+                let target_name = self.read_cql_str()?;
+                (Some(target_name), Vec::new())
+            }
+            "FUNCTION" | "AGGREGATE" => {
+                let target_name = self.read_cql_str()?;
+                (Some(target_name), self.read_cql_string_list()?)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((change_type, target, ks_name, name, arg_types)))
+    }
+
+    // A change this client doesn't know, in an event it does, is read as
+    // ServerEvent::Other, as a whole event it doesn't know is.
+    fn read_cql_event(&mut self) -> Result<ServerEvent> {
+        let event_type = self.read_cql_str()?;
+        let other = || Ok(ServerEvent::Other(event_type.clone()));
+        let event = match event_type.as_str() {
+            "TOPOLOGY_CHANGE" => {
+                let change = match self.read_cql_str()?.as_str() {
+                    "NEW_NODE" => TopologyChangeType::NewNode,
+                    "REMOVED_NODE" => TopologyChangeType::RemovedNode,
+                    "MOVED_NODE" => TopologyChangeType::MovedNode,
+                    _ => return other(),
+                };
+                let node = self.read_cql_inet_node()?;
+                ServerEvent::TopologyChange { change, node }
+            }
+            "STATUS_CHANGE" => {
+                let change = match self.read_cql_str()?.as_str() {
+                    "UP" => StatusChangeType::Up,
+                    "DOWN" => StatusChangeType::Down,
+                    _ => return other(),
+                };
+                let node = self.read_cql_inet_node()?;
+                ServerEvent::StatusChange { change, node }
+            }
+            "SCHEMA_CHANGE" => {
+                let (change, target, keyspace, name, arg_types) =
+                    match self.read_cql_schema_change()? {
+                        Some(fields) => fields,
+                        None => return other(),
+                    };
+                let change = match change.as_str() {
+                    "CREATED" => SchemaChangeType::Created,
+                    "UPDATED" => SchemaChangeType::Updated,
+                    "DROPPED" => SchemaChangeType::Dropped,
+                    _ => return other(),
+                };
+                ServerEvent::SchemaChange(SchemaChange {
+                    change,
+                    target,
+                    keyspace,
+                    name,
+                    arg_types,
+                })
+            }
+            // Its body is left unread, as there's no telling its layout.
+            _ => return other(),
+        };
+        Ok(event)
+    }
+
+    fn read_cql_error_detail(&mut self, code: u32) -> Result<ErrorDetail> {
+        let detail = match code {
+            0x1000 => {
+                let consistency = self.read_cql_consistency()?;
+                let required = self.read_int()?;
+                let alive = self.read_int()?;
+                ErrorDetail::Unavailable {
+                    consistency,
+                    required,
+                    alive,
+                }
+            }
+            0x1100 => {
+                let consistency = self.read_cql_consistency()?;
+                let received = self.read_int()?;
+                let block_for = self.read_int()?;
+                let write_type = self.read_cql_str()?;
+                ErrorDetail::WriteTimeout {
+                    consistency,
+                    received,
+                    block_for,
+                    write_type,
+                }
+            }
+            0x1200 => {
+                let consistency = self.read_cql_consistency()?;
+                let received = self.read_int()?;
+                let block_for = self.read_int()?;
+                let data_present = self.read_u8()? != 0;
+                ErrorDetail::ReadTimeout {
+                    consistency,
+                    received,
+                    block_for,
+                    data_present,
+                }
+            }
+            0x1300 => {
+                let consistency = self.read_cql_consistency()?;
+                let received = self.read_int()?;
+                let block_for = self.read_int()?;
+                let num_failures = self.read_int()?;
+                let data_present = self.read_u8()? != 0;
+                ErrorDetail::ReadFailure {
+                    consistency,
+                    received,
+                    block_for,
+                    num_failures,
+                    data_present,
+                }
+            }
+            0x1400 => {
+                let keyspace = self.read_cql_str()?;
+                let function = self.read_cql_str()?;
+                let arg_types = self.read_cql_string_list()?;
+                ErrorDetail::FunctionFailure {
+                    keyspace,
+                    function,
+                    arg_types,
+                }
+            }
+            0x1500 => {
+                let consistency = self.read_cql_consistency()?;
+                let received = self.read_int()?;
+                let block_for = self.read_int()?;
+                let num_failures = self.read_int()?;
+                let write_type = self.read_cql_str()?;
+                ErrorDetail::WriteFailure {
+                    consistency,
+                    received,
+                    block_for,
+                    num_failures,
+                    write_type,
+                }
+            }
+            0x2400 => {
+                let keyspace = self.read_cql_str()?;
+                let table = self.read_cql_str()?;
+                ErrorDetail::AlreadyExists {
+                    keyspace,
+                    table: if table.is_empty() { None } else { Some(table) },
+                }
+            }
+            0x2500 => {
+                let len = self.read_short()?;
+                let id = self.read_bytes(usize::from(len))?;
+                ErrorDetail::Unprepared { id }
+            }
+            _ => ErrorDetail::None,
+        };
+        Ok(detail)
+    }
+
+    fn read_cql_body(
+        &mut self,
+        opcode: Opcode,
+        version: u8,
+        result_metadata: Option<&Metadata>,
+    ) -> Result<ResponseBody> {
         let body = match opcode {
             Opcode::Ready => ResponseBody::Ready,
             Opcode::Auth => ResponseBody::Auth(self.read_cql_str()?),
             Opcode::Error => {
                 let code = self.read_u32::<BigEndian>()?;
                 let msg = self.read_cql_str()?;
-
-                match code {
-                    0x2400 => {
-                        let _ks = self.read_cql_str()?;
-                        let _namespace = self.read_cql_str()?;
-                    }
-                    _ => (),
-                }
-                ResponseBody::Error(code, msg)
+                let detail = self.read_cql_error_detail(code)?;
+                ResponseBody::Error(code, msg, detail)
             }
-            Opcode::Result => ResponseBody::Result(self.read_cql_result()?),
+            Opcode::Result => ResponseBody::Result(self.read_cql_result(version, result_metadata)?),
             Opcode::Supported => ResponseBody::Supported(self.read_cql_string_multimap()?),
+            Opcode::AuthChallenge => ResponseBody::AuthChallenge(self.read_cql_bytes()?),
+            Opcode::AuthSuccess => ResponseBody::AuthSuccess(self.read_cql_bytes()?),
+            Opcode::Event => ResponseBody::Event(self.read_cql_event()?),
             _ => return Err(Error::Protocol),
         };
         Ok(body)
     }
 
-    fn read_cql_response(&mut self) -> Result<Response> {
+    // What a frame carries ahead of the body proper, as its flags say: the
+    // trace id of a traced request, then in v4 warnings and a custom
+    // payload, which is skipped.
+    fn read_cql_prelude(
+        &mut self,
+        header: &FrameHeader,
+    ) -> Result<(Option<[u8; 16]>, Vec<String>)> {
+        let tracing_id = if header.flags & TRACING != 0 {
+            let mut id = [0u8; 16];
+            self.read_full(&mut id)?;
+            Some(id)
+        } else {
+            None
+        };
+        if header.protocol_version() < CQL_VERSION_V4 {
+            return Ok((tracing_id, Vec::new()));
+        }
+        let warnings = if header.flags & WARNING != 0 {
+            self.read_cql_string_list()?
+        } else {
+            Vec::new()
+        };
+        if header.flags & CUSTOM_PAYLOAD != 0 {
+            for _ in 0..self.read_short()? {
+                self.read_cql_str()?;
+                self.read_cql_bytes()?;
+            }
+        }
+        Ok((tracing_id, warnings))
+    }
+
+    fn read_cql_frame(&mut self, expected_version: u8) -> Result<RawFrame> {
+        let (header, length) = self.read_cql_frame_header(expected_version)?;
+        let body = self.read_bytes(length as usize)?;
+        Ok(RawFrame { header, body })
+    }
+
+    fn read_cql_frame_header(&mut self, expected_version: u8) -> Result<(FrameHeader, u32)> {
         let header_data = self.read_bytes(9)?;
         let mut header_reader = io::Cursor::new(header_data.as_slice());
 
@@ -419,37 +1086,52 @@ trait CqlReader: io::Read {
         let stream = header_reader.read_i16::<BigEndian>()?;
         let opcode = opcode(header_reader.read_u8()?);
         let length = header_reader.read_u32::<BigEndian>()?;
-        eprintln!("len: {:?}, opcode: {:?}", length, opcode);
+        trace!("len: {:?}, opcode: {:?}", length, opcode);
 
-        let body_data = self.read_bytes(length as usize)?;
-        let mut reader = io::Cursor::new(body_data.as_slice());
+        if version != 0x80 | expected_version {
+            return Err(Error::ProtocolVersion {
+                expected: 0x80 | expected_version,
+                actual: version,
+            });
+        }
 
-        let body = reader.read_cql_body(opcode)?;
-        eprintln!("body: {:?}", body);
-        // println!("byte: {:?} {:?}", header_data, body_data);
+        let header = FrameHeader {
+            version,
+            flags,
+            stream,
+            opcode,
+        };
+        Ok((header, length))
+    }
 
-        if reader.position() != length as u64 {
-            eprintln!("short: {} != {}", reader.position(), length);
-        }
+    fn read_cql_response(&mut self, expected_version: u8) -> Result<Response> {
+        self.read_cql_response_compressed(expected_version, Compression::None)
+    }
 
-        Ok(Response {
-            header: FrameHeader {
-                version,
-                flags,
-                stream,
-                opcode,
-            },
-            body: body,
-        })
+    fn read_cql_response_compressed(
+        &mut self,
+        expected_version: u8,
+        compression: Compression,
+    ) -> Result<Response> {
+        let mut frame = self.read_cql_frame(expected_version)?;
+        frame.decompress(compression)?;
+        frame.decode()
     }
 
     fn read_cql_varint(&mut self, len: usize) -> Result<i64> {
-        let v = self.read_bytes(len)?;
-        if v.len() > 10 {
-            //TODO: add bigint?
+        parse_varint(&self.read_bytes(len)?)
+    }
+
+    fn read_cql_duration(&mut self, len: usize) -> Result<Value> {
+        let bytes = self.read_bytes(len)?;
+        let mut reader = bytes.as_slice();
+        let months = i32::try_from(read_vint(&mut reader)?).map_err(|_| Error::Protocol)?;
+        let days = i32::try_from(read_vint(&mut reader)?).map_err(|_| Error::Protocol)?;
+        let nanos = read_vint(&mut reader)?;
+        if !reader.is_empty() || mixed_signs(months, days, nanos) {
             return Err(Error::Protocol);
         }
-        Ok(parse_varint(&v))
+        Ok(Value::CqlDuration(months, days, nanos))
     }
 
     fn read_cql_col_ty(&mut self, col_type: ColumnType, len: usize) -> Result<Value> {
@@ -466,27 +1148,30 @@ trait CqlReader: io::Read {
             }),
             Blob => CqlBlob(self.read_bytes(len)?),
             Boolean => CqlBoolean(match len {
+                // Some servers send an empty value rather than false.
+                0 => false,
                 1 => self.read_u8()? != 0,
                 _len => return Err(Error::Protocol),
             }),
-            //TODO
-            Counter => return Err(Error::Unimplemented),
+            Counter => CqlCounter(match len {
+                8 => self.read_i64::<BigEndian>()?,
+                _len => return Err(Error::Protocol),
+            }),
             Decimal => {
+                if len < 4 {
+                    return Err(Error::Protocol);
+                }
                 let scale = self.read_int()?;
-                let unscaled = self.read_cql_varint(len)?;
+                let unscaled = self.read_cql_varint(len - 4)?;
                 CqlDecimal(scale, unscaled)
             }
-            Double => unsafe {
-                match len {
-                    8 => CqlDouble(transmute(self.read_u64::<BigEndian>()?)),
-                    _len => return Err(Error::Protocol),
-                }
+            Double => match len {
+                8 => CqlDouble(f64::from_bits(self.read_u64::<BigEndian>()?)),
+                _len => return Err(Error::Protocol),
             },
-            Float => unsafe {
-                match len {
-                    4 => CqlFloat(transmute(self.read_u32::<BigEndian>()?)),
-                    _len => return Err(Error::Protocol),
-                }
+            Float => match len {
+                4 => CqlFloat(f32::from_bits(self.read_u32::<BigEndian>()?)),
+                _len => return Err(Error::Protocol),
             },
             Int => match len {
                 4 => CqlInt(self.read_int()?),
@@ -515,19 +1200,27 @@ trait CqlReader: io::Read {
                 }
                 _len => return Err(Error::Protocol),
             },
-            Inet => CqlInet(match len {
-                4 => {
-                    let mut v = [0u8; 4];
-                    self.read_full(&mut v)?;
-                    std::net::IpAddr::V4(v.into())
-                }
-                16 => {
-                    let mut v = [0u8; 16];
-                    self.read_full(&mut v)?;
-                    std::net::IpAddr::V6(v.into())
-                }
+            Inet => CqlInet(self.read_cql_inet_column(len)?),
+            Date => match len {
+                4 => CqlDate(self.read_u32::<BigEndian>()?),
                 _len => return Err(Error::Protocol),
-            }),
+            },
+            Time => match len {
+                8 => match self.read_i64::<BigEndian>()? {
+                    nanos if (0..NANOS_PER_DAY).contains(&nanos) => CqlTime(nanos),
+                    _nanos => return Err(Error::Protocol),
+                },
+                _len => return Err(Error::Protocol),
+            },
+            SmallInt => match len {
+                2 => CqlSmallInt(self.read_i16::<BigEndian>()?),
+                _len => return Err(Error::Protocol),
+            },
+            TinyInt => match len {
+                1 => CqlTinyInt(self.read_i8()?),
+                _len => return Err(Error::Protocol),
+            },
+            Duration => self.read_cql_duration(len)?,
             Custom | List | Map | Set | UDT | Tuple => {
                 unreachable!("non-singular type on read_cql_col_ty: {:?}", col_type);
             }
@@ -542,63 +1235,136 @@ trait CqlReader: io::Read {
     fn read_cql_col(&mut self, col_type: &CqlColDescr) -> Result<Value> {
         let len = match self.read_int()? {
             -1 => return Ok(Value::CqlNull),
+            len if len < 0 => return Err(Error::Protocol),
             len => len as usize,
         };
+        self.read_cql_col_content(col_type, len)
+    }
 
+    // The `len` bytes of a non-null value.
+    fn read_cql_col_content(&mut self, col_type: &CqlColDescr, len: usize) -> Result<Value> {
         match *col_type {
             CqlColDescr::Custom(ref name) => {
                 let data = self.read_bytes(len)?;
                 Ok(Value::CqlCustom(name.clone(), data))
             }
             CqlColDescr::Single(ty) => self.read_cql_col_ty(ty, len),
-            CqlColDescr::List(ref ty) => {
-                let n = self.read_int()? as usize;
-                let mut l = Vec::with_capacity(n);
-                for _ in 0..n {
-                    l.push(self.read_cql_col(ty)?);
+            _ => {
+                // Decode composite values from their own [bytes] region, so
+                // one which does not fill it exactly can't shift the rest of
+                // the frame.
+                let data = self.read_bytes(len)?;
+                let mut reader = data.as_slice();
+                let value = match *col_type {
+                    CqlColDescr::Udt { ref fields, .. } => read_cql_udt(&mut reader, fields)?,
+                    _ => read_cql_composite(&mut reader, col_type)?,
+                };
+                if !reader.is_empty() {
+                    return Err(Error::Protocol);
                 }
-                Ok(Value::CqlList(l))
+                Ok(value)
             }
-            CqlColDescr::Map(ref ty_tup) => {
-                let n = self.read_int()? as usize;
-                let mut l = Vec::with_capacity(n);
-                for _ in 0..n {
-                    let key = self.read_cql_col(&ty_tup.0)?;
-                    let val = self.read_cql_col(&ty_tup.1)?;
-                    l.push((key, val));
-                }
-                Ok(Value::CqlMap(l))
+        }
+    }
+}
+
+impl<T: io::Read> CqlReader for T {}
+
+fn read_cql_composite(reader: &mut &[u8], col_type: &CqlColDescr) -> Result<Value> {
+    match *col_type {
+        CqlColDescr::Custom(_) | CqlColDescr::Single(_) | CqlColDescr::Udt { .. } => {
+            unreachable!("non-composite type on read_cql_composite: {:?}", col_type);
+        }
+        CqlColDescr::List(ref ty) => {
+            let n = read_cql_count(reader, 4)?;
+            let mut l = Vec::with_capacity(n);
+            for _ in 0..n {
+                l.push(reader.read_cql_col(ty)?);
             }
-            CqlColDescr::Tuple(ref ty_list) => {
-                let n = self.read_int()? as usize;
-                let mut l = Vec::with_capacity(n);
-                for _ in 0..n {
-                    let mut row = Vec::with_capacity(ty_list.len());
-                    for ty in ty_list.iter() {
-                        row.push(self.read_cql_col(ty)?);
-                    }
-                    l.push(row)
-                }
-                Ok(Value::CqlTuple(l))
+            Ok(Value::CqlList(l))
+        }
+        CqlColDescr::Map(ref ty_tup) => {
+            let n = read_cql_count(reader, 8)?;
+            let mut l = Vec::with_capacity(n);
+            for _ in 0..n {
+                let key = reader.read_cql_col(&ty_tup.0)?;
+                let val = reader.read_cql_col(&ty_tup.1)?;
+                l.push((key, val));
             }
-            CqlColDescr::Set(ref ty) => {
-                let n = self.read_int()? as usize;
-                let mut l = Vec::with_capacity(n);
-                for _ in 0..n {
-                    l.push(self.read_cql_col(ty)?);
-                }
-                Ok(Value::CqlSet(l))
+            Ok(Value::CqlMap(l))
+        }
+        // Exactly one [bytes] value per field, with no count first.
+        CqlColDescr::Tuple(ref ty_list) => {
+            let mut l = Vec::with_capacity(ty_list.len());
+            for ty in ty_list.iter() {
+                l.push(reader.read_cql_col(ty)?);
             }
+            Ok(Value::CqlTuple(l))
         }
+        CqlColDescr::Set(ref ty) => {
+            let n = read_cql_count(reader, 4)?;
+            let mut l = Vec::with_capacity(n);
+            for _ in 0..n {
+                l.push(reader.read_cql_col(ty)?);
+            }
+            Ok(Value::CqlSet(l))
+        }
+    }
+}
+
+// A collection's element count. Each element takes at least `min_size`
+// of the bytes left, so a corrupt count fails before anything is allocated.
+fn read_cql_count(reader: &mut &[u8], min_size: usize) -> Result<usize> {
+    match reader.read_i32::<BigEndian>()? {
+        n if n < 0 || n as usize > reader.len() / min_size => Err(Error::Protocol),
+        n => Ok(n as usize),
+    }
+}
+
+// A value written before fields were added to the type ends early; the
+// missing fields are null.
+fn read_cql_udt(reader: &mut &[u8], fields: &[(String, CqlColDescr)]) -> Result<Value> {
+    let mut l = Vec::with_capacity(fields.len());
+    for (name, ty) in fields {
+        let value = if reader.is_empty() {
+            Value::CqlNull
+        } else {
+            reader.read_cql_col(ty)?
+        };
+        l.push((name.clone(), value));
     }
+    Ok(Value::CqlUDT(l))
 }
 
-impl<'a, T: io::Read> CqlReader for T {}
+fn decode_response(
+    header: FrameHeader,
+    body_data: &[u8],
+    result_metadata: Option<&Metadata>,
+) -> Result<Response> {
+    let mut reader = io::Cursor::new(body_data);
+    let (tracing_id, warnings) = reader.read_cql_prelude(&header)?;
+    for warning in warnings.iter() {
+        warn!("server warning: {}", warning);
+    }
+    let body = reader.read_cql_body(header.opcode, header.protocol_version(), result_metadata)?;
+    trace!("body: {:?}", body);
+
+    if reader.position() != body_data.len() as u64 {
+        warn!("short: {} != {}", reader.position(), body_data.len());
+    }
+
+    Ok(Response {
+        header,
+        tracing_id,
+        warnings,
+        body,
+    })
+}
 
 struct ShortString<'a>(&'a str);
 impl<'a> CqlSerializable for ShortString<'a> {
     fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        buf.write_u16::<BigEndian>(self.0.len() as u16)?;
+        buf.write_u16::<BigEndian>(short_len(self.0.len())?)?;
         buf.write_all(self.0.as_bytes())?;
         Ok(())
     }
@@ -610,7 +1376,7 @@ impl<'a> CqlSerializable for ShortString<'a> {
 struct LongString<'a>(&'a str);
 impl<'a> CqlSerializable for LongString<'a> {
     fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        buf.write_u32::<BigEndian>(self.0.len() as u32)?;
+        buf.write_i32::<BigEndian>(int_len(self.0.len())?)?;
         buf.write_all(self.0.as_bytes())?;
         Ok(())
     }
@@ -643,7 +1409,7 @@ pub struct StringMap {
 
 impl CqlSerializable for StringMap {
     fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        buf.write_u16::<BigEndian>(self.pairs.len() as u16)?;
+        buf.write_u16::<BigEndian>(short_len(self.pairs.len())?)?;
         for pair in self.pairs.iter() {
             pair.serialize(buf)?;
         }
@@ -659,33 +1425,208 @@ impl CqlSerializable for StringMap {
     }
 }
 
-#[derive(Debug)]
-struct CqlColMetadata {
+#[derive(Clone, Debug)]
+pub struct CqlColMetadata {
     keyspace: Option<String>,
     table: Option<String>,
     col_name: String,
     col_type: CqlColDescr,
 }
 
-#[derive(Debug)]
-enum CqlColDescr {
+impl CqlColMetadata {
+    pub fn keyspace(&self) -> Option<&str> {
+        self.keyspace.as_deref()
+    }
+
+    pub fn table(&self) -> Option<&str> {
+        self.table.as_deref()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.col_name
+    }
+
+    pub fn col_type(&self) -> &CqlColDescr {
+        &self.col_type
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CqlColDescr {
     Custom(String),
     Single(ColumnType),
     List(Box<CqlColDescr>),
     Map(Box<(CqlColDescr, CqlColDescr)>),
     Set(Box<CqlColDescr>),
-    //UDT,
     Tuple(Box<[CqlColDescr]>),
+    Udt {
+        keyspace: String,
+        name: String,
+        fields: Vec<(String, CqlColDescr)>,
+    },
 }
 
-#[derive(Debug)]
-pub struct Metadata {
-    flags: u32,
-    column_count: u32,
-    keyspace: Option<String>,
-    table: Option<String>,
-    row_metadata: Vec<CqlColMetadata>,
-}
+// The type as written in CQL, e.g. `map<text, frozen<address>>`. A UDT
+// is named without its keyspace, which is always the table's own.
+// Collections and tuples inside another type are frozen, as CQL requires.
+impl fmt::Display for CqlColDescr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CqlColDescr::Custom(ref class) => write!(f, "'{}'", class),
+            CqlColDescr::Single(ty) => write!(f, "{}", ty),
+            CqlColDescr::List(ref ty) => write!(f, "list<{}>", Nested(ty)),
+            CqlColDescr::Set(ref ty) => write!(f, "set<{}>", Nested(ty)),
+            CqlColDescr::Map(ref tys) => {
+                write!(f, "map<{}, {}>", Nested(&tys.0), Nested(&tys.1))
+            }
+            CqlColDescr::Tuple(ref tys) => {
+                f.write_str("tuple<")?;
+                for (i, ty) in tys.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", Nested(ty))?;
+                }
+                f.write_str(">")
+            }
+            CqlColDescr::Udt { ref name, .. } => write!(f, "frozen<{}>", name),
+        }
+    }
+}
+
+// A type inside another one. UDTs are frozen by their own Display.
+struct Nested<'a>(&'a CqlColDescr);
+
+impl<'a> fmt::Display for Nested<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            CqlColDescr::List(_)
+            | CqlColDescr::Set(_)
+            | CqlColDescr::Map(_)
+            | CqlColDescr::Tuple(_) => write!(f, "frozen<{}>", self.0),
+            ref ty => write!(f, "{}", ty),
+        }
+    }
+}
+
+impl CqlColDescr {
+    fn heap_size(&self) -> usize {
+        use std::mem::size_of;
+
+        match *self {
+            CqlColDescr::Custom(ref name) => name.capacity(),
+            CqlColDescr::Single(_) => 0,
+            CqlColDescr::List(ref ty) | CqlColDescr::Set(ref ty) => {
+                size_of::<CqlColDescr>() + ty.heap_size()
+            }
+            CqlColDescr::Map(ref tys) => {
+                size_of::<(CqlColDescr, CqlColDescr)>() + tys.0.heap_size() + tys.1.heap_size()
+            }
+            CqlColDescr::Tuple(ref tys) => tys
+                .iter()
+                .map(|ty| size_of::<CqlColDescr>() + ty.heap_size())
+                .sum(),
+            CqlColDescr::Udt {
+                ref keyspace,
+                ref name,
+                ref fields,
+            } => {
+                keyspace.capacity()
+                    + name.capacity()
+                    + fields.capacity() * size_of::<(String, CqlColDescr)>()
+                    + fields
+                        .iter()
+                        .map(|(name, ty)| name.capacity() + ty.heap_size())
+                        .sum::<usize>()
+            }
+        }
+    }
+}
+
+// Bits of `Metadata::flags`, which combine.
+pub const GLOBAL_TABLE_SPEC: u32 = 0x0001;
+pub const HAS_MORE_PAGES: u32 = 0x0002;
+pub const NO_METADATA: u32 = 0x0004;
+// Protocol v5 only.
+pub const METADATA_CHANGED: u32 = 0x0008;
+
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    flags: u32,
+    column_count: u32,
+    keyspace: Option<String>,
+    table: Option<String>,
+    paging_state: Option<Vec<u8>>,
+    row_metadata: Vec<CqlColMetadata>,
+    column_indexes: HashMap<String, usize>,
+}
+
+// Maps names to the first column of that name, as a scan would find.
+fn index_columns(columns: &[CqlColMetadata]) -> HashMap<String, usize> {
+    let mut indexes = HashMap::with_capacity(columns.len());
+    for (i, col) in columns.iter().enumerate() {
+        indexes.entry(col.col_name.clone()).or_insert(i);
+    }
+    indexes
+}
+
+impl Metadata {
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn column_count(&self) -> u32 {
+        self.column_count
+    }
+
+    pub fn keyspace(&self) -> Option<&str> {
+        self.keyspace.as_deref()
+    }
+
+    pub fn table(&self) -> Option<&str> {
+        self.table.as_deref()
+    }
+
+    pub fn columns(&self) -> &[CqlColMetadata] {
+        &self.row_metadata
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.column_indexes.get(name).cloned()
+    }
+
+    fn heap_size(&self) -> usize {
+        use std::mem::size_of;
+
+        let strings = [&self.keyspace, &self.table]
+            .iter()
+            .filter_map(|s| s.as_ref())
+            .map(|s| s.capacity())
+            .sum::<usize>();
+        let columns = self.row_metadata.iter().map(|col| {
+            col.keyspace.as_ref().map_or(0, |s| s.capacity())
+                + col.table.as_ref().map_or(0, |s| s.capacity())
+                + col.col_name.capacity()
+                + col.col_type.heap_size()
+        });
+        let indexes = self.column_indexes.capacity() * size_of::<(String, usize)>()
+            + self
+                .column_indexes
+                .keys()
+                .map(|k| k.capacity())
+                .sum::<usize>();
+        strings
+            + self.paging_state.as_ref().map_or(0, |s| s.capacity())
+            + self.row_metadata.capacity() * size_of::<CqlColMetadata>()
+            + columns.sum::<usize>()
+            + indexes
+    }
+
+    // Set when the server has more pages; send it back to fetch the next one.
+    pub fn paging_state(&self) -> Option<&[u8]> {
+        self.paging_state.as_deref()
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum Value {
@@ -696,7 +1637,8 @@ pub enum Value {
     CqlBigint(i64),
     CqlBlob(Vec<u8>),
     CqlBoolean(bool),
-    CqlCounter(u64),
+    // Signed like bigint: counters can be decremented below zero.
+    CqlCounter(i64),
     CqlDecimal(i32, i64),
     CqlDouble(f64),
     CqlFloat(f32),
@@ -708,533 +1650,6834 @@ pub enum Value {
     CqlVarInt(i64),
     CqlTimeUUID([u8; 16]),
     CqlInet(std::net::IpAddr),
+    // Days since the epoch, offset by 2^31 so the epoch is in the middle.
+    CqlDate(u32),
+    // Nanoseconds since midnight.
+    CqlTime(i64),
+    CqlSmallInt(i16),
+    CqlTinyInt(i8),
+    // Months, days and nanoseconds, kept apart since their lengths vary.
+    CqlDuration(i32, i32, i64),
     CqlList(Vec<Value>),
     CqlMap(Vec<(Value, Value)>),
     CqlSet(Vec<Value>),
-    CqlUDT,
-    CqlTuple(Vec<Vec<Value>>),
+    // Fields in the order the type declares them.
+    CqlUDT(Vec<(String, Value)>),
+    // Likewise, every field.
+    CqlTuple(Vec<Value>),
     CqlUnknown,
 }
 
-impl CqlSerializable for Value {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+impl Value {
+    pub fn timestamp_from_unix_millis(millis: i64) -> Value {
+        Value::CqlTimestamp(millis)
+    }
+
+    // Decodes the content of a [bytes] value, without its length, as a value
+    // of `col_type`. Every byte must be used.
+    pub fn decode(col_type: &CqlColDescr, bytes: &[u8]) -> Result<Value> {
+        let mut reader = bytes;
+        let value = reader.read_cql_col_content(col_type, bytes.len())?;
+        if !reader.is_empty() {
+            return Err(Error::Protocol);
+        }
+        Ok(value)
+    }
+
+    // The content `decode` reads back. Null is a length with no content, so
+    // it has none to encode.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        if self.is_null() {
+            return Err(Error::Protocol);
+        }
+        let mut bytes = self.to_vec()?;
+        bytes.drain(..4);
+        Ok(bytes)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(*self, Value::CqlNull)
+    }
+
+    // The CQL name of the value's type, e.g. "int" or "list".
+    pub fn type_name(&self) -> &'static str {
         use Value::*;
 
-        match self {
-            CqlNull => {
-                buf.write_i32::<BigEndian>(-1)?;
-                return Ok(());
+        match *self {
+            CqlNull => "null",
+            CqlCustom(..) => "custom",
+            CqlAscii(_) => "ascii",
+            CqlBigint(_) => "bigint",
+            CqlBlob(_) => "blob",
+            CqlBoolean(_) => "boolean",
+            CqlCounter(_) => "counter",
+            CqlDecimal(..) => "decimal",
+            CqlDouble(_) => "double",
+            CqlFloat(_) => "float",
+            CqlInt(_) => "int",
+            CqlText(_) => "text",
+            CqlTimestamp(_) => "timestamp",
+            CqlUUID(_) => "uuid",
+            CqlVarChar(_) => "varchar",
+            CqlVarInt(_) => "varint",
+            CqlTimeUUID(_) => "timeuuid",
+            CqlInet(_) => "inet",
+            CqlDate(_) => "date",
+            CqlTime(_) => "time",
+            CqlSmallInt(_) => "smallint",
+            CqlTinyInt(_) => "tinyint",
+            CqlDuration(..) => "duration",
+            CqlList(_) => "list",
+            CqlMap(_) => "map",
+            CqlSet(_) => "set",
+            CqlUDT(_) => "udt",
+            CqlTuple(_) => "tuple",
+            CqlUnknown => "unknown",
+        }
+    }
+
+    // A duration in full; `From<std::time::Duration>` only sets nanoseconds.
+    // All three should have the same sign, as the server refuses others.
+    pub fn duration(months: i32, days: i32, nanos: i64) -> Value {
+        Value::CqlDuration(months, days, nanos)
+    }
+
+    pub fn to_unix_millis(&self) -> Option<i64> {
+        match *self {
+            Value::CqlTimestamp(millis) => Some(millis),
+            _ => None,
+        }
+    }
+
+    // Bytes allocated on the heap for this value, not counting the value
+    // itself. Unlike `len_`, this is about memory rather than the wire.
+    pub fn heap_size(&self) -> usize {
+        use std::mem::size_of;
+        use Value::*;
+
+        match *self {
+            CqlCustom(ref name, ref v) => name.capacity() + v.capacity(),
+            CqlAscii(ref v) | CqlText(ref v) | CqlVarChar(ref v) => v.capacity(),
+            CqlBlob(ref v) => v.capacity(),
+            CqlList(ref v) | CqlSet(ref v) | CqlTuple(ref v) => {
+                v.capacity() * size_of::<Value>()
+                    + v.iter().map(|item| item.heap_size()).sum::<usize>()
             }
-            _ => (),
+            CqlMap(ref v) => {
+                v.capacity() * size_of::<(Value, Value)>()
+                    + v.iter()
+                        .map(|(key, val)| key.heap_size() + val.heap_size())
+                        .sum::<usize>()
+            }
+            CqlUDT(ref v) => {
+                v.capacity() * size_of::<(String, Value)>()
+                    + v.iter()
+                        .map(|(name, val)| name.capacity() + val.heap_size())
+                        .sum::<usize>()
+            }
+            _ => 0,
         }
+    }
 
-        let len = self.len_() - 4;
-        buf.write_u32::<BigEndian>(len as u32)?;
-        match self {
-            CqlNull => unreachable!(),
-            CqlCustom(ref _name, ref v) => buf.write_all(&v)?,
-            CqlAscii(ref v) => buf.write_all(v.as_bytes())?,
-            CqlBigint(v) => buf.write_i64::<BigEndian>(*v)?,
-            CqlBlob(ref v) => buf.write_all(&v)?,
-            CqlBoolean(ref b) => buf.write_u8(*b as u8)?,
-            CqlCounter(_) => return Err(Error::Unimplemented),
-            CqlDecimal(_, _) => return Err(Error::Unimplemented),
-            CqlDouble(v) => {
-                let b: u64 = unsafe { transmute(*v) };
-                buf.write_u64::<BigEndian>(b)?;
+    // The server never sends null set elements or map keys, so finding one
+    // means the decoder lost track of the frame.
+    fn validate_strict(&self) -> Result<()> {
+        use Value::*;
+
+        match *self {
+            CqlList(ref v) | CqlTuple(ref v) => {
+                v.iter().try_for_each(|item| item.validate_strict())
+            }
+            CqlSet(ref v) => v.iter().try_for_each(|item| match *item {
+                CqlNull => Err(Error::Protocol),
+                ref item => item.validate_strict(),
+            }),
+            CqlMap(ref v) => v.iter().try_for_each(|(key, val)| match *key {
+                CqlNull => Err(Error::Protocol),
+                ref key => key.validate_strict().and_then(|_| val.validate_strict()),
+            }),
+            CqlUDT(ref v) => v.iter().try_for_each(|(_, val)| val.validate_strict()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Values as CQL literals, e.g. 'it''s', 0xcafe or {1: 'a'}, with dates,
+// times and durations in the forms CQL reads them.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Value::*;
+
+        match *self {
+            CqlNull => f.write_str("null"),
+            CqlCustom(_, ref v) | CqlBlob(ref v) => {
+                f.write_str("0x")?;
+                v.iter().try_for_each(|b| write!(f, "{:02x}", b))
             }
-            CqlFloat(v) => {
-                let b: u32 = unsafe { transmute(*v) };
-                buf.write_u32::<BigEndian>(b)?;
+            CqlAscii(ref v) | CqlText(ref v) | CqlVarChar(ref v) => {
+                write!(f, "'{}'", v.replace('\'', "''"))
             }
-            CqlInt(v) => buf.write_i32::<BigEndian>(*v)?,
-            CqlText(ref v) => buf.write_all(v.as_bytes())?,
-            CqlTimestamp(v) => buf.write_i64::<BigEndian>(*v)?,
-            CqlUUID(ref v) => buf.write_all(v)?,
-            CqlVarChar(ref v) => buf.write_all(v.as_bytes())?,
-            CqlVarInt(v) => {
-                //TODO: compress varint
-                buf.write_i64::<BigEndian>(*v)?
+            CqlBigint(v) | CqlCounter(v) | CqlVarInt(v) => write!(f, "{}", v),
+            CqlBoolean(v) => write!(f, "{}", v),
+            CqlDecimal(scale, unscaled) => fmt_decimal(f, scale, unscaled),
+            CqlDouble(v) if v.is_finite() => write!(f, "{:?}", v),
+            CqlFloat(v) if v.is_finite() => write!(f, "{:?}", v),
+            CqlDouble(v) => fmt_non_finite(f, v),
+            CqlFloat(v) => fmt_non_finite(f, f64::from(v)),
+            CqlInt(v) => write!(f, "{}", v),
+            CqlTimestamp(millis) => {
+                let (days, millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+                f.write_str("'")?;
+                fmt_date(f, days)?;
+                f.write_str(" ")?;
+                fmt_time(f, millis / 1000, &format!("{:03}", millis % 1000))?;
+                f.write_str("+0000'")
             }
-            CqlTimeUUID(ref v) => buf.write_all(v)?,
-            CqlInet(ref v) => match v {
-                std::net::IpAddr::V4(v) => buf.write_all(&v.octets())?,
-                std::net::IpAddr::V6(v) => buf.write_all(&v.octets())?,
-            },
-            CqlList(v) => {
-                buf.write_u32::<BigEndian>(v.len() as u32)?;
-                for item in v {
-                    item.serialize(buf)?;
+            CqlUUID(ref v) | CqlTimeUUID(ref v) => {
+                for (i, b) in v.iter().enumerate() {
+                    if i == 4 || i == 6 || i == 8 || i == 10 {
+                        f.write_str("-")?;
+                    }
+                    write!(f, "{:02x}", b)?;
                 }
+                Ok(())
             }
-            CqlMap(v) => {
-                buf.write_u32::<BigEndian>(v.len() as u32)?;
-                for (ref k, ref v) in v {
-                    k.serialize(buf)?;
-                    v.serialize(buf)?;
-                }
+            CqlInet(ref v) => write!(f, "'{}'", v),
+            CqlDate(v) => {
+                f.write_str("'")?;
+                fmt_date(f, i64::from(v) - (1 << 31))?;
+                f.write_str("'")
             }
-            CqlSet(v) => {
-                buf.write_u32::<BigEndian>(v.len() as u32)?;
-                for item in v {
-                    item.serialize(buf)?;
+            CqlTime(nanos) => {
+                let fraction = format!("{:09}", nanos % 1_000_000_000);
+                f.write_str("'")?;
+                fmt_time(f, nanos / 1_000_000_000, &fraction)?;
+                f.write_str("'")
+            }
+            CqlSmallInt(v) => write!(f, "{}", v),
+            CqlTinyInt(v) => write!(f, "{}", v),
+            CqlDuration(months, days, nanos) => fmt_duration(f, months, days, nanos),
+            CqlList(ref v) => fmt_seq(f, "[", v.iter(), "]"),
+            CqlSet(ref v) => fmt_seq(f, "{", v.iter(), "}"),
+            CqlMap(ref v) => {
+                f.write_str("{")?;
+                for (i, (key, val)) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", key, val)?;
                 }
+                f.write_str("}")
             }
-            CqlUDT => return Err(Error::Unimplemented),
-            CqlTuple(v) => {
-                buf.write_u32::<BigEndian>(v.len() as u32)?;
-                for tup in v {
-                    for item in tup {
-                        item.serialize(buf)?;
+            CqlUDT(ref v) => {
+                f.write_str("{")?;
+                for (i, (name, val)) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
                     }
+                    write!(f, "{}: {}", name, val)?;
                 }
+                f.write_str("}")
             }
-            CqlUnknown => return Err(Error::Unimplemented),
-        };
-        Ok(())
+            CqlTuple(ref v) => fmt_seq(f, "(", v.iter(), ")"),
+            CqlUnknown => f.write_str("<unknown>"),
+        }
     }
+}
 
-    fn len_(&self) -> usize {
-        use std::mem::size_of;
-        use Value::*;
-
-        let body_len = match self {
-            CqlNull => 0,
-            CqlCustom(ref _name, ref v) => v.len(),
-            CqlAscii(ref v) => v.len(),
-            CqlBigint(_) => size_of::<i64>(),
-            CqlBlob(ref v) => v.len(),
-            CqlBoolean(_) => size_of::<u8>(),
-            CqlCounter(_) => size_of::<u64>(),
-            CqlDecimal(_, _) => unimplemented!(),
-            CqlDouble(_) => size_of::<f64>(),
-            CqlFloat(_) => size_of::<f32>(),
-            CqlInt(_) => size_of::<i32>(),
-            CqlText(ref v) => v.len(),
-            CqlTimestamp(_) => size_of::<i64>(),
-            CqlUUID(_) => 16,
-            CqlVarChar(ref v) => v.len(),
-            CqlVarInt(_) => {
-                //TODO: compress varint
-                size_of::<i64>()
-            }
-            CqlTimeUUID(_) => 16,
-            CqlInet(ref v) => match *v {
-                std::net::IpAddr::V4(_) => 4,
-                std::net::IpAddr::V6(_) => 16,
-            },
-            CqlList(v) => 4 + v.iter().map(|item| item.len_()).sum::<usize>(),
-            CqlMap(v) => {
-                4 + v.iter()
-                    .map(|tup| tup.0.len_() + tup.1.len_())
-                    .sum::<usize>()
-            }
-            CqlSet(v) => 4 + v.iter().map(|item| item.len_()).sum::<usize>(),
-            CqlUDT => unimplemented!(),
-            CqlTuple(v) => {
-                4 + v.iter()
-                    .map(|t| -> usize { t.iter().map(|c| c.len_()).sum::<usize>() })
-                    .sum::<usize>()
-            }
-            CqlUnknown => unimplemented!(),
-        };
-        4 + body_len
+fn fmt_seq<'a, I: Iterator<Item = &'a Value>>(
+    f: &mut fmt::Formatter,
+    open: &str,
+    items: I,
+    close: &str,
+) -> fmt::Result {
+    f.write_str(open)?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
     }
+    f.write_str(close)
 }
 
-#[derive(Debug)]
-pub struct Row {
-    cols: Vec<Value>,
-    metadata: Rc<Metadata>,
+fn fmt_non_finite(f: &mut fmt::Formatter, v: f64) -> fmt::Result {
+    if v.is_nan() {
+        f.write_str("NaN")
+    } else if v > 0.0 {
+        f.write_str("Infinity")
+    } else {
+        f.write_str("-Infinity")
+    }
 }
 
-impl Row {
-    pub fn get_column(&self, col_name: &str) -> Option<Value> {
-        self.metadata
-            .row_metadata
-            .iter()
-            .position(|m| m.col_name == col_name)
-            .map(|i| self.cols[i].clone())
+// Plain unless that would take more than six zeros after the point, like
+// Java's BigDecimal.
+fn fmt_decimal(f: &mut fmt::Formatter, scale: i32, unscaled: i64) -> fmt::Result {
+    let digits = unscaled.unsigned_abs().to_string();
+    if scale < 0 || scale as usize > digits.len() + 6 {
+        return write!(f, "{}E{}", unscaled, -i64::from(scale));
+    }
+    let scale = scale as usize;
+    let sign = if unscaled < 0 { "-" } else { "" };
+    if scale == 0 {
+        write!(f, "{}{}", sign, digits)
+    } else if scale < digits.len() {
+        let (int, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, int, fraction)
+    } else {
+        write!(f, "{}0.{:0>2$}", sign, digits, scale)
     }
 }
 
-#[derive(Debug)]
-pub struct Rows {
-    metadata: Rc<Metadata>,
-    rows: Vec<Row>,
+// The proleptic Gregorian date `days` after 1970-01-01, as in
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn fmt_date(f: &mut fmt::Formatter, days: i64) -> fmt::Result {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    write!(f, "{:04}-{:02}-{:02}", year, month, day)
 }
 
-struct BodyStartup {
-    body: StringMap,
+fn fmt_time(f: &mut fmt::Formatter, seconds: i64, fraction: &str) -> fmt::Result {
+    write!(
+        f,
+        "{:02}:{:02}:{:02}.{}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        fraction
+    )
 }
-impl CqlSerializable for BodyStartup {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        self.body.serialize(buf)
-    }
 
-    fn len_(&self) -> usize {
-        self.body.len_()
-    }
+fn mixed_signs(months: i32, days: i32, nanos: i64) -> bool {
+    let parts = [i64::from(months), i64::from(days), nanos];
+    parts.iter().any(|&v| v < 0) && parts.iter().any(|&v| v > 0)
 }
 
-struct QueryParams {
-    con: Consistency,
-    params: Vec<Value>,
+// Like Cassandra, e.g. 1y2mo3d4h5m6s7ms8us9ns, leaving out zero units. A
+// duration with mixed signs, which CQL can't write, gets a sign per part,
+// e.g. 1mo-1d.
+fn fmt_duration(f: &mut fmt::Formatter, months: i32, days: i32, nanos: i64) -> fmt::Result {
+    if months == 0 && days == 0 && nanos == 0 {
+        return f.write_str("0s");
+    }
+    if mixed_signs(months, days, nanos) {
+        for &(months, days, nanos) in &[(months, 0, 0), (0, days, 0), (0, 0, nanos)] {
+            if (months, days, nanos) != (0, 0, 0) {
+                fmt_duration(f, months, days, nanos)?;
+            }
+        }
+        return Ok(());
+    }
+    if months < 0 || days < 0 || nanos < 0 {
+        f.write_str("-")?;
+    }
+    let mut unit = |v: u64, per: u64, name: &str| -> std::result::Result<u64, fmt::Error> {
+        if v >= per {
+            write!(f, "{}{}", v / per, name)?;
+        }
+        Ok(v % per)
+    };
+    let months = unit(u64::from(months.unsigned_abs()), 12, "y")?;
+    unit(months, 1, "mo")?;
+    unit(u64::from(days.unsigned_abs()), 1, "d")?;
+    let mut nanos = nanos.unsigned_abs();
+    for &(per, name) in &[
+        (3_600_000_000_000, "h"),
+        (60_000_000_000, "m"),
+        (1_000_000_000, "s"),
+        (1_000_000, "ms"),
+        (1000, "us"),
+        (1, "ns"),
+    ] {
+        nanos = unit(nanos, per, name)?;
+    }
+    Ok(())
 }
-impl CqlSerializable for QueryParams {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        buf.write_u16::<BigEndian>(self.con.clone() as u16)?;
-        buf.write_u8(0x01)?;
 
-        buf.write_u16::<BigEndian>(self.params.len() as u16)?;
-        for v in &self.params {
-            v.serialize(buf)?;
+// Like SELECT JSON: numbers, booleans, arrays and objects where JSON has
+// them, and the CQL literal without quotes for everything else.
+#[cfg(feature = "json")]
+impl Value {
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+        use Value::*;
+
+        match *self {
+            CqlNull | CqlUnknown => Json::Null,
+            CqlAscii(ref v) | CqlText(ref v) | CqlVarChar(ref v) => Json::String(v.clone()),
+            CqlBigint(v) | CqlCounter(v) | CqlVarInt(v) => Json::from(v),
+            CqlInt(v) => Json::from(v),
+            CqlSmallInt(v) => Json::from(v),
+            CqlTinyInt(v) => Json::from(v),
+            CqlBoolean(v) => Json::Bool(v),
+            CqlDouble(v) if v.is_finite() => Json::from(v),
+            // Through the shortest decimal form, so 0.1f32 stays 0.1.
+            CqlFloat(v) if v.is_finite() => Json::from(v.to_string().parse::<f64>().unwrap()),
+            CqlList(ref v) | CqlSet(ref v) | CqlTuple(ref v) => {
+                Json::Array(v.iter().map(|item| item.to_json()).collect())
+            }
+            CqlMap(ref v) => Json::Object(
+                v.iter()
+                    .map(|(key, val)| {
+                        let key = match key.to_json() {
+                            Json::String(key) => key,
+                            key => key.to_string(),
+                        };
+                        (key, val.to_json())
+                    })
+                    .collect(),
+            ),
+            CqlUDT(ref v) => Json::Object(
+                v.iter()
+                    .map(|(name, val)| (name.clone(), val.to_json()))
+                    .collect(),
+            ),
+            _ => {
+                let literal = self.to_string();
+                Json::String(literal.trim_matches('\'').to_string())
+            }
         }
-        Ok(())
-    }
-    fn len_(&self) -> usize {
-        3 + 2 + self.params.iter().map(|v| v.len_()).sum::<usize>()
     }
 }
 
-struct BodyQuery {
-    query: String,
-    params: QueryParams,
+pub trait FromCqlValue: Sized {
+    fn from_cql_value(value: Value) -> Result<Self>;
 }
-impl CqlSerializable for BodyQuery {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        LongString(&self.query).serialize(buf)?;
-        self.params.serialize(buf)
-    }
 
-    fn len_(&self) -> usize {
-        LongString(&self.query).len_() + self.params.len_()
+impl FromCqlValue for Value {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        Ok(value)
     }
 }
 
-struct BodyExecute {
-    id: Vec<u8>,
-    params: QueryParams,
-}
-impl CqlSerializable for BodyExecute {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        buf.write_u16::<BigEndian>(self.id.len() as u16)?;
-        buf.write_all(&self.id)?;
-        self.params.serialize(buf)
+impl FromCqlValue for i32 {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlInt(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
     }
+}
 
-    fn len_(&self) -> usize {
-        2 + self.id.len() + self.params.len_()
+impl FromCqlValue for i8 {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlTinyInt(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
     }
 }
 
-struct BodyPrepare {
-    query: String,
+impl FromCqlValue for i16 {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlSmallInt(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
 }
-impl CqlSerializable for BodyPrepare {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        LongString(&self.query).serialize(buf)
+
+impl FromCqlValue for i64 {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlBigint(v) | Value::CqlCounter(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
     }
+}
 
-    fn len_(&self) -> usize {
-        LongString(&self.query).len_()
+impl FromCqlValue for String {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlAscii(v) | Value::CqlText(v) | Value::CqlVarChar(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
     }
 }
 
-struct BodyEmpty;
-impl CqlSerializable for BodyEmpty {
-    fn serialize<T: io::Write>(&self, _buf: &mut T) -> Result<()> {
-        Ok(())
+impl FromCqlValue for f32 {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlFloat(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
     }
+}
 
-    fn len_(&self) -> usize {
-        0
+impl FromCqlValue for f64 {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlDouble(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
     }
 }
 
-type StringMultiMap = Vec<(String, Vec<String>)>;
-
-#[derive(Debug)]
-pub enum ResponseBody {
-    Error(u32, String),
-    Ready,
-    Auth(String),
-    Supported(StringMultiMap),
-    Result(ResponseResult),
+impl FromCqlValue for bool {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlBoolean(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub enum ResponseResult {
-    Void,
-    Rows(Rows),
-    Keyspace(String),
-    Prepared(Vec<u8>, Metadata),
-    SchemaChange(String, String, String, Option<String>),
+impl FromCqlValue for Vec<u8> {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlBlob(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct FrameHeader {
-    version: u8,
-    flags: u8,
-    stream: i16,
-    opcode: Opcode,
-}
-impl FrameHeader {
-    fn new(stream: i16, opcode: Opcode) -> Self {
-        Self {
-            version: CQL_VERSION,
-            flags: 0x00,
-            stream,
-            opcode,
+// Either a uuid or a timeuuid.
+#[cfg(feature = "uuid")]
+impl FromCqlValue for uuid::Uuid {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlUUID(v) | Value::CqlTimeUUID(v) => Ok(uuid::Uuid::from_bytes(v)),
+            _ => Err(Error::TypeMismatch),
         }
     }
 }
 
-#[derive(Debug)]
-struct Request<B: CqlSerializable> {
-    header: FrameHeader,
-    body: B,
+// Values outside of chrono's range, some 262,000 years either side of the
+// epoch, are a TypeMismatch like any other that doesn't fit.
+#[cfg(feature = "chrono")]
+impl FromCqlValue for chrono::DateTime<chrono::Utc> {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlTimestamp(millis) => {
+                chrono::DateTime::from_timestamp_millis(millis).ok_or(Error::TypeMismatch)
+            }
+            _ => Err(Error::TypeMismatch),
+        }
+    }
 }
 
-impl<B: CqlSerializable> CqlSerializable for Request<B> {
-    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
-        let header = &self.header;
-        buf.write_u8(header.version)?;
-        buf.write_u8(header.flags)?;
-        buf.write_i16::<BigEndian>(header.stream)?;
-        buf.write_u8(header.opcode.clone() as u8)?;
-
-        buf.write_u32::<BigEndian>(self.body.len_() as u32)?;
-        self.body.serialize(buf)?;
-        Ok(())
+#[cfg(feature = "chrono")]
+impl FromCqlValue for chrono::NaiveDate {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlDate(v) => i32::try_from(i64::from(v) - (1 << 31) + DAYS_FROM_CE_TO_EPOCH)
+                .ok()
+                .and_then(chrono::NaiveDate::from_num_days_from_ce_opt)
+                .ok_or(Error::TypeMismatch),
+            _ => Err(Error::TypeMismatch),
+        }
     }
+}
 
-    fn len_(&self) -> usize {
-        self.body.len_() + 9
+#[cfg(feature = "chrono")]
+impl FromCqlValue for chrono::NaiveTime {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlTime(nanos) if (0..NANOS_PER_DAY).contains(&nanos) => {
+                chrono::NaiveTime::from_num_seconds_from_midnight_opt(
+                    (nanos / 1_000_000_000) as u32,
+                    (nanos % 1_000_000_000) as u32,
+                )
+                .ok_or(Error::TypeMismatch)
+            }
+            _ => Err(Error::TypeMismatch),
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct Response {
-    header: FrameHeader,
-    body: ResponseBody,
+impl FromCqlValue for IpAddr {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlInet(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
 }
 
-fn startup() -> Request<BodyStartup> {
-    let body = StringMap {
-        pairs: vec![Pair {
-            key: "CQL_VERSION".to_owned(),
-            value: "3.0.0".to_owned(),
-        }],
-    };
-    Request {
-        header: FrameHeader::new(1, Opcode::Startup),
-        body: BodyStartup { body },
+impl<T: FromCqlValue> FromCqlValue for Option<T> {
+    fn from_cql_value(value: Value) -> Result<Self> {
+        match value {
+            Value::CqlNull => Ok(None),
+            value => T::from_cql_value(value).map(Some),
+        }
     }
 }
 
-/*
-#[allow(unused)]
-fn auth(creds: Vec<Vec<u8>>) -> Request {
-    return Request {
-        header: FrameHeader::new(1, Opcode::Auth),
-        body: RequestBody::RequestCred(creds),
-    };
+impl From<i8> for Value {
+    fn from(v: i8) -> Value {
+        Value::CqlTinyInt(v)
+    }
 }
-*/
 
-#[allow(unused)]
-fn options() -> Request<BodyEmpty> {
-    Request {
-        header: FrameHeader::new(1, Opcode::Opts),
-        body: BodyEmpty,
+impl From<i16> for Value {
+    fn from(v: i16) -> Value {
+        Value::CqlSmallInt(v)
     }
 }
 
-fn query(stream: i16, query_str: &str, con: Consistency, params: Vec<Value>) -> Request<BodyQuery> {
-    Request {
-        header: FrameHeader::new(stream, Opcode::Query),
-        body: BodyQuery {
-            query: query_str.to_owned(),
-            params: QueryParams { con, params },
-        },
+impl From<i32> for Value {
+    fn from(v: i32) -> Value {
+        Value::CqlInt(v)
     }
 }
 
-fn prepare(stream: i16, query_str: &str) -> Request<BodyPrepare> {
-    Request {
-        header: FrameHeader::new(stream, Opcode::Prepare),
-        body: BodyPrepare {
-            query: query_str.to_owned(),
-        },
+impl From<i64> for Value {
+    fn from(v: i64) -> Value {
+        Value::CqlBigint(v)
     }
 }
 
-fn execute(stream: i16, id: Vec<u8>, con: Consistency, params: Vec<Value>) -> Request<BodyExecute> {
-    Request {
-        header: FrameHeader::new(stream, Opcode::Execute),
-        body: BodyExecute {
-            id: id.clone(),
-            params: QueryParams { con, params },
-        },
+impl From<f32> for Value {
+    fn from(v: f32) -> Value {
+        Value::CqlFloat(v)
     }
 }
 
-pub struct Client {
-    socket: TcpStream,
+impl From<f64> for Value {
+    fn from(v: f64) -> Value {
+        Value::CqlDouble(v)
+    }
 }
 
-impl Client {
-    pub fn new(addr: &str) -> Result<Client> {
-        let mut socket = TcpStream::connect(addr)?;
-        let msg_startup = startup().to_vec()?;
-
-        socket.write_all(&msg_startup)?;
-        let response = socket.read_cql_response()?;
-        match response.body {
-            ResponseBody::Ready => Ok(Client { socket: socket }),
-            /*
-            Auth(_) => {
-                match(creds) {
-                    Some(cred) => {
-                        let msg_auth = Auth(cred);
-                        msg_auth.serialize::<net_tcp::TcpSocketBuf>(&buf);
-                        let response = buf.read_cql_response();
-                        match response.body {
-                            Ready => result::Ok(Client { socket: buf }),
-                            Error(_, ref msg) => {
-                                result::Err(Error(~"Error", copy *msg))
-                            }
-                            _ => {
-                                result::Err(Error(~"Error", ~"Server returned unknown message"))
-                            },
-                        }
-                    },
-                    None => {
-                        result::Err(Error(~"Error", ~"Credential should be provided"))
-                    },
-                }
-            }
-            */
-            ResponseBody::Auth(_) => Err(Error::Unimplemented),
-            _ => Err(Error::Protocol),
-        }
+impl From<bool> for Value {
+    fn from(v: bool) -> Value {
+        Value::CqlBoolean(v)
     }
+}
 
-    pub fn options(&mut self) -> Result<Response> {
-        let msg = options().to_vec()?;
-        self.send(&msg)
+impl From<String> for Value {
+    fn from(v: String) -> Value {
+        Value::CqlVarChar(v)
     }
+}
 
-    //TODO: signature
-    pub fn query(
-        &mut self,
-        query_str: &str,
-        con: Consistency,
-        values: Vec<Value>,
-    ) -> Result<Response> {
-        let msg = query(0, query_str, con, values).to_vec()?;
-        self.send(&msg)
+impl<'a> From<&'a str> for Value {
+    fn from(v: &'a str) -> Value {
+        Value::CqlVarChar(v.to_owned())
     }
+}
 
-    pub fn prepare(&mut self, query_str: &str) -> Result<Vec<u8>> {
-        let msg = prepare(0, query_str).to_vec()?;
-        let resp = self.send(&msg)?;
-        match resp.body {
-            ResponseBody::Result(res) => match res {
-                ResponseResult::Prepared(id, _) => Ok(id),
-                _ => Err(Error::Protocol),
-            },
-            _ => Err(Error::Protocol),
-        }
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Value {
+        Value::CqlBlob(v)
     }
+}
 
-    pub fn execute(
-        &mut self,
-        id: Vec<u8>,
-        con: Consistency,
-        values: Vec<Value>,
-    ) -> Result<Response> {
-        let msg = execute(0, id, con, values).to_vec()?;
-        self.send(&msg)
+impl From<IpAddr> for Value {
+    fn from(v: IpAddr) -> Value {
+        Value::CqlInet(v)
     }
+}
 
-    fn send(&mut self, data: &[u8]) -> Result<Response> {
-        self.socket.write_all(data)?;
-        self.socket.read_cql_response()
+// Taken as a uuid; use CqlTimeUUID directly for a timeuuid column.
+impl From<[u8; 16]> for Value {
+    fn from(v: [u8; 16]) -> Value {
+        Value::CqlUUID(v)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Only as many nanoseconds as fit an i64, about 292 years, are kept.
+impl From<Duration> for Value {
+    fn from(v: Duration) -> Value {
+        Value::CqlDuration(0, 0, i64::try_from(v.as_nanos()).unwrap_or(i64::MAX))
+    }
+}
 
-    #[test]
-    fn test_parse_varint() {
-        assert_eq!(0, parse_varint(&[0]));
-        assert_eq!(1, parse_varint(&[1]));
-        assert_eq!(127, parse_varint(&[0x7f]));
-        assert_eq!(128, parse_varint(&[0x00, 0x80]));
-        assert_eq!(129, parse_varint(&[0x00, 0x81]));
+// Taken as a uuid like the array, which binds to a timeuuid column just as
+// well.
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for Value {
+    fn from(v: uuid::Uuid) -> Value {
+        Value::CqlUUID(v.into_bytes())
+    }
+}
 
-        assert_eq!(-1, parse_varint(&[0xff]));
-        assert_eq!(-128, parse_varint(&[0x80]));
-        assert_eq!(-129, parse_varint(&[0xff, 0x7f]));
+// Truncated to the millisecond, towards the past for pre-epoch times too.
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Value {
+    fn from(v: chrono::DateTime<chrono::Utc>) -> Value {
+        Value::CqlTimestamp(v.timestamp_millis())
     }
+}
 
-    #[test]
-    fn resp_ready() {
-        let v = vec![131, 0, 0, 1, 2, 0, 0, 0, 0];
-        let resp = v.as_slice().read_cql_response();
-        assert!(resp.is_ok())
+#[cfg(feature = "chrono")]
+impl From<chrono::NaiveDate> for Value {
+    fn from(v: chrono::NaiveDate) -> Value {
+        use chrono::Datelike;
+        let days = i64::from(v.num_days_from_ce()) - DAYS_FROM_CE_TO_EPOCH;
+        Value::CqlDate((days + (1 << 31)) as u32)
     }
+}
 
-    #[test]
-    fn resp_error() {
-        let v = vec![
-            131, 0, 0, 0, 0, 0, 0, 0, 49, 0, 0, 36, 0, 0, 35, 67, 97, 110, 110, 111, 116, 32, 97,
-            100, 100, 32, 101, 120, 105, 115, 116, 105, 110, 103, 32, 107, 101, 121, 115, 112, 97,
-            99, 101, 32, 34, 114, 117, 115, 116, 34, 0, 4, 114, 117, 115, 116, 0, 0,
-        ];
-        let resp = v.as_slice().read_cql_response();
-        assert!(resp.is_ok())
+// A leap second, which CQL has no room for, is kept as the end of the second
+// before it.
+#[cfg(feature = "chrono")]
+impl From<chrono::NaiveTime> for Value {
+    fn from(v: chrono::NaiveTime) -> Value {
+        use chrono::Timelike;
+        let nanos = v.nanosecond().min(999_999_999);
+        Value::CqlTime(i64::from(v.num_seconds_from_midnight()) * 1_000_000_000 + i64::from(nanos))
     }
+}
 
-    #[test]
-    fn resp_schema_change() {
-        let v = vec![
-            131, 0, 0, 0, 8, 0, 0, 0, 29, 0, 0, 0, 5, 0, 7, 67, 82, 69, 65, 84, 69, 68, 0, 8, 75,
-            69, 89, 83, 80, 65, 67, 69, 0, 4, 114, 117, 115, 116,
-        ];
-        let resp = v.as_slice().read_cql_response();
-        assert!(resp.is_ok())
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Value {
+        v.map_or(Value::CqlNull, Into::into)
     }
+}
 
-    #[test]
-    fn resp_schema_change_table() {
-        let v = vec![
-            131, 0, 0, 0, 8, 0, 0, 0, 32, 0, 0, 0, 5, 0, 7, 67, 82, 69, 65, 84, 69, 68, 0, 5, 84,
-            65, 66, 76, 69, 0, 4, 114, 117, 115, 116, 0, 4, 116, 101, 115, 116,
-        ];
-        let resp = v.as_slice().read_cql_response();
-        assert!(resp.is_ok())
+impl CqlSerializable for Value {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        use Value::*;
+
+        if let CqlNull = self {
+            buf.write_i32::<BigEndian>(-1)?;
+            return Ok(());
+        }
+
+        let len = self.len_() - 4;
+        buf.write_i32::<BigEndian>(int_len(len)?)?;
+        match self {
+            CqlNull => unreachable!(),
+            // The class name is implied by the column bound to, so a value
+            // read from a custom column can be bound back as it is.
+            CqlCustom(ref _name, ref v) => buf.write_all(v)?,
+            CqlAscii(ref v) => buf.write_all(v.as_bytes())?,
+            CqlBigint(v) => buf.write_i64::<BigEndian>(*v)?,
+            CqlBlob(ref v) => buf.write_all(v)?,
+            CqlBoolean(ref b) => buf.write_u8(*b as u8)?,
+            CqlCounter(_) => return Err(Error::Unimplemented),
+            CqlDecimal(scale, unscaled) => {
+                buf.write_i32::<BigEndian>(*scale)?;
+                write_varint(buf, *unscaled)?
+            }
+            CqlDouble(v) => buf.write_u64::<BigEndian>(v.to_bits())?,
+            CqlFloat(v) => buf.write_u32::<BigEndian>(v.to_bits())?,
+            CqlInt(v) => buf.write_i32::<BigEndian>(*v)?,
+            CqlText(ref v) => buf.write_all(v.as_bytes())?,
+            CqlTimestamp(v) => buf.write_i64::<BigEndian>(*v)?,
+            CqlUUID(ref v) => buf.write_all(v)?,
+            CqlVarChar(ref v) => buf.write_all(v.as_bytes())?,
+            CqlVarInt(v) => write_varint(buf, *v)?,
+            CqlTimeUUID(ref v) => buf.write_all(v)?,
+            CqlInet(ref v) => match v {
+                std::net::IpAddr::V4(v) => buf.write_all(&v.octets())?,
+                std::net::IpAddr::V6(v) => buf.write_all(&v.octets())?,
+            },
+            CqlDate(v) => buf.write_u32::<BigEndian>(*v)?,
+            CqlTime(v) => buf.write_i64::<BigEndian>(*v)?,
+            CqlSmallInt(v) => buf.write_i16::<BigEndian>(*v)?,
+            CqlTinyInt(v) => buf.write_i8(*v)?,
+            CqlDuration(months, days, nanos) => {
+                write_vint(buf, i64::from(*months))?;
+                write_vint(buf, i64::from(*days))?;
+                write_vint(buf, *nanos)?;
+            }
+            CqlList(v) => {
+                buf.write_i32::<BigEndian>(int_len(v.len())?)?;
+                for item in v {
+                    item.serialize(buf)?;
+                }
+            }
+            CqlMap(v) => {
+                buf.write_i32::<BigEndian>(int_len(v.len())?)?;
+                for (ref k, ref v) in v {
+                    k.serialize(buf)?;
+                    v.serialize(buf)?;
+                }
+            }
+            CqlSet(v) => {
+                buf.write_i32::<BigEndian>(int_len(v.len())?)?;
+                for item in v {
+                    item.serialize(buf)?;
+                }
+            }
+            // Null fields are written as such, keeping the later ones in
+            // place.
+            CqlUDT(v) => {
+                for (name, field) in v {
+                    field
+                        .serialize(buf)
+                        .map_err(|e| Error::UdtField(name.clone(), Box::new(e)))?;
+                }
+            }
+            CqlTuple(v) => {
+                for field in v {
+                    field.serialize(buf)?;
+                }
+            }
+            CqlUnknown => return Err(Error::Unimplemented),
+        };
+        Ok(())
     }
 
-    #[test]
-    fn resp_result_void() {
-        let v = vec![131, 0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0, 1];
-        let resp = v.as_slice().read_cql_response();
-        assert!(resp.is_ok())
+    fn len_(&self) -> usize {
+        use std::mem::size_of;
+        use Value::*;
+
+        let body_len = match self {
+            CqlNull => 0,
+            CqlCustom(ref _name, ref v) => v.len(),
+            CqlAscii(ref v) => v.len(),
+            CqlBigint(_) => size_of::<i64>(),
+            CqlBlob(ref v) => v.len(),
+            CqlBoolean(_) => size_of::<u8>(),
+            CqlCounter(_) => size_of::<i64>(),
+            CqlDecimal(_, unscaled) => 4 + varint_len(*unscaled),
+            CqlDouble(_) => size_of::<f64>(),
+            CqlFloat(_) => size_of::<f32>(),
+            CqlInt(_) => size_of::<i32>(),
+            CqlText(ref v) => v.len(),
+            CqlTimestamp(_) => size_of::<i64>(),
+            CqlUUID(_) => 16,
+            CqlVarChar(ref v) => v.len(),
+            CqlVarInt(v) => varint_len(*v),
+            CqlTimeUUID(_) => 16,
+            CqlInet(ref v) => match *v {
+                std::net::IpAddr::V4(_) => 4,
+                std::net::IpAddr::V6(_) => 16,
+            },
+            CqlDate(_) => size_of::<u32>(),
+            CqlTime(_) => size_of::<i64>(),
+            CqlSmallInt(_) => size_of::<i16>(),
+            CqlTinyInt(_) => size_of::<i8>(),
+            CqlDuration(months, days, nanos) => {
+                vint_len(i64::from(*months)) + vint_len(i64::from(*days)) + vint_len(*nanos)
+            }
+            CqlList(v) => 4 + v.iter().map(|item| item.len_()).sum::<usize>(),
+            CqlMap(v) => {
+                4 + v
+                    .iter()
+                    .map(|tup| tup.0.len_() + tup.1.len_())
+                    .sum::<usize>()
+            }
+            CqlSet(v) => 4 + v.iter().map(|item| item.len_()).sum::<usize>(),
+            CqlUDT(ref v) => v.iter().map(|(_, val)| val.len_()).sum::<usize>(),
+            CqlTuple(v) => v.iter().map(|field| field.len_()).sum::<usize>(),
+            // Fails in `serialize` instead.
+            CqlUnknown => 0,
+        };
+        4 + body_len
     }
+}
 
-    #[test]
-    fn resp_result_select() {
-        let v = vec![
+#[derive(Clone, Debug)]
+pub struct Row {
+    cols: Vec<Value>,
+    metadata: Arc<Metadata>,
+}
+
+impl Row {
+    pub fn get_column(&self, col_name: &str) -> Option<Value> {
+        self.metadata
+            .column_index(col_name)
+            .map(|i| self.cols[i].clone())
+    }
+
+    pub fn into_values(self) -> Vec<Value> {
+        self.cols
+    }
+
+    // The named column converted to `T`, e.g. `let id: i32 = row.get("id")?`.
+    // Read a nullable column as an Option; a null is a TypeMismatch otherwise.
+    pub fn get<T: FromCqlValue>(&self, col_name: &str) -> Result<T> {
+        match self.metadata.column_index(col_name) {
+            Some(i) => T::from_cql_value(self.cols[i].clone()),
+            None => Err(Error::ColumnNotFound(col_name.to_owned())),
+        }
+    }
+
+    // The result of `writetime(col_name)` selected alongside, in
+    // microseconds since the epoch.
+    pub fn writetime(&self, col_name: &str) -> Option<i64> {
+        match self.get_column(&format!("writetime({})", col_name)) {
+            Some(Value::CqlBigint(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    // The result of `ttl(col_name)` selected alongside; None too for a
+    // value written without a TTL.
+    pub fn ttl(&self, col_name: &str) -> Option<i32> {
+        match self.get_column(&format!("ttl({})", col_name)) {
+            Some(Value::CqlInt(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn take(mut self, col_name: &str) -> Option<Value> {
+        self.metadata
+            .column_index(col_name)
+            .map(|i| self.cols.swap_remove(i))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rows {
+    metadata: Arc<Metadata>,
+    rows: Vec<Row>,
+}
+
+impl Rows {
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    // Whether a conditional (IF ...) statement was applied, from the
+    // `[applied]` column of its single row; None for any other result. When
+    // false, the row also holds the current values of the columns in the
+    // condition (every column for IF NOT EXISTS), read as usual with
+    // `rows()[0].get(name)`. A failed IF EXISTS has none to add.
+    pub fn applied(&self) -> Option<bool> {
+        match self.rows.first()?.get_column("[applied]") {
+            Some(Value::CqlBoolean(applied)) => Some(applied),
+            _ => None,
+        }
+    }
+
+    // The named column of each row in turn. A missing column gives a single
+    // Error::ColumnNotFound.
+    pub fn column_iter<'a, T: FromCqlValue + 'a>(
+        &'a self,
+        name: &str,
+    ) -> impl Iterator<Item = Result<T>> + 'a {
+        let index = self.metadata.column_index(name);
+        let missing = match index {
+            Some(_) => None,
+            None => Some(Err(Error::ColumnNotFound(name.to_owned()))),
+        };
+        let values = self
+            .rows
+            .iter()
+            .filter_map(move |row| index.map(|i| T::from_cql_value(row.cols[i].clone())));
+        missing.into_iter().chain(values)
+    }
+
+    // The rows with only the named columns, in the order named. A name
+    // that isn't a column fails with Error::ColumnNotFound.
+    pub fn project(&self, names: &[&str]) -> Result<Rows> {
+        let indexes = names
+            .iter()
+            .map(|name| {
+                self.metadata
+                    .column_index(name)
+                    .ok_or_else(|| Error::ColumnNotFound((*name).to_owned()))
+            })
+            .collect::<Result<Vec<usize>>>()?;
+
+        let row_metadata: Vec<CqlColMetadata> = indexes
+            .iter()
+            .map(|&i| self.metadata.row_metadata[i].clone())
+            .collect();
+        let metadata = Arc::new(Metadata {
+            flags: self.metadata.flags,
+            column_count: row_metadata.len() as u32,
+            keyspace: self.metadata.keyspace.clone(),
+            table: self.metadata.table.clone(),
+            paging_state: self.metadata.paging_state.clone(),
+            column_indexes: index_columns(&row_metadata),
+            row_metadata,
+        });
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| Row {
+                cols: indexes.iter().map(|&i| row.cols[i].clone()).collect(),
+                metadata: metadata.clone(),
+            })
+            .collect();
+        Ok(Rows { metadata, rows })
+    }
+
+    // Heap footprint of the rows, with the metadata they share counted once.
+    pub fn heap_size(&self) -> usize {
+        use std::mem::size_of;
+
+        let rows = self.rows.iter().map(|row| {
+            row.cols.capacity() * size_of::<Value>()
+                + row.cols.iter().map(|col| col.heap_size()).sum::<usize>()
+        });
+        self.rows.capacity() * size_of::<Row>()
+            + rows.sum::<usize>()
+            + size_of::<Metadata>()
+            + self.metadata.heap_size()
+    }
+
+    fn validate_strict(&self) -> Result<()> {
+        self.rows
+            .iter()
+            .flat_map(|row| row.cols.iter())
+            .try_for_each(|col| col.validate_strict())
+    }
+}
+
+struct BodyStartup {
+    body: StringMap,
+}
+impl CqlSerializable for BodyStartup {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        self.body.serialize(buf)
+    }
+
+    fn len_(&self) -> usize {
+        self.body.len_()
+    }
+}
+
+// The consistency and options of a QUERY or EXECUTE, built with
+// QueryParamsBuilder for `Client::query_with` and `execute_with`.
+#[derive(Debug)]
+pub struct QueryParams {
+    con: Consistency,
+    params: Vec<Value>,
+    // Bind marker names, one per value, for `:name` markers.
+    names: Option<Vec<String>>,
+    skip_metadata: bool,
+    page_size: Option<i32>,
+    paging_state: Option<Vec<u8>>,
+    serial_consistency: Option<Consistency>,
+    timestamp: Option<i64>,
+}
+impl QueryParams {
+    fn new(con: Consistency, params: Vec<Value>) -> Self {
+        QueryParams {
+            con,
+            params,
+            names: None,
+            skip_metadata: false,
+            page_size: None,
+            paging_state: None,
+            serial_consistency: None,
+            timestamp: None,
+        }
+    }
+
+    // Each option that is set has its bit.
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if !self.params.is_empty() {
+            flags |= 0x01;
+        }
+        if self.skip_metadata {
+            flags |= 0x02;
+        }
+        if self.page_size.is_some() {
+            flags |= 0x04;
+        }
+        if self.paging_state.is_some() {
+            flags |= 0x08;
+        }
+        if self.serial_consistency.is_some() {
+            flags |= 0x10;
+        }
+        if self.timestamp.is_some() {
+            flags |= 0x20;
+        }
+        if self.names.is_some() && !self.params.is_empty() {
+            flags |= 0x40;
+        }
+        flags
+    }
+}
+impl CqlSerializable for QueryParams {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        buf.write_u16::<BigEndian>(self.con.clone() as u16)?;
+        buf.write_u8(self.flags())?;
+
+        if !self.params.is_empty() {
+            buf.write_u16::<BigEndian>(short_len(self.params.len())?)?;
+            for (i, v) in self.params.iter().enumerate() {
+                if let Some(ref names) = self.names {
+                    ShortString(&names[i]).serialize(buf)?;
+                }
+                v.serialize(buf)?;
+            }
+        }
+        if let Some(page_size) = self.page_size {
+            buf.write_i32::<BigEndian>(page_size)?;
+        }
+        if let Some(ref paging_state) = self.paging_state {
+            buf.write_i32::<BigEndian>(int_len(paging_state.len())?)?;
+            buf.write_all(paging_state)?;
+        }
+        if let Some(ref serial_consistency) = self.serial_consistency {
+            buf.write_u16::<BigEndian>(serial_consistency.clone() as u16)?;
+        }
+        if let Some(timestamp) = self.timestamp {
+            buf.write_i64::<BigEndian>(timestamp)?;
+        }
+        Ok(())
+    }
+    fn len_(&self) -> usize {
+        let values_len = if self.params.is_empty() {
+            0
+        } else {
+            2 + self.params.iter().map(|v| v.len_()).sum::<usize>()
+        };
+        let names_len = self.names.as_ref().map_or(0, |names| {
+            names.iter().map(|name| ShortString(name).len_()).sum()
+        });
+        let page_size_len = if self.page_size.is_some() { 4 } else { 0 };
+        let paging_state_len = self.paging_state.as_ref().map_or(0, |s| 4 + s.len());
+        let serial_consistency_len = if self.serial_consistency.is_some() {
+            2
+        } else {
+            0
+        };
+        let timestamp_len = if self.timestamp.is_some() { 8 } else { 0 };
+        3 + values_len
+            + names_len
+            + page_size_len
+            + paging_state_len
+            + serial_consistency_len
+            + timestamp_len
+    }
+}
+
+pub struct QueryParamsBuilder {
+    params: QueryParams,
+}
+
+impl QueryParamsBuilder {
+    pub fn new(con: Consistency) -> QueryParamsBuilder {
+        QueryParamsBuilder {
+            params: QueryParams::new(con, Vec::new()),
+        }
+    }
+
+    pub fn values(mut self, values: Vec<Value>) -> QueryParamsBuilder {
+        self.params.params = values;
+        self.params.names = None;
+        self
+    }
+
+    // Fails with Error::DuplicateName if a name is given twice.
+    pub fn named_values(mut self, values: NamedValues) -> Result<QueryParamsBuilder> {
+        let (names, values) = values.split()?;
+        self.params.params = values;
+        self.params.names = Some(names);
+        Ok(self)
+    }
+
+    // Asks for rows without their column metadata, which `execute_with`
+    // takes from the prepared statement instead. `query_with` ignores it.
+    pub fn skip_metadata(mut self, skip: bool) -> QueryParamsBuilder {
+        self.params.skip_metadata = skip;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: i32) -> QueryParamsBuilder {
+        self.params.page_size = Some(page_size);
+        self
+    }
+
+    pub fn paging_state(mut self, paging_state: Vec<u8>) -> QueryParamsBuilder {
+        self.params.paging_state = Some(paging_state);
+        self
+    }
+
+    // The consistency of the Paxos phase of a conditional update. Not how
+    // to make a serial read: that passes Serial as the consistency itself.
+    pub fn serial_consistency(mut self, con: Consistency) -> QueryParamsBuilder {
+        self.params.serial_consistency = Some(con);
+        self
+    }
+
+    // In microseconds since the epoch, overriding the client's timestamp
+    // generator.
+    pub fn timestamp(mut self, timestamp: i64) -> QueryParamsBuilder {
+        self.params.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn build(self) -> QueryParams {
+        self.params
+    }
+}
+
+// Values bound to `:name` markers by name, for `Client::query_named` and
+// `execute_named`. A marker used several times in the query takes the
+// one value.
+#[derive(Clone, Debug, Default)]
+pub struct NamedValues(pub Vec<(String, Value)>);
+
+impl NamedValues {
+    // The names and values apart, failing with Error::DuplicateName if a
+    // name is given twice.
+    fn split(self) -> Result<(Vec<String>, Vec<Value>)> {
+        let mut seen = HashSet::with_capacity(self.0.len());
+        let mut names = Vec::with_capacity(self.0.len());
+        let mut values = Vec::with_capacity(self.0.len());
+        for (name, value) in self.0 {
+            if !seen.insert(name.clone()) {
+                return Err(Error::DuplicateName(name));
+            }
+            names.push(name);
+            values.push(value);
+        }
+        Ok((names, values))
+    }
+}
+
+struct BodyQuery {
+    query: String,
+    params: QueryParams,
+}
+impl CqlSerializable for BodyQuery {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        LongString(&self.query).serialize(buf)?;
+        self.params.serialize(buf)
+    }
+
+    fn len_(&self) -> usize {
+        LongString(&self.query).len_() + self.params.len_()
+    }
+}
+
+struct BodyExecute {
+    id: Vec<u8>,
+    params: QueryParams,
+}
+impl CqlSerializable for BodyExecute {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        buf.write_u16::<BigEndian>(short_len(self.id.len())?)?;
+        buf.write_all(&self.id)?;
+        self.params.serialize(buf)
+    }
+
+    fn len_(&self) -> usize {
+        2 + self.id.len() + self.params.len_()
+    }
+}
+
+struct BodyPrepare {
+    query: String,
+}
+impl CqlSerializable for BodyPrepare {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        LongString(&self.query).serialize(buf)
+    }
+
+    fn len_(&self) -> usize {
+        LongString(&self.query).len_()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchType {
+    Logged = 0x00,
+    Unlogged = 0x01,
+    Counter = 0x02,
+}
+
+// A statement of a batch with its bind values.
+#[derive(Clone, Debug)]
+pub enum BatchQuery {
+    Query(String, Vec<Value>),
+    Prepared(Vec<u8>, Vec<Value>),
+}
+
+impl CqlSerializable for BatchQuery {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        let values = match *self {
+            BatchQuery::Query(ref query, ref values) => {
+                buf.write_u8(0)?;
+                LongString(query).serialize(buf)?;
+                values
+            }
+            BatchQuery::Prepared(ref id, ref values) => {
+                buf.write_u8(1)?;
+                buf.write_u16::<BigEndian>(short_len(id.len())?)?;
+                buf.write_all(id)?;
+                values
+            }
+        };
+        buf.write_u16::<BigEndian>(short_len(values.len())?)?;
+        for v in values {
+            v.serialize(buf)?;
+        }
+        Ok(())
+    }
+
+    fn len_(&self) -> usize {
+        let (len, values) = match *self {
+            BatchQuery::Query(ref query, ref values) => (LongString(query).len_(), values),
+            BatchQuery::Prepared(ref id, ref values) => (2 + id.len(), values),
+        };
+        1 + len + 2 + values.iter().map(|v| v.len_()).sum::<usize>()
+    }
+}
+
+struct BodyBatch {
+    batch_type: BatchType,
+    queries: Vec<BatchQuery>,
+    con: Consistency,
+    timestamp: Option<i64>,
+}
+impl CqlSerializable for BodyBatch {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        buf.write_u8(self.batch_type as u8)?;
+        buf.write_u16::<BigEndian>(short_len(self.queries.len())?)?;
+        for query in &self.queries {
+            query.serialize(buf)?;
+        }
+        buf.write_u16::<BigEndian>(self.con.clone() as u16)?;
+        // Unlike QUERY, the flags have no bit for values, which each
+        // statement carries itself.
+        let flags = if self.timestamp.is_some() { 0x20 } else { 0 };
+        buf.write_u8(flags)?;
+        if let Some(timestamp) = self.timestamp {
+            buf.write_i64::<BigEndian>(timestamp)?;
+        }
+        Ok(())
+    }
+
+    fn len_(&self) -> usize {
+        let timestamp_len = if self.timestamp.is_some() { 8 } else { 0 };
+        1 + 2 + self.queries.iter().map(|q| q.len_()).sum::<usize>() + 3 + timestamp_len
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    TopologyChange,
+    StatusChange,
+    SchemaChange,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            EventType::TopologyChange => "TOPOLOGY_CHANGE",
+            EventType::StatusChange => "STATUS_CHANGE",
+            EventType::SchemaChange => "SCHEMA_CHANGE",
+        }
+    }
+}
+
+struct BodyRegister {
+    events: Vec<EventType>,
+}
+impl CqlSerializable for BodyRegister {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        buf.write_u16::<BigEndian>(self.events.len() as u16)?;
+        for event in &self.events {
+            ShortString(event.as_str()).serialize(buf)?;
+        }
+        Ok(())
+    }
+
+    fn len_(&self) -> usize {
+        2 + self
+            .events
+            .iter()
+            .map(|event| ShortString(event.as_str()).len_())
+            .sum::<usize>()
+    }
+}
+
+struct BodyAuthResponse {
+    token: Vec<u8>,
+}
+impl CqlSerializable for BodyAuthResponse {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        buf.write_i32::<BigEndian>(int_len(self.token.len())?)?;
+        buf.write_all(&self.token)?;
+        Ok(())
+    }
+
+    fn len_(&self) -> usize {
+        4 + self.token.len()
+    }
+}
+
+struct BodyEmpty;
+impl CqlSerializable for BodyEmpty {
+    fn serialize<T: io::Write>(&self, _buf: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn len_(&self) -> usize {
+        0
+    }
+}
+
+type StringMultiMap = Vec<(String, Vec<String>)>;
+
+// The change type, target, keyspace, name and argument types of a schema
+// change, as read by `read_cql_schema_change`.
+type SchemaChangeFields = (String, String, String, Option<String>, Vec<String>);
+
+#[derive(Clone, Debug)]
+pub enum ErrorDetail {
+    None,
+    Unavailable {
+        consistency: Consistency,
+        required: i32,
+        alive: i32,
+    },
+    WriteTimeout {
+        consistency: Consistency,
+        received: i32,
+        block_for: i32,
+        write_type: String,
+    },
+    ReadTimeout {
+        consistency: Consistency,
+        received: i32,
+        block_for: i32,
+        data_present: bool,
+    },
+    ReadFailure {
+        consistency: Consistency,
+        received: i32,
+        block_for: i32,
+        num_failures: i32,
+        data_present: bool,
+    },
+    FunctionFailure {
+        keyspace: String,
+        function: String,
+        arg_types: Vec<String>,
+    },
+    WriteFailure {
+        consistency: Consistency,
+        received: i32,
+        block_for: i32,
+        num_failures: i32,
+        write_type: String,
+    },
+    // `table` is None when it is the keyspace itself that already exists.
+    AlreadyExists {
+        keyspace: String,
+        table: Option<String>,
+    },
+    // The id of the statement the node doesn't have prepared.
+    Unprepared {
+        id: Vec<u8>,
+    },
+}
+
+// Boxing the rarer PREPARED metadata would change the public variants.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum ResponseBody {
+    Error(u32, String, ErrorDetail),
+    Ready,
+    Auth(String),
+    Supported(StringMultiMap),
+    Result(ResponseResult),
+    AuthChallenge(Option<Vec<u8>>),
+    AuthSuccess(Option<Vec<u8>>),
+    Event(ServerEvent),
+}
+
+// What the server pushes to connections that registered for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerEvent {
+    TopologyChange {
+        change: TopologyChangeType,
+        node: SocketAddr,
+    },
+    StatusChange {
+        change: StatusChangeType,
+        node: SocketAddr,
+    },
+    SchemaChange(SchemaChange),
+    // An event type newer than this client, or a change newer than it
+    // within a known one, by event type.
+    Other(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopologyChangeType {
+    NewNode,
+    RemovedNode,
+    MovedNode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusChangeType {
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaChangeType {
+    Created,
+    Updated,
+    Dropped,
+}
+
+// `target` is KEYSPACE, TABLE, TYPE, FUNCTION or AGGREGATE as sent, and
+// `name` the changed object unless it's the keyspace. `arg_types` are the
+// argument types of a function or aggregate, and empty otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaChange {
+    pub change: SchemaChangeType,
+    pub target: String,
+    pub keyspace: String,
+    pub name: Option<String>,
+    pub arg_types: Vec<String>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum ResponseResult {
+    Void,
+    Rows(Rows),
+    Keyspace(String),
+    // The id, then the metadata of the bind markers and of the result rows.
+    Prepared(Vec<u8>, Metadata, Metadata),
+    // The change type, target, keyspace, name and argument types, as in
+    // `SchemaChange`.
+    SchemaChange(String, String, String, Option<String>, Vec<String>),
+}
+
+impl ResponseResult {
+    pub fn rows(self) -> Option<Rows> {
+        match self {
+            ResponseResult::Rows(rows) => Some(rows),
+            _ => None,
+        }
+    }
+
+    pub fn as_rows(&self) -> Option<&Rows> {
+        match *self {
+            ResponseResult::Rows(ref rows) => Some(rows),
+            _ => None,
+        }
+    }
+
+    // The outcome of a conditional (IF ...) statement: whether it was
+    // applied, and the row it answered with when that has columns besides
+    // `[applied]`, i.e. the current values when the condition failed.
+    pub fn was_applied(self) -> Result<(bool, Option<Row>)> {
+        let rows = self.rows();
+        let applied = rows.as_ref().and_then(Rows::applied);
+        let applied = applied.ok_or_else(|| Error::ColumnNotFound("[applied]".to_owned()))?;
+        let row = rows
+            .and_then(|rows| rows.rows.into_iter().next())
+            .filter(|row| row.cols.len() > 1);
+        Ok((applied, row))
+    }
+
+    pub fn into_prepared(self) -> Option<PreparedStatement> {
+        match self {
+            ResponseResult::Prepared(id, metadata, result_metadata) => Some(PreparedStatement {
+                id,
+                metadata,
+                result_metadata,
+                query: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// A statement prepared on the server, with the metadata of its bind markers.
+#[derive(Clone, Debug)]
+pub struct PreparedStatement {
+    id: Vec<u8>,
+    metadata: Metadata,
+    result_metadata: Metadata,
+    // Known when prepared through `Client::prepare`.
+    query: Option<String>,
+}
+
+impl PreparedStatement {
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn result_metadata(&self) -> &Metadata {
+        &self.result_metadata
+    }
+
+    // Columns of the rows the statement returns; none unless it's a SELECT.
+    pub fn result_columns(&self) -> &[CqlColMetadata] {
+        self.result_metadata.columns()
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    // Only statements with result columns return rows; they are the ones
+    // the client's default page size applies to.
+    fn returns_rows(&self) -> bool {
+        self.result_metadata.column_count() > 0
+    }
+
+    // Prepares the query again on `client`'s node. There's no cheaper way to
+    // ask a node whether it still knows an id, and preparing a known query
+    // is a lookup on the server. The id changes if the schema did, so this is
+    // also the way to pick up a new one, e.g. after a rolling restart.
+    pub fn ensure_valid(&mut self, client: &mut Client) -> Result<()> {
+        let query = match self.query {
+            Some(ref query) => query.clone(),
+            None => return Err(Error::Protocol),
+        };
+        let fresh = client.prepare(&query)?;
+        if fresh.id != self.id {
+            debug!("prepared id of {:?} changed", query);
+        }
+        *self = fresh;
+        Ok(())
+    }
+}
+
+// Bit of `FrameHeader::flags` set when the body is compressed.
+pub const COMPRESSED: u8 = 0x01;
+// Set on a request to have it traced, and on the response to one.
+pub const TRACING: u8 = 0x02;
+// v4 only: the body starts with a custom payload, or with warnings.
+pub const CUSTOM_PAYLOAD: u8 = 0x04;
+pub const WARNING: u8 = 0x08;
+
+// The protocol's limit on a frame body, compressed or not.
+#[cfg(feature = "lz4")]
+const MAX_FRAME_BODY: usize = 256 * 1024 * 1024;
+
+// Frame body compression, as named in STARTUP's COMPRESSION option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    // Algorithms this build can use, in order of preference.
+    fn available() -> &'static [Compression] {
+        &[
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ]
+    }
+
+    fn from_name(name: &str) -> Option<Compression> {
+        Compression::available()
+            .iter()
+            .find(|c| c.name() == Some(name))
+            .cloned()
+    }
+
+    // The name in STARTUP's COMPRESSION option and OPTIONS' answer.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some("lz4"),
+        }
+    }
+
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn compress(self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => body.to_vec(),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut data = Vec::with_capacity(4 + body.len());
+                data.extend_from_slice(&(body.len() as i32).to_be_bytes());
+                data.extend(lz4_flex::block::compress(body));
+                data
+            }
+        }
+    }
+
+    // A compressed body, then its uncompressed size as an [int] followed by
+    // an LZ4 block in the case of lz4.
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn decompress(self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            // The server only compresses once an algorithm is agreed on.
+            Compression::None => Err(Error::Protocol),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut reader = body;
+                // Checked before it's allocated, as it's up to the server.
+                let len = reader.read_i32::<BigEndian>()?;
+                if len < 0 || len as usize > MAX_FRAME_BODY {
+                    return Err(Error::Protocol);
+                }
+                let data = lz4_flex::block::decompress(reader, len as usize)
+                    .map_err(|_| Error::Protocol)?;
+                if data.len() != len as usize {
+                    return Err(Error::Protocol);
+                }
+                Ok(data)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameHeader {
+    version: u8,
+    flags: u8,
+    stream: i16,
+    opcode: Opcode,
+}
+impl FrameHeader {
+    fn new(stream: i16, opcode: Opcode) -> Self {
+        Self {
+            version: CQL_VERSION,
+            flags: 0x00,
+            stream,
+            opcode,
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    // The version without the response direction bit.
+    pub fn protocol_version(&self) -> u8 {
+        self.version & 0x7f
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn stream(&self) -> i16 {
+        self.stream
+    }
+}
+
+#[derive(Debug)]
+struct Request<B: CqlSerializable> {
+    header: FrameHeader,
+    body: B,
+}
+
+impl<B: CqlSerializable> CqlSerializable for Request<B> {
+    fn serialize<T: io::Write>(&self, buf: &mut T) -> Result<()> {
+        let header = &self.header;
+        buf.write_u8(header.version)?;
+        buf.write_u8(header.flags)?;
+        buf.write_i16::<BigEndian>(header.stream)?;
+        buf.write_u8(header.opcode as u8)?;
+
+        let len = u32::try_from(self.body.len_()).map_err(|_| Error::Protocol)?;
+        buf.write_u32::<BigEndian>(len)?;
+        self.body.serialize(buf)?;
+        Ok(())
+    }
+
+    fn len_(&self) -> usize {
+        self.body.len_() + 9
+    }
+}
+
+// A frame as read off the wire, before its body is decoded.
+#[derive(Clone, Debug)]
+pub struct RawFrame {
+    header: FrameHeader,
+    body: Vec<u8>,
+}
+
+impl RawFrame {
+    pub fn header(&self) -> &FrameHeader {
+        &self.header
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn decode(&self) -> Result<Response> {
+        self.decode_with(None)
+    }
+
+    fn decode_with(&self, result_metadata: Option<&Metadata>) -> Result<Response> {
+        decode_response(self.header.clone(), &self.body, result_metadata)
+    }
+
+    // Replaces a compressed body with the original, clearing COMPRESSED.
+    fn decompress(&mut self, compression: Compression) -> Result<()> {
+        if self.header.flags & COMPRESSED != 0 {
+            self.body = compression.decompress(&self.body)?;
+            self.header.flags &= !COMPRESSED;
+        }
+        Ok(())
+    }
+}
+
+// Receives the frames a Client reads while waiting for the response to
+// another stream: server events, pushed on stream -1, and responses to
+// requests the client already gave up on.
+pub trait OrphanFrameHandler: Send {
+    fn handle_orphan(&mut self, frame: RawFrame);
+
+    fn next_event(&mut self) -> Option<RawFrame> {
+        None
+    }
+}
+
+// The default handler: keeps events until they are asked for and drops
+// everything else.
+#[derive(Debug, Default)]
+pub struct EventBuffer {
+    events: VecDeque<RawFrame>,
+}
+
+impl OrphanFrameHandler for EventBuffer {
+    fn handle_orphan(&mut self, frame: RawFrame) {
+        if frame.header.stream == -1 {
+            self.events.push_back(frame);
+        } else {
+            debug!("discarding late response on stream {}", frame.header.stream);
+        }
+    }
+
+    fn next_event(&mut self) -> Option<RawFrame> {
+        self.events.pop_front()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    header: FrameHeader,
+    tracing_id: Option<[u8; 16]>,
+    warnings: Vec<String>,
+    body: ResponseBody,
+}
+
+impl Response {
+    pub fn header(&self) -> &FrameHeader {
+        &self.header
+    }
+
+    pub fn body(&self) -> &ResponseBody {
+        &self.body
+    }
+
+    // The session the server traced the request in, if it was asked to.
+    pub fn tracing_id(&self) -> Option<[u8; 16]> {
+        self.tracing_id
+    }
+
+    // Warnings the server attached to a v4 response.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn into_body(self) -> ResponseBody {
+        self.body
+    }
+
+    // The response itself, unless it is an ERROR, which becomes Error::Cql.
+    fn into_ok(self) -> Result<Response> {
+        match self.body {
+            ResponseBody::Error(code, message, details) => Err(Error::Cql {
+                code,
+                message,
+                details,
+            }),
+            _ => Ok(self),
+        }
+    }
+
+    fn into_result(self) -> Result<ResponseResult> {
+        match self.into_ok()?.body {
+            ResponseBody::Result(res) => Ok(res),
+            _ => Err(Error::Protocol),
+        }
+    }
+}
+
+// The options the client needs, changed by `overrides` in turn: each sets
+// its key, replacing any earlier value, or with None leaves it out.
+fn startup(
+    throw_on_overload: bool,
+    compression: Compression,
+    overrides: &[(String, Option<String>)],
+) -> Request<BodyStartup> {
+    let mut body = StringMap {
+        pairs: vec![Pair {
+            key: "CQL_VERSION".to_owned(),
+            value: "3.0.0".to_owned(),
+        }],
+    };
+    if throw_on_overload {
+        body.pairs.push(Pair {
+            key: "THROW_ON_OVERLOAD".to_owned(),
+            value: "1".to_owned(),
+        });
+    }
+    if let Some(name) = compression.name() {
+        body.pairs.push(Pair {
+            key: "COMPRESSION".to_owned(),
+            value: name.to_owned(),
+        });
+    }
+    for (key, value) in overrides {
+        body.pairs.retain(|pair| pair.key != *key);
+        if let Some(value) = value {
+            body.pairs.push(Pair {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    Request {
+        header: FrameHeader::new(1, Opcode::Startup),
+        body: BodyStartup { body },
+    }
+}
+
+// The SASL PLAIN token PasswordAuthenticator expects.
+fn auth_response(stream: i16, user: &str, password: &str) -> Request<BodyAuthResponse> {
+    let mut token = Vec::with_capacity(2 + user.len() + password.len());
+    token.push(0);
+    token.extend_from_slice(user.as_bytes());
+    token.push(0);
+    token.extend_from_slice(password.as_bytes());
+    Request {
+        header: FrameHeader::new(stream, Opcode::AuthResponse),
+        body: BodyAuthResponse { token },
+    }
+}
+
+fn options() -> Request<BodyEmpty> {
+    Request {
+        header: FrameHeader::new(1, Opcode::Opts),
+        body: BodyEmpty,
+    }
+}
+
+fn query(stream: i16, query_str: &str, con: Consistency, params: Vec<Value>) -> Request<BodyQuery> {
+    query_with(stream, query_str, QueryParams::new(con, params))
+}
+
+fn query_with(stream: i16, query_str: &str, params: QueryParams) -> Request<BodyQuery> {
+    Request {
+        header: FrameHeader::new(stream, Opcode::Query),
+        body: BodyQuery {
+            query: query_str.to_owned(),
+            params,
+        },
+    }
+}
+
+fn prepare(stream: i16, query_str: &str) -> Request<BodyPrepare> {
+    Request {
+        header: FrameHeader::new(stream, Opcode::Prepare),
+        body: BodyPrepare {
+            query: query_str.to_owned(),
+        },
+    }
+}
+
+fn execute(stream: i16, id: Vec<u8>, con: Consistency, params: Vec<Value>) -> Request<BodyExecute> {
+    execute_with(stream, id, QueryParams::new(con, params))
+}
+
+fn execute_with(stream: i16, id: Vec<u8>, params: QueryParams) -> Request<BodyExecute> {
+    Request {
+        header: FrameHeader::new(stream, Opcode::Execute),
+        body: BodyExecute { id, params },
+    }
+}
+
+fn batch(
+    stream: i16,
+    batch_type: BatchType,
+    queries: Vec<BatchQuery>,
+    con: Consistency,
+) -> Request<BodyBatch> {
+    Request {
+        header: FrameHeader::new(stream, Opcode::Batch),
+        body: BodyBatch {
+            batch_type,
+            queries,
+            con,
+            timestamp: None,
+        },
+    }
+}
+
+fn register(stream: i16, events: &[EventType]) -> Request<BodyRegister> {
+    Request {
+        header: FrameHeader::new(stream, Opcode::Register),
+        body: BodyRegister {
+            events: events.to_vec(),
+        },
+    }
+}
+
+// Supplies the default timestamp, in microseconds since the epoch, sent with
+// each QUERY and EXECUTE.
+pub trait TimestampGenerator: Send {
+    fn next_timestamp(&mut self) -> i64;
+}
+
+// Follows the system clock but never hands out the same or an earlier
+// timestamp twice, so writes from one connection keep their order.
+#[derive(Debug, Default)]
+pub struct MonotonicTimestampGenerator {
+    last: i64,
+}
+
+impl MonotonicTimestampGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next_after(&mut self, now: i64) -> i64 {
+        self.last = if now > self.last { now } else { self.last + 1 };
+        self.last
+    }
+}
+
+impl TimestampGenerator for MonotonicTimestampGenerator {
+    fn next_timestamp(&mut self) -> i64 {
+        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64 * 1_000_000 + i64::from(d.subsec_micros()),
+            Err(e) => {
+                -(e.duration().as_secs() as i64 * 1_000_000
+                    + i64::from(e.duration().subsec_micros()))
+            }
+        };
+        self.next_after(now)
+    }
+}
+
+pub const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
+
+// Stream ids a v3 connection can have in flight.
+pub const MAX_STREAMS: usize = 32768;
+
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+// Prepared queries a client remembers, to prepare again when a node has
+// forgotten them. The oldest are forgotten first.
+pub const MAX_PREPARED_QUERIES: usize = 1024;
+
+pub struct ClientBuilder {
+    addr: String,
+    buffer_capacity: usize,
+    tcp_keepalive: Option<Duration>,
+    protocol_version: Option<u8>,
+    timestamp_generator: Option<Box<dyn TimestampGenerator>>,
+    strict: bool,
+    check_bind_count: bool,
+    default_consistency: Consistency,
+    throw_on_overload: bool,
+    startup_options: Vec<(String, Option<String>)>,
+    nonstandard_startup: bool,
+    credentials: Option<(String, String)>,
+    orphan_handler: Box<dyn OrphanFrameHandler>,
+    cache_prepared: bool,
+    max_in_flight: usize,
+    block_in_flight: bool,
+    compress: bool,
+    compression_threshold: usize,
+    default_page_size: Option<i32>,
+}
+
+impl ClientBuilder {
+    pub fn new(addr: &str) -> ClientBuilder {
+        ClientBuilder {
+            addr: addr.to_owned(),
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            tcp_keepalive: None,
+            protocol_version: None,
+            timestamp_generator: None,
+            strict: false,
+            check_bind_count: false,
+            default_consistency: Consistency::One,
+            throw_on_overload: false,
+            startup_options: Vec::new(),
+            nonstandard_startup: false,
+            credentials: None,
+            orphan_handler: Box::new(EventBuffer::default()),
+            cache_prepared: false,
+            max_in_flight: MAX_STREAMS,
+            block_in_flight: true,
+            compress: false,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            default_page_size: None,
+        }
+    }
+
+    pub fn buffer_capacity(mut self, capacity: usize) -> ClientBuilder {
+        self.buffer_capacity = capacity;
+        self
+    }
+
+    // Has the OS probe the connection once it's idle this long, and again
+    // as often until the server answers, so one through a NAT or firewall
+    // stays open and a dead server is noticed. Off by default; unlike
+    // `Client::enable_keepalive` nothing reaches the server itself. The OS
+    // counts whole seconds, so `idle` is rounded up to one.
+    pub fn tcp_keepalive(mut self, idle: Option<Duration>) -> ClientBuilder {
+        self.tcp_keepalive = idle.map(|idle| {
+            let secs = idle.as_secs() + u64::from(idle.subsec_nanos() > 0);
+            Duration::from_secs(secs.max(1))
+        });
+        self
+    }
+
+    // Speaks only this version. Left unset, v4 is tried first and v3 used
+    // if the server refuses it.
+    pub fn protocol_version(mut self, version: u8) -> ClientBuilder {
+        self.protocol_version = Some(version);
+        self
+    }
+
+    pub fn timestamp_generator<G>(mut self, generator: G) -> ClientBuilder
+    where
+        G: TimestampGenerator + 'static,
+    {
+        self.timestamp_generator = Some(Box::new(generator));
+        self
+    }
+
+    // Rejects results holding null set elements or map keys with
+    // Error::Protocol instead of passing them on.
+    pub fn strict(mut self, strict: bool) -> ClientBuilder {
+        self.strict = strict;
+        self
+    }
+
+    // Makes `Client::query` count the `?` markers of each query and fail
+    // with Error::BindCount, before sending anything, unless there are as
+    // many values.
+    pub fn check_bind_count(mut self, check: bool) -> ClientBuilder {
+        self.check_bind_count = check;
+        self
+    }
+
+    // The level of `Client::query_default` and `execute_default`.
+    pub fn default_consistency(mut self, con: Consistency) -> ClientBuilder {
+        self.default_consistency = con;
+        self
+    }
+
+    // Asks the server to fail requests with an Overloaded error instead of
+    // queueing them when it can't keep up.
+    pub fn throw_on_overload(mut self, enabled: bool) -> ClientBuilder {
+        self.throw_on_overload = enabled;
+        self
+    }
+
+    // Sends `key` in STARTUP with `value`, replacing the client's own value
+    // for it, if any. The client compresses with whatever COMPRESSION is
+    // sent, which must be an algorithm it has, failing the connect with
+    // Error::UnsupportedCompression otherwise.
+    pub fn startup_option(mut self, key: &str, value: &str) -> ClientBuilder {
+        self.startup_options
+            .push((key.to_owned(), Some(value.to_owned())));
+        self
+    }
+
+    // Leaves `key` out of STARTUP. Leaving out CQL_VERSION, which the
+    // protocol requires, fails with Error::MissingStartupOption unless
+    // `nonstandard_startup` is set.
+    pub fn remove_startup_option(mut self, key: &str) -> ClientBuilder {
+        self.startup_options.push((key.to_owned(), None));
+        self
+    }
+
+    // Allows a STARTUP without CQL_VERSION, for proxies that expect one.
+    pub fn nonstandard_startup(mut self, enabled: bool) -> ClientBuilder {
+        self.nonstandard_startup = enabled;
+        self
+    }
+
+    // Logs in with these if the server asks for authentication, as
+    // PasswordAuthenticator does.
+    pub fn credentials(mut self, user: &str, password: &str) -> ClientBuilder {
+        self.credentials = Some((user.to_owned(), password.to_owned()));
+        self
+    }
+
+    // Makes `Client::prepare_and_execute` keep the ids it prepares, so
+    // running the same query again takes a single round trip.
+    pub fn cache_prepared(mut self, enabled: bool) -> ClientBuilder {
+        self.cache_prepared = enabled;
+        self
+    }
+
+    // Caps the requests awaiting a response, as servers bound them per
+    // connection. At the cap a new request first reads responses off the
+    // socket until one finishes, or with `block` unset fails with
+    // Error::InFlightLimit.
+    pub fn max_in_flight(mut self, limit: usize, block: bool) -> ClientBuilder {
+        self.max_in_flight = limit.clamp(1, MAX_STREAMS);
+        self.block_in_flight = block;
+        self
+    }
+
+    // Asks the server, with OPTIONS, which compression it supports and
+    // picks the first this build has (lz4 with the lz4 feature). Requests
+    // from then on are compressed, and the server compresses its responses.
+    pub fn compression(mut self, enabled: bool) -> ClientBuilder {
+        self.compress = enabled;
+        self
+    }
+
+    // Request bodies shorter than this go uncompressed.
+    pub fn compression_threshold(mut self, bytes: usize) -> ClientBuilder {
+        self.compression_threshold = bytes;
+        self
+    }
+
+    // The page size `Client::query` and `execute` send with a SELECT, so it
+    // can't return an unbounded result by accident. Other statements don't
+    // return rows and are sent without one. `query_rows` and the helpers
+    // built on it fetch every page. Without a default no page size is sent
+    // and the server returns every row.
+    pub fn default_page_size(mut self, page_size: i32) -> ClientBuilder {
+        self.default_page_size = Some(page_size);
+        self
+    }
+
+    pub fn orphan_frame_handler<H>(mut self, handler: H) -> ClientBuilder
+    where
+        H: OrphanFrameHandler + 'static,
+    {
+        self.orphan_handler = Box::new(handler);
+        self
+    }
+
+    pub fn connect(self) -> Result<Client> {
+        let cql_version = self
+            .startup_options
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "CQL_VERSION");
+        if let Some((key, None)) = cql_version {
+            if !self.nonstandard_startup {
+                return Err(Error::MissingStartupOption(key.clone()));
+            }
+        }
+        let compression = self
+            .startup_options
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "COMPRESSION");
+        if let Some((_, Some(name))) = compression {
+            if Compression::from_name(name).is_none() {
+                return Err(Error::UnsupportedCompression(name.clone()));
+            }
+        }
+
+        let options = ConnectOptions {
+            addr: self.addr,
+            buffer_capacity: self.buffer_capacity,
+            tcp_keepalive: self.tcp_keepalive,
+            compress: self.compress,
+            throw_on_overload: self.throw_on_overload,
+            startup_options: self.startup_options,
+            credentials: self.credentials,
+        };
+        let socket = options.open_socket()?;
+        let mut client = Client {
+            reader: BufReader::with_capacity(self.buffer_capacity, socket.try_clone()?),
+            writer: BufWriter::with_capacity(self.buffer_capacity, socket),
+            options,
+            queries: HashMap::new(),
+            query_ids: VecDeque::new(),
+            registered: Vec::new(),
+            version: self.protocol_version.unwrap_or(CQL_VERSION_V4),
+            timestamp_generator: self.timestamp_generator,
+            hexdump: false,
+            strict: self.strict,
+            check_bind_count: self.check_bind_count,
+            default_consistency: self.default_consistency,
+            orphan_handler: self.orphan_handler,
+            keepalive: None,
+            last_activity: Instant::now(),
+            next_stream: 0,
+            in_flight: HashSet::new(),
+            received: HashMap::new(),
+            max_in_flight: self.max_in_flight,
+            block_in_flight: self.block_in_flight,
+            compression: Compression::None,
+            compression_threshold: self.compression_threshold,
+            default_page_size: self.default_page_size,
+            prepared: if self.cache_prepared {
+                Some(HashMap::new())
+            } else {
+                None
+            },
+        };
+
+        let response = match client.handshake() {
+            Err(ref e) if self.protocol_version.is_none() && refuses_version(e) => {
+                // The server hangs up after refusing a version.
+                client.version = CQL_VERSION;
+                client.reopen()?;
+                client.handshake()
+            }
+            response => response,
+        }?;
+        client.start(response)?;
+        Ok(client)
+    }
+}
+
+// What the client needs to open its connection again.
+struct ConnectOptions {
+    addr: String,
+    buffer_capacity: usize,
+    tcp_keepalive: Option<Duration>,
+    compress: bool,
+    throw_on_overload: bool,
+    startup_options: Vec<(String, Option<String>)>,
+    credentials: Option<(String, String)>,
+}
+
+impl ConnectOptions {
+    fn open_socket(&self) -> Result<TcpStream> {
+        let socket = TcpStream::connect(self.addr.as_str())?;
+        if let Some(idle) = self.tcp_keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                windows
+            ))]
+            let keepalive = keepalive.with_interval(idle);
+            socket2::SockRef::from(&socket).set_tcp_keepalive(&keepalive)?;
+        }
+        Ok(socket)
+    }
+}
+
+// A PROTOCOL_ERROR, or a response in a lower version, is how servers
+// refuse a protocol version they don't speak.
+fn refuses_version(e: &Error) -> bool {
+    match *e {
+        Error::Cql { code, .. } => code == 0x000A,
+        Error::ProtocolVersion { expected, actual } => actual < expected,
+        _ => false,
+    }
+}
+
+fn unpaged(result: ResponseResult) -> Result<Option<Rows>> {
+    match result.rows() {
+        Some(ref rows) if rows.metadata.paging_state.is_some() => Err(Error::MorePages),
+        rows => Ok(rows),
+    }
+}
+
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    version: u8,
+    timestamp_generator: Option<Box<dyn TimestampGenerator>>,
+    hexdump: bool,
+    strict: bool,
+    check_bind_count: bool,
+    default_consistency: Consistency,
+    orphan_handler: Box<dyn OrphanFrameHandler>,
+    keepalive: Option<Duration>,
+    last_activity: Instant,
+    next_stream: i16,
+    in_flight: HashSet<i16>,
+    received: HashMap<i16, RawFrame>,
+    max_in_flight: usize,
+    block_in_flight: bool,
+    compression: Compression,
+    compression_threshold: usize,
+    default_page_size: Option<i32>,
+    // Prepared ids by query, when caching is enabled.
+    prepared: Option<HashMap<String, Vec<u8>>>,
+    // The query of the last MAX_PREPARED_QUERIES ids prepared through this
+    // client, to prepare again when a node has forgotten one, and the ids
+    // oldest first.
+    queries: HashMap<Vec<u8>, String>,
+    query_ids: VecDeque<Vec<u8>>,
+    options: ConnectOptions,
+    // Sent again on reconnect, as registrations die with the connection.
+    registered: Vec<EventType>,
+}
+
+impl Client {
+    pub fn new(addr: &str) -> Result<Client> {
+        ClientBuilder::new(addr).connect()
+    }
+
+    pub fn with_credentials(addr: &str, user: &str, password: &str) -> Result<Client> {
+        ClientBuilder::new(addr)
+            .credentials(user, password)
+            .connect()
+    }
+
+    // SASL PLAIN: a single response with both credentials, which the server
+    // accepts with AUTH_SUCCESS or rejects with a BadCredentials error.
+    fn authenticate(&mut self, user: &str, password: &str) -> Result<()> {
+        match self.send(auth_response(0, user, password))?.into_ok()?.body {
+            ResponseBody::AuthSuccess(_) => Ok(()),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    pub fn options(&mut self) -> Result<Response> {
+        self.send(options())
+    }
+
+    // Waits for the next event from the server, returning any already
+    // received first. Needs a `register`ed connection and, with a custom
+    // orphan frame handler, one which keeps events.
+    pub fn poll_event(&mut self) -> Result<ServerEvent> {
+        loop {
+            let frame = match self.orphan_handler.next_event() {
+                Some(frame) => frame,
+                None => {
+                    self.writer.flush()?;
+                    let (header, length) = self.reader.read_cql_frame_header(self.version)?;
+                    if header.stream != -1 {
+                        let pending = self.in_flight.remove(&header.stream);
+                        self.keep_frame(header, length, pending)?;
+                        continue;
+                    }
+                    self.read_frame(header, length)?
+                }
+            };
+            return match frame.decode()?.body {
+                ResponseBody::Event(event) => Ok(event),
+                _ => Err(Error::Protocol),
+            };
+        }
+    }
+
+    // OPTIONS, if compression is wanted, then STARTUP.
+    fn handshake(&mut self) -> Result<Response> {
+        let compression = if self.options.compress {
+            self.negotiate_compression()?
+        } else {
+            Compression::None
+        };
+        let req = startup(
+            self.options.throw_on_overload,
+            compression,
+            &self.options.startup_options,
+        );
+        // The startup options may name another algorithm, or none, and the
+        // server goes by what was sent.
+        let compression = match req.body.body.pairs.iter().find(|p| p.key == "COMPRESSION") {
+            Some(pair) => Compression::from_name(&pair.value)
+                .ok_or_else(|| Error::UnsupportedCompression(pair.value.clone()))?,
+            None => Compression::None,
+        };
+        let response = self.send(req)?;
+        // Anything after STARTUP may be compressed.
+        self.compression = compression;
+        response.into_ok()
+    }
+
+    // Authenticates if the server answered STARTUP with AUTHENTICATE.
+    fn start(&mut self, response: Response) -> Result<()> {
+        match response.body {
+            ResponseBody::Ready => Ok(()),
+            ResponseBody::Auth(authenticator) => match self.options.credentials.clone() {
+                Some((user, password)) => self.authenticate(&user, &password),
+                None => Err(Error::AuthRequired(authenticator)),
+            },
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    // A fresh socket to the same address, with nothing in flight on it.
+    fn reopen(&mut self) -> Result<()> {
+        let _ = self.writer.get_ref().shutdown(Shutdown::Both);
+        let socket = self.options.open_socket()?;
+        let capacity = self.options.buffer_capacity;
+        self.reader = BufReader::with_capacity(capacity, socket.try_clone()?);
+        self.writer = BufWriter::with_capacity(capacity, socket);
+        self.next_stream = 0;
+        self.in_flight.clear();
+        self.received.clear();
+        self.compression = Compression::None;
+        Ok(())
+    }
+
+    // Connects again at the protocol version already in use, going through
+    // STARTUP and authentication as before and registering for the same
+    // events.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.reopen()?;
+        let response = self.handshake()?;
+        self.start(response)?;
+        self.last_activity = Instant::now();
+        if !self.registered.is_empty() {
+            let events = self.registered.clone();
+            self.send_register(&events)?;
+        }
+        Ok(())
+    }
+
+    fn negotiate_compression(&mut self) -> Result<Compression> {
+        let supported = match self.options()?.into_ok()?.body {
+            ResponseBody::Supported(options) => options,
+            _ => return Err(Error::Protocol),
+        };
+        let names = supported
+            .iter()
+            .find(|(key, _)| key == "COMPRESSION")
+            .map_or(&[][..], |(_, names)| names.as_slice());
+        let compression = Compression::available()
+            .iter()
+            .find(|c| names.iter().any(|name| c.name() == Some(name.as_str())));
+        Ok(compression.cloned().unwrap_or(Compression::None))
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // The server acknowledges with READY; the events themselves are pushed
+    // later on stream -1.
+    // Registering for an event more than once is harmless.
+    pub fn register(&mut self, events: &[EventType]) -> Result<()> {
+        self.send_register(events)?;
+        for event in events {
+            if !self.registered.contains(event) {
+                self.registered.push(*event);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn registered_events(&self) -> &[EventType] {
+        &self.registered
+    }
+
+    fn send_register(&mut self, events: &[EventType]) -> Result<()> {
+        match self.send(register(0, events))?.into_ok()?.body {
+            ResponseBody::Ready => Ok(()),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    //TODO: signature
+    pub fn query(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Response> {
+        let page_size = self.default_page_size.filter(|_| is_select(query_str));
+        self.query_sized(query_str, con, values, page_size)
+    }
+
+    fn query_sized(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+        page_size: Option<i32>,
+    ) -> Result<Response> {
+        if con.is_serial() && is_write(query_str) {
+            warn!(
+                "{:?} used as the consistency of a write: {}",
+                con, query_str
+            );
+        }
+        if self.check_bind_count {
+            let expected = count_placeholders(query_str);
+            if expected != values.len() {
+                return Err(Error::BindCount {
+                    expected,
+                    actual: values.len(),
+                });
+            }
+        }
+        let mut req = query(0, query_str, con, values);
+        req.body.params.page_size = page_size;
+        req.body.params.timestamp = self.next_timestamp();
+        self.send(req)?.into_ok()
+    }
+
+    // A conditional (IF ...) write: `con` applies to the commit, and must
+    // not be serial, as the server rejects a serial consistency for a write.
+    // `serial` applies to its Paxos phase and must be Serial or LocalSerial.
+    // For a serial read, which sees the result of any in-progress Paxos
+    // round, call `query` with Serial as the consistency instead.
+    pub fn query_with_serial(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+        serial: Consistency,
+    ) -> Result<Response> {
+        if !serial.is_serial() {
+            warn!(
+                "{:?} used as the serial consistency of: {}",
+                serial, query_str
+            );
+        }
+        let params = QueryParamsBuilder::new(con)
+            .values(values)
+            .serial_consistency(serial)
+            .build();
+        self.query_with(query_str, params)
+    }
+
+    // Like `query`, with every option of the request set by `params`. The
+    // client's default page size and timestamp fill in for unset ones.
+    // `skip_metadata` is ignored, as there are no known columns to read the
+    // rows with.
+    pub fn query_with(&mut self, query_str: &str, mut params: QueryParams) -> Result<Response> {
+        params.skip_metadata = false;
+        if params.page_size.is_none() && is_select(query_str) {
+            params.page_size = self.default_page_size;
+        }
+        if params.timestamp.is_none() {
+            params.timestamp = self.next_timestamp();
+        }
+        self.send(query_with(0, query_str, params))?.into_ok()
+    }
+
+    pub fn execute_with(
+        &mut self,
+        statement: &PreparedStatement,
+        mut params: QueryParams,
+    ) -> Result<Response> {
+        if params.page_size.is_none() && statement.returns_rows() {
+            params.page_size = self.default_page_size;
+        }
+        if params.timestamp.is_none() {
+            params.timestamp = self.next_timestamp();
+        }
+        let result_metadata = Some(&statement.result_metadata).filter(|_| params.skip_metadata);
+        self.send_execute(
+            execute_with(0, statement.id.clone(), params),
+            result_metadata,
+        )
+    }
+
+    // Like `query`, binding values to `:name` markers by name.
+    pub fn query_named(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: NamedValues,
+    ) -> Result<Response> {
+        let (names, values) = values.split()?;
+        let mut req = query(0, query_str, con, values);
+        req.body.params.names = Some(names);
+        req.body.params.page_size = self.default_page_size.filter(|_| is_select(query_str));
+        req.body.params.timestamp = self.next_timestamp();
+        self.send(req)?.into_ok()
+    }
+
+    // Every row of the result. With a default page size, the pages after
+    // the first are fetched with its paging state until there are no more.
+    pub fn query_rows(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Option<Rows>> {
+        let page_size = match self.default_page_size {
+            Some(page_size) if is_select(query_str) => page_size,
+            _ => return Ok(self.query(query_str, con, values)?.into_result()?.rows()),
+        };
+        let first = self.query(query_str, con.clone(), values.clone())?;
+        let mut rows = match first.into_result()?.rows() {
+            Some(rows) => rows,
+            None => return Ok(None),
+        };
+        while let Some(paging_state) = rows.metadata.paging_state.clone() {
+            let (page, _) = self.query_paged(
+                query_str,
+                con.clone(),
+                values.clone(),
+                page_size,
+                Some(paging_state),
+            )?;
+            rows.rows.extend(page.rows);
+            rows.metadata = page.metadata;
+        }
+        Ok(Some(rows))
+    }
+
+    /// Runs `query_str` at the default consistency without bind values.
+    ///
+    /// ```no_run
+    /// # fn run() -> cql::Result<()> {
+    /// let mut client = cql::Client::new("localhost:9042")?;
+    /// let rows = client.rows("select * from system.local")?;
+    /// println!("{:?}", rows);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rows(&mut self, query_str: &str) -> Result<Option<Rows>> {
+        let con = self.default_consistency.clone();
+        self.query_rows(query_str, con, Vec::new())
+    }
+
+    pub fn query_default(&mut self, query_str: &str, values: Vec<Value>) -> Result<Response> {
+        let con = self.default_consistency.clone();
+        self.query(query_str, con, values)
+    }
+
+    pub fn execute_default(
+        &mut self,
+        statement: &PreparedStatement,
+        values: Vec<Value>,
+    ) -> Result<Response> {
+        let con = self.default_consistency.clone();
+        self.execute(statement, con, values)
+    }
+
+    pub fn default_consistency(&self) -> &Consistency {
+        &self.default_consistency
+    }
+
+    pub fn default_page_size(&self) -> Option<i32> {
+        self.default_page_size
+    }
+
+    // Fetches one page of at most `page_size` rows. The paging state
+    // returned with it, if there are more pages, is passed back to fetch the
+    // next one.
+    pub fn query_paged(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+        page_size: i32,
+        paging_state: Option<Vec<u8>>,
+    ) -> Result<(Rows, Option<Vec<u8>>)> {
+        let mut req = query(0, query_str, con, values);
+        req.body.params.page_size = Some(page_size);
+        req.body.params.paging_state = paging_state;
+        req.body.params.timestamp = self.next_timestamp();
+        match self.send(req)?.into_result()?.rows() {
+            Some(rows) => {
+                let paging_state = rows.metadata.paging_state.clone();
+                Ok((rows, paging_state))
+            }
+            None => Err(Error::Protocol),
+        }
+    }
+
+    // Like `query_rows`, but fails with Error::MorePages rather than return
+    // only the first page of the rows. No page size is sent, not even the
+    // default one, so this only happens if the server pages anyway.
+    pub fn query_unpaged(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Option<Rows>> {
+        let result = self
+            .query_sized(query_str, con, values, None)?
+            .into_result()?;
+        unpaged(result)
+    }
+
+    pub fn execute_unpaged(
+        &mut self,
+        statement: &PreparedStatement,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Option<Rows>> {
+        let result = self
+            .execute_id(statement.id.clone(), con, values, false)?
+            .into_result()?;
+        unpaged(result)
+    }
+
+    // Returns the first column of every row, e.g. the keys of a table.
+    pub fn query_column<T: FromCqlValue>(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Vec<T>> {
+        let rows = match self.query_rows(query_str, con, values)? {
+            Some(rows) => rows.rows,
+            None => return Ok(Vec::new()),
+        };
+        rows.into_iter()
+            .map(|row| match row.cols.into_iter().next() {
+                Some(col) => T::from_cql_value(col),
+                None => Err(Error::Protocol),
+            })
+            .collect()
+    }
+
+    // Like `query_rows`, but decodes rows straight off the socket as the
+    // iterator is advanced, fetching the following pages as needed.
+    pub fn query_stream(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<RowStream<'_>> {
+        RowStream::new(self, query_str, con, values)
+    }
+
+    // Like `query_stream`, but fetches and decodes a page at a time with
+    // `query_paged`.
+    pub fn query_iter(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<RowIterator<'_>> {
+        RowIterator::new(self, query_str, con, values, STREAM_PAGE_SIZE)
+    }
+
+    // Like `query_rows`, but leaves each column undecoded.
+    pub fn query_raw_bytes(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<RawRows> {
+        raw::query_raw_bytes(self, query_str, con, values)
+    }
+
+    pub fn prepare(&mut self, query_str: &str) -> Result<PreparedStatement> {
+        match self
+            .send(prepare(0, query_str))?
+            .into_result()?
+            .into_prepared()
+        {
+            Some(mut statement) => {
+                statement.query = Some(query_str.to_owned());
+                self.remember_query(statement.id.clone(), query_str);
+                Ok(statement)
+            }
+            None => Err(Error::Protocol),
+        }
+    }
+
+    // A forgotten id also leaves the prepared cache, which would otherwise
+    // hand out an id the client can't prepare again.
+    fn remember_query(&mut self, id: Vec<u8>, query_str: &str) {
+        if self
+            .queries
+            .insert(id.clone(), query_str.to_owned())
+            .is_some()
+        {
+            return;
+        }
+        self.query_ids.push_back(id);
+        if self.query_ids.len() <= MAX_PREPARED_QUERIES {
+            return;
+        }
+        let oldest = self.query_ids.pop_front().unwrap();
+        let query = self.queries.remove(&oldest).unwrap();
+        if let Some(ref mut prepared) = self.prepared {
+            if prepared.get(&query) == Some(&oldest) {
+                prepared.remove(&query);
+            }
+        }
+    }
+
+    // Runs a query with bound values as a prepared statement, preparing it
+    // first unless its id is cached.
+    pub fn prepare_and_execute(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<ResponseResult> {
+        let cached = self
+            .prepared
+            .as_ref()
+            .and_then(|prepared| prepared.get(query_str).cloned());
+        let id = match cached {
+            Some(id) => id,
+            None => {
+                let id = self.prepare(query_str)?.id;
+                if let Some(ref mut prepared) = self.prepared {
+                    prepared.insert(query_str.to_owned(), id.clone());
+                }
+                id
+            }
+        };
+        let select = is_select(query_str);
+        self.execute_id(id, con, values, select)?.into_result()
+    }
+
+    pub fn execute(
+        &mut self,
+        statement: &PreparedStatement,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<Response> {
+        self.execute_id(statement.id.clone(), con, values, statement.returns_rows())
+    }
+
+    // A conditional statement, with the outcome from
+    // `ResponseResult::was_applied`.
+    pub fn query_cas(
+        &mut self,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<(bool, Option<Row>)> {
+        self.query(query_str, con, values)?
+            .into_result()?
+            .was_applied()
+    }
+
+    pub fn execute_cas(
+        &mut self,
+        statement: &PreparedStatement,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<(bool, Option<Row>)> {
+        self.execute(statement, con, values)?
+            .into_result()?
+            .was_applied()
+    }
+
+    // `select` is whether the statement returns rows, and so gets the
+    // default page size.
+    fn execute_id(
+        &mut self,
+        id: Vec<u8>,
+        con: Consistency,
+        values: Vec<Value>,
+        select: bool,
+    ) -> Result<Response> {
+        let mut req = execute(0, id, con, values);
+        req.body.params.page_size = self.default_page_size.filter(|_| select);
+        req.body.params.timestamp = self.next_timestamp();
+        self.send_execute(req, None)
+    }
+
+    pub fn execute_named(
+        &mut self,
+        statement: &PreparedStatement,
+        con: Consistency,
+        values: NamedValues,
+    ) -> Result<Response> {
+        let (names, values) = values.split()?;
+        let mut req = execute(0, statement.id.clone(), con, values);
+        req.body.params.names = Some(names);
+        req.body.params.page_size = self.default_page_size.filter(|_| statement.returns_rows());
+        req.body.params.timestamp = self.next_timestamp();
+        self.send_execute(req, None)
+    }
+
+    // Sends an EXECUTE. A node that has forgotten the statement, e.g. after a
+    // restart, answers with an Unprepared error; if the client prepared it,
+    // it is prepared again and the EXECUTE retried once under the new id.
+    fn send_execute(
+        &mut self,
+        mut req: Request<BodyExecute>,
+        result_metadata: Option<&Metadata>,
+    ) -> Result<Response> {
+        let stream = self.send_frame(&req.to_vec()?)?;
+        let resp = self.recv_frame_with(stream, result_metadata)?;
+        let query = match resp.body {
+            ResponseBody::Error(_, _, ErrorDetail::Unprepared { ref id }) => {
+                self.queries.get(id).cloned()
+            }
+            _ => None,
+        };
+        let query = match query {
+            Some(query) => query,
+            None => return resp.into_ok(),
+        };
+
+        debug!("preparing {:?} again", query);
+        let id = self.prepare(&query)?.id;
+        if let Some(ref mut prepared) = self.prepared {
+            if let Some(cached) = prepared.get_mut(&query) {
+                *cached = id.clone();
+            }
+        }
+        req.body.id = id;
+        let stream = self.send_frame(&req.to_vec()?)?;
+        self.recv_frame_with(stream, result_metadata)?.into_ok()
+    }
+
+    // Runs the statements in one request. An empty batch fails with
+    // Error::EmptyBatch without sending anything.
+    pub fn batch(
+        &mut self,
+        batch_type: BatchType,
+        queries: Vec<BatchQuery>,
+        con: Consistency,
+    ) -> Result<ResponseResult> {
+        if queries.is_empty() {
+            return Err(Error::EmptyBatch);
+        }
+        let mut req = batch(0, batch_type, queries, con);
+        req.body.timestamp = self.next_timestamp();
+        self.send(req)?.into_result()
+    }
+
+    fn next_timestamp(&mut self) -> Option<i64> {
+        self.timestamp_generator
+            .as_mut()
+            .map(|generator| generator.next_timestamp())
+    }
+
+    pub fn protocol_version(&self) -> u8 {
+        self.version
+    }
+
+    // The client does not ping by itself: callers poll `should_ping` from
+    // their own loop and call `ping` when it returns true, which keeps idle
+    // connections from being dropped by load balancers.
+    pub fn enable_keepalive(&mut self, interval: Duration) {
+        self.keepalive = Some(interval);
+    }
+
+    pub fn should_ping(&self, now: Instant) -> bool {
+        match self.keepalive {
+            Some(interval) => now.saturating_duration_since(self.last_activity) >= interval,
+            None => false,
+        }
+    }
+
+    pub fn ping(&mut self) -> Result<()> {
+        match self.options()?.body {
+            ResponseBody::Supported(_) => Ok(()),
+            _ => Err(Error::Protocol),
+        }
+    }
+
+    // Logs every request and response frame as a hex dump at trace level.
+    pub fn set_hexdump(&mut self, enabled: bool) {
+        self.hexdump = enabled;
+    }
+
+    fn send<B: CqlSerializable>(&mut self, req: Request<B>) -> Result<Response> {
+        let stream = self.send_frame(&req.to_vec()?)?;
+        self.recv_frame(stream)
+    }
+
+    // Queues a serialized request frame on a fresh stream id, which is
+    // returned, without waiting for the response. The frame's own version
+    // and stream id are replaced, and its body compressed if compression
+    // was negotiated. Nothing reaches the socket before `flush`
+    // or the next receive, so several requests can go out in one write.
+    pub fn send_frame(&mut self, frame: &[u8]) -> Result<i16> {
+        if frame.len() < 9 {
+            return Err(Error::Protocol);
+        }
+        if self.in_flight.len() >= self.max_in_flight {
+            if !self.block_in_flight {
+                return Err(Error::InFlightLimit(self.max_in_flight));
+            }
+            self.writer.flush()?;
+            while self.in_flight.len() >= self.max_in_flight {
+                let (header, length) = self.reader.read_cql_frame_header(self.version)?;
+                let pending = self.in_flight.remove(&header.stream);
+                self.keep_frame(header, length, pending)?;
+            }
+        }
+        let stream = self.next_stream()?;
+        let mut data = frame.to_vec();
+        data[0] = self.version;
+        (&mut data[2..4]).write_i16::<BigEndian>(stream)?;
+        if self.compression != Compression::None
+            && data[1] & COMPRESSED == 0
+            && data.len() - 9 >= self.compression_threshold
+        {
+            let body = self.compression.compress(&data[9..]);
+            data.truncate(9);
+            data[1] |= COMPRESSED;
+            (&mut data[5..9]).write_u32::<BigEndian>(body.len() as u32)?;
+            data.extend(body);
+        }
+        if self.hexdump {
+            trace!("request:\n{}", debug::hexdump(&data));
+        }
+        self.writer.write_all(&data)?;
+        self.in_flight.insert(stream);
+        Ok(stream)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // Waits for the response on `stream`. Responses to other requests sent
+    // with `send_frame` are kept until they are asked for.
+    pub fn recv_frame(&mut self, stream: i16) -> Result<Response> {
+        self.recv_frame_with(stream, None)
+    }
+
+    fn recv_frame_with(
+        &mut self,
+        stream: i16,
+        result_metadata: Option<&Metadata>,
+    ) -> Result<Response> {
+        let frame = match self.received.remove(&stream) {
+            Some(frame) => frame,
+            None => {
+                let (header, length) = self.recv_header(stream)?;
+                self.read_frame(header, length)?
+            }
+        };
+        let resp = frame.decode_with(result_metadata)?;
+        if self.strict {
+            if let ResponseBody::Result(ResponseResult::Rows(ref rows)) = resp.body {
+                rows.validate_strict()?;
+            }
+        }
+        Ok(resp)
+    }
+
+    // Writes `req` and reads up to the header of its response, leaving the
+    // body on the socket.
+    fn send_header<B: CqlSerializable>(&mut self, req: Request<B>) -> Result<(FrameHeader, u32)> {
+        let stream = self.send_frame(&req.to_vec()?)?;
+        self.recv_header(stream)
+    }
+
+    fn recv_header(&mut self, stream: i16) -> Result<(FrameHeader, u32)> {
+        if !self.in_flight.contains(&stream) {
+            return Err(Error::Protocol);
+        }
+        self.writer.flush()?;
+
+        loop {
+            let (header, length) = self.reader.read_cql_frame_header(self.version)?;
+            let pending = self.in_flight.remove(&header.stream);
+            if header.stream == stream {
+                self.last_activity = Instant::now();
+                return Ok((header, length));
+            }
+            self.keep_frame(header, length, pending)?;
+        }
+    }
+
+    // Reads the body of a response nobody is waiting for yet, keeping it
+    // for `recv_frame` if its request is `pending`.
+    fn keep_frame(&mut self, header: FrameHeader, length: u32, pending: bool) -> Result<()> {
+        let frame = self.read_frame(header, length)?;
+        if pending {
+            self.received.insert(frame.header.stream, frame);
+        } else {
+            self.orphan_handler.handle_orphan(frame);
+        }
+        Ok(())
+    }
+
+    // Reads the body following `header`, decompressed.
+    fn read_frame(&mut self, header: FrameHeader, length: u32) -> Result<RawFrame> {
+        let mut frame = RawFrame {
+            header,
+            body: self.reader.read_bytes(length as usize)?,
+        };
+        frame.decompress(self.compression)?;
+        if self.hexdump {
+            trace!(
+                "response: {:?}\n{}",
+                frame.header,
+                debug::hexdump(&frame.body)
+            );
+        }
+        Ok(frame)
+    }
+
+    // A stream id neither awaiting a response nor holding one kept for
+    // `recv_frame`. Kept responses don't count against `max_in_flight`, so
+    // they can use up every id; that fails after one pass over them.
+    fn next_stream(&mut self) -> Result<i16> {
+        for _ in 0..MAX_STREAMS {
+            let stream = self.next_stream;
+            self.next_stream = stream.checked_add(1).unwrap_or(0);
+            if !self.in_flight.contains(&stream) && !self.received.contains_key(&stream) {
+                return Ok(stream);
+            }
+        }
+        Err(Error::InFlightLimit(MAX_STREAMS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    pub fn frame(stream: i16, opcode: Opcode, body: &[u8]) -> Vec<u8> {
+        let mut v = vec![0x80 | CQL_VERSION, 0];
+        v.write_i16::<BigEndian>(stream).unwrap();
+        v.write_u8(opcode as u8).unwrap();
+        v.write_u32::<BigEndian>(body.len() as u32).unwrap();
+        v.extend_from_slice(body);
+        v
+    }
+
+    pub fn frame_stream(frame: &[u8]) -> i16 {
+        (&frame[2..4]).read_i16::<BigEndian>().unwrap()
+    }
+
+    // Answers a request in any version but v3 as a v3-only server does,
+    // with a PROTOCOL_ERROR, after which the connection should be dropped.
+    pub fn refuse_version(socket: &mut TcpStream, req: &[u8]) -> bool {
+        if req[0] == CQL_VERSION {
+            return false;
+        }
+        let mut body = vec![0, 0, 0, 0x0A];
+        ShortString("Invalid or unsupported protocol version")
+            .serialize(&mut body)
+            .unwrap();
+        socket
+            .write_all(&frame(frame_stream(req), Opcode::Error, &body))
+            .unwrap();
+        true
+    }
+
+    // Serves `connections` connections one after another, answering STARTUP
+    // with READY and every other request frame with whatever `handler`
+    // returns. Joining the handle yields all request frames received, once the
+    // last client hangs up.
+    pub fn mock_server<F>(
+        connections: usize,
+        handler: F,
+    ) -> (String, thread::JoinHandle<Vec<Vec<u8>>>)
+    where
+        F: FnMut(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        mock_server_on("127.0.0.1:0", connections, handler)
+    }
+
+    pub fn mock_server_on<F>(
+        addr: &str,
+        connections: usize,
+        mut handler: F,
+    ) -> (String, thread::JoinHandle<Vec<Vec<u8>>>)
+    where
+        F: FnMut(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut frames = Vec::new();
+            let mut served = 0;
+            while served < connections {
+                let (mut socket, _) = listener.accept().unwrap();
+                served += 1;
+                while let Ok(mut req) = socket.read_bytes(9) {
+                    let len = (&req[5..9]).read_u32::<BigEndian>().unwrap();
+                    req.extend(socket.read_bytes(len as usize).unwrap());
+                    if refuse_version(&mut socket, &req) {
+                        served -= 1;
+                        break;
+                    }
+
+                    let resp = if req[4] == Opcode::Startup as u8 {
+                        frame(frame_stream(&req), Opcode::Ready, &[])
+                    } else {
+                        handler(&req)
+                    };
+                    socket.write_all(&resp).unwrap();
+                    frames.push(req);
+                }
+            }
+            frames
+        });
+        (addr, handle)
+    }
+
+    const RESULT_VOID: &[u8] = &[0, 0, 0, 1];
+
+    // A statement with `id` and no bind markers or result columns.
+    pub fn statement(id: Vec<u8>) -> PreparedStatement {
+        let metadata = Metadata {
+            flags: 0,
+            column_count: 0,
+            keyspace: None,
+            table: None,
+            paging_state: None,
+            row_metadata: Vec::new(),
+            column_indexes: HashMap::new(),
+        };
+        PreparedStatement {
+            id,
+            metadata: metadata.clone(),
+            result_metadata: metadata,
+            query: None,
+        }
+    }
+
+    // Replays a recorded response frame on the stream of `req`.
+    pub fn respond(req: &[u8], recorded: &[u8]) -> Vec<u8> {
+        let mut resp = recorded.to_vec();
+        resp[2..4].copy_from_slice(&req[2..4]);
+        resp
+    }
+
+    #[test]
+    fn test_parse_varint() {
+        assert_eq!(0, parse_varint(&[0]).unwrap());
+        assert_eq!(1, parse_varint(&[1]).unwrap());
+        assert_eq!(127, parse_varint(&[0x7f]).unwrap());
+        assert_eq!(128, parse_varint(&[0x00, 0x80]).unwrap());
+        assert_eq!(129, parse_varint(&[0x00, 0x81]).unwrap());
+
+        assert_eq!(-1, parse_varint(&[0xff]).unwrap());
+        assert_eq!(-128, parse_varint(&[0x80]).unwrap());
+        assert_eq!(-129, parse_varint(&[0xff, 0x7f]).unwrap());
+    }
+
+    #[test]
+    fn varint_too_long() {
+        let nine = [0x00, 0x80, 0, 0, 0, 0, 0, 0, 0];
+        let ten = [0xff; 10];
+        for bytes in [&nine[..], &ten[..], &[][..]].iter() {
+            match parse_varint(bytes) {
+                Err(Error::Protocol) => (),
+                res => panic!("unexpected result: {:?}", res),
+            }
+
+            let mut varint = (bytes.len() as i32).to_be_bytes().to_vec();
+            varint.extend_from_slice(bytes);
+            match varint
+                .as_slice()
+                .read_cql_col(&CqlColDescr::Single(ColumnType::VarInt))
+            {
+                Err(Error::Protocol) => (),
+                v => panic!("unexpected value: {:?}", v),
+            }
+
+            let mut decimal = (4 + bytes.len() as i32).to_be_bytes().to_vec();
+            decimal.extend_from_slice(&[0, 0, 0, 2]);
+            decimal.extend_from_slice(bytes);
+            match decimal
+                .as_slice()
+                .read_cql_col(&CqlColDescr::Single(ColumnType::Decimal))
+            {
+                Err(Error::Protocol) => (),
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_varint() {
+        let cases: &[(i64, &[u8])] = &[
+            (0, &[0]),
+            (1, &[1]),
+            (127, &[0x7f]),
+            (128, &[0x00, 0x80]),
+            (-1, &[0xff]),
+            (-128, &[0x80]),
+            (-129, &[0xff, 0x7f]),
+            (i64::MAX, &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            (i64::MIN, &[0x80, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+        for &(v, bytes) in cases {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, v).unwrap();
+            assert_eq!(bytes, buf.as_slice(), "{}", v);
+            assert_eq!(bytes.len(), varint_len(v));
+            assert_eq!(v, parse_varint(&buf).unwrap());
+        }
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        // xorshift64, so failures reproduce.
+        let mut x = 0x2545_f491_4f6c_dd1d_u64;
+        for i in 0..10_000 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            // Spread the values over every encoded length.
+            let v = (x as i64) >> (i % 64);
+            let mut buf = Vec::new();
+            write_varint(&mut buf, v).unwrap();
+            assert_eq!(varint_len(v), buf.len());
+            assert_eq!(v, parse_varint(&buf).unwrap(), "{:?}", buf);
+            // No shorter form reads back the same.
+            if buf.len() > 1 {
+                assert_ne!(v, parse_varint(&buf[1..]).unwrap(), "{:?}", buf);
+            }
+        }
+
+        let value = Value::CqlVarInt(-129);
+        assert_eq!(
+            &[0, 0, 0, 2, 0xff, 0x7f],
+            value.to_vec().unwrap().as_slice()
+        );
+        match value
+            .to_vec()
+            .unwrap()
+            .as_slice()
+            .read_cql_col(&CqlColDescr::Single(ColumnType::VarInt))
+        {
+            Ok(Value::CqlVarInt(-129)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn decimal_round_trip() {
+        let ty = CqlColDescr::Single(ColumnType::Decimal);
+        for &(scale, unscaled) in &[(2, 12345), (0, 0), (3, -1), (-1, -129), (10, i64::MAX)] {
+            let bytes = Value::CqlDecimal(scale, unscaled).to_vec().unwrap();
+            assert_eq!(bytes.len(), 4 + 4 + varint_len(unscaled));
+            match bytes.as_slice().read_cql_col(&ty) {
+                Ok(Value::CqlDecimal(s, u)) => assert_eq!((scale, unscaled), (s, u)),
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+        // 1.23 as sent by the server.
+        let bytes = Value::CqlDecimal(2, 123).to_vec().unwrap();
+        assert_eq!(&[0, 0, 0, 5, 0, 0, 0, 2, 123], bytes.as_slice());
+
+        // Too short for the scale, then no unscaled value after it.
+        for &bad in &[&[0, 0, 0, 2, 0, 0][..], &[0, 0, 0, 4, 0, 0, 0, 2]] {
+            let mut bad = bad;
+            match bad.read_cql_col(&ty) {
+                Err(Error::Protocol) => (),
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn resp_ready() {
+        let v = vec![131, 0, 0, 1, 2, 0, 0, 0, 0];
+        let resp = v.as_slice().read_cql_response(CQL_VERSION);
+        assert!(resp.is_ok())
+    }
+
+    #[test]
+    fn resp_error() {
+        let v = vec![
+            131, 0, 0, 0, 0, 0, 0, 0, 49, 0, 0, 36, 0, 0, 35, 67, 97, 110, 110, 111, 116, 32, 97,
+            100, 100, 32, 101, 120, 105, 115, 116, 105, 110, 103, 32, 107, 101, 121, 115, 112, 97,
+            99, 101, 32, 34, 114, 117, 115, 116, 34, 0, 4, 114, 117, 115, 116, 0, 0,
+        ];
+        let resp = v.as_slice().read_cql_response(CQL_VERSION);
+        assert!(resp.is_ok());
+        match resp.unwrap().body {
+            ResponseBody::Error(0x2400, _, ErrorDetail::AlreadyExists { keyspace, table }) => {
+                assert_eq!("rust", keyspace);
+                assert_eq!(None, table);
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    const ERROR_UNPREPARED: &[u8] = &[
+        131, 0, 0, 0, 0, 0, 0, 0, 89, 0, 0, 37, 0, 0, 65, 80, 114, 101, 112, 97, 114, 101, 100, 32,
+        113, 117, 101, 114, 121, 32, 119, 105, 116, 104, 32, 73, 68, 32, 97, 48, 97, 49, 97, 50,
+        97, 51, 97, 52, 97, 53, 97, 54, 97, 55, 97, 56, 97, 57, 97, 97, 97, 98, 97, 99, 97, 100,
+        97, 101, 97, 102, 32, 110, 111, 116, 32, 102, 111, 117, 110, 100, 0, 16, 160, 161, 162,
+        163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175,
+    ];
+
+    #[test]
+    fn resp_error_unprepared() {
+        let mut frame = ERROR_UNPREPARED;
+        let frame = frame.read_cql_frame(CQL_VERSION).unwrap();
+        let mut body = io::Cursor::new(frame.body());
+        match body
+            .read_cql_body(Opcode::Error, CQL_VERSION, None)
+            .unwrap()
+        {
+            ResponseBody::Error(0x2500, _, ErrorDetail::Unprepared { ref id }) => {
+                assert_eq!(&ERROR_UNPREPARED[82..], id.as_slice());
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+        assert_eq!(frame.body().len() as u64, body.position());
+    }
+
+    #[test]
+    fn resp_error_unavailable() {
+        let v = vec![
+            131, 0, 0, 0, 0, 0, 0, 0, 55, 0, 0, 16, 0, 0, 39, 67, 97, 110, 110, 111, 116, 32, 97,
+            99, 104, 105, 101, 118, 101, 32, 99, 111, 110, 115, 105, 115, 116, 101, 110, 99, 121,
+            32, 108, 101, 118, 101, 108, 32, 81, 85, 79, 82, 85, 77, 0, 4, 0, 0, 0, 3, 0, 0, 0, 1,
+        ];
+        let resp = v.as_slice().read_cql_response(CQL_VERSION).unwrap();
+        match resp.body {
+            ResponseBody::Error(
+                0x1000,
+                _,
+                ErrorDetail::Unavailable {
+                    consistency,
+                    required,
+                    alive,
+                },
+            ) => {
+                assert_eq!(Consistency::Quorum, consistency);
+                assert_eq!(3, required);
+                assert_eq!(1, alive);
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn resp_error_v4_failures() {
+        // WRITE_FAILURE at QUORUM: 1 received, 2 required, 1 failure, SIMPLE.
+        let mut body = vec![0, 0, 0x15, 0, 0, 4, b'f', b'a', b'i', b'l', 0, 4];
+        body.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 6]);
+        body.extend_from_slice(b"SIMPLE");
+        let mut reader = io::Cursor::new(body.as_slice());
+        match reader
+            .read_cql_body(Opcode::Error, CQL_VERSION_V4, None)
+            .unwrap()
+        {
+            ResponseBody::Error(
+                0x1500,
+                _,
+                ErrorDetail::WriteFailure {
+                    consistency: Consistency::Quorum,
+                    received: 1,
+                    block_for: 2,
+                    num_failures: 1,
+                    ref write_type,
+                },
+            ) => assert_eq!("SIMPLE", write_type),
+            body => panic!("unexpected body: {:?}", body),
+        }
+        assert_eq!(body.len() as u64, reader.position());
+
+        // FUNCTION_FAILURE in ks.f(int, text).
+        let mut body = vec![0, 0, 0x14, 0, 0, 0, 0, 2, b'k', b's', 0, 1, b'f', 0, 2];
+        body.extend_from_slice(&[0, 3, b'i', b'n', b't', 0, 4, b't', b'e', b'x', b't']);
+        let mut reader = io::Cursor::new(body.as_slice());
+        match reader
+            .read_cql_body(Opcode::Error, CQL_VERSION_V4, None)
+            .unwrap()
+        {
+            ResponseBody::Error(
+                0x1400,
+                _,
+                ErrorDetail::FunctionFailure {
+                    ref keyspace,
+                    ref function,
+                    ref arg_types,
+                },
+            ) => {
+                assert_eq!(("ks", "f"), (keyspace.as_str(), function.as_str()));
+                assert_eq!(vec!["int", "text"], *arg_types);
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+        assert_eq!(body.len() as u64, reader.position());
+    }
+
+    #[test]
+    fn client_query_error() {
+        // UNAVAILABLE at QUORUM with 3 required and 1 alive.
+        let body = [
+            0, 0, 0x10, 0, 0, 4, b'd', b'o', b'w', b'n', 0, 4, 0, 0, 0, 3, 0, 0, 0, 1,
+        ];
+        let (addr, server) =
+            mock_server(1, move |req| frame(frame_stream(req), Opcode::Error, &body));
+
+        let mut client = Client::new(&addr).unwrap();
+        let err = client
+            .query("select * from rust.test", Consistency::Quorum, Vec::new())
+            .unwrap_err();
+        assert_eq!(Some(ErrorCode::Unavailable), err.error_code());
+        match err {
+            Error::Cql {
+                message,
+                details:
+                    ErrorDetail::Unavailable {
+                        consistency: Consistency::Quorum,
+                        required: 3,
+                        alive: 1,
+                    },
+                ..
+            } => assert_eq!("down", message),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_consistency() {
+        assert_eq!(Consistency::Serial, consistency(0x0008));
+        assert_eq!(Consistency::LocalSerial, consistency(0x0009));
+        assert_eq!(Consistency::LocalOne, consistency(0x000A));
+        assert_eq!(Consistency::Unknown, consistency(0x000B));
+    }
+
+    #[test]
+    fn resp_schema_change() {
+        let v = vec![
+            131, 0, 0, 0, 8, 0, 0, 0, 29, 0, 0, 0, 5, 0, 7, 67, 82, 69, 65, 84, 69, 68, 0, 8, 75,
+            69, 89, 83, 80, 65, 67, 69, 0, 4, 114, 117, 115, 116,
+        ];
+        let resp = v.as_slice().read_cql_response(CQL_VERSION);
+        assert!(resp.is_ok())
+    }
+
+    #[test]
+    fn resp_schema_change_table() {
+        let v = vec![
+            131, 0, 0, 0, 8, 0, 0, 0, 32, 0, 0, 0, 5, 0, 7, 67, 82, 69, 65, 84, 69, 68, 0, 5, 84,
+            65, 66, 76, 69, 0, 4, 114, 117, 115, 116, 0, 4, 116, 101, 115, 116,
+        ];
+        let resp = v.as_slice().read_cql_response(CQL_VERSION);
+        assert!(resp.is_ok())
+    }
+
+    #[test]
+    fn resp_schema_change_function() {
+        let result = |body: &[u8]| {
+            let mut bytes = vec![0, 0, 0, 5];
+            bytes.extend_from_slice(body);
+            let mut frame = frame(0, Opcode::Result, &bytes);
+            frame[0] = 0x84;
+            match frame.as_slice().read_cql_response(4).unwrap().body {
+                ResponseBody::Result(ResponseResult::SchemaChange(
+                    change,
+                    target,
+                    keyspace,
+                    name,
+                    arg_types,
+                )) => (change, target, keyspace, name, arg_types),
+                body => panic!("unexpected body: {:?}", body),
+            }
+        };
+
+        let (change, target, keyspace, name, arg_types) = result(
+            b"\x00\x07CREATED\x00\x09AGGREGATE\x00\x04rust\x00\x06avg_of\x00\x01\x00\x09list<int>",
+        );
+        assert_eq!(("CREATED", "AGGREGATE"), (change.as_str(), target.as_str()));
+        assert_eq!("rust", keyspace);
+        assert_eq!(Some("avg_of".to_owned()), name);
+        assert_eq!(vec!["list<int>".to_owned()], arg_types);
+
+        // A function whose argument types are cut short.
+        let mut truncated = frame(
+            0,
+            Opcode::Result,
+            b"\x00\x00\x00\x05\x00\x07DROPPED\x00\x08FUNCTION\x00\x04rust\x00\x01f\x00\x01",
+        );
+        truncated[0] = 0x84;
+        assert!(truncated.as_slice().read_cql_response(4).is_err());
+    }
+
+    #[test]
+    fn resp_result_void() {
+        let v = vec![131, 0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0, 1];
+        let resp = v.as_slice().read_cql_response(CQL_VERSION);
+        assert!(resp.is_ok())
+    }
+
+    #[test]
+    fn resp_result_select() {
+        let v = vec![
             131, 0, 0, 0, 8, 0, 0, 0, 59, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 4, 114, 117, 115,
             116, 0, 4, 116, 101, 115, 116, 0, 2, 105, 100, 0, 13, 0, 5, 118, 97, 108, 117, 101, 0,
             8, 0, 0, 0, 1, 0, 0, 0, 4, 97, 115, 100, 102, 0, 0, 0, 4, 63, 158, 4, 25,
         ];
-        let resp = v.as_slice().read_cql_response();
-        assert!(resp.is_ok())
+        let resp = v.as_slice().read_cql_response(CQL_VERSION);
+        assert!(resp.is_ok())
+    }
+
+    // A first page of `select id, value from rust.test`, with flags 0x0003.
+    const RESULT_SELECT_PAGED: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 79, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 4, 1, 2, 3, 4,
+        0, 4, 114, 117, 115, 116, 0, 4, 116, 101, 115, 116, 0, 2, 105, 100, 0, 13, 0, 5, 118, 97,
+        108, 117, 101, 0, 8, 0, 0, 0, 2, 0, 0, 0, 4, 97, 115, 100, 102, 0, 0, 0, 4, 63, 192, 0, 0,
+        0, 0, 0, 4, 113, 119, 101, 114, 255, 255, 255, 255,
+    ];
+
+    #[test]
+    fn resp_result_select_paged() {
+        let rows = read_rows(RESULT_SELECT_PAGED);
+        let metadata = rows.metadata();
+        assert_eq!(GLOBAL_TABLE_SPEC | HAS_MORE_PAGES, metadata.flags());
+        assert_eq!(Some(&[1, 2, 3, 4][..]), metadata.paging_state());
+        assert_eq!(Some("rust"), metadata.keyspace());
+        assert_eq!(Some("test"), metadata.table());
+        let names: Vec<&str> = metadata.columns().iter().map(|c| c.name()).collect();
+        assert_eq!(vec!["id", "value"], names);
+
+        let values: Vec<String> = rows
+            .rows()
+            .iter()
+            .map(|row| format!("{:?}", row.clone().into_values()))
+            .collect();
+        assert_eq!(
+            vec![
+                "[CqlVarChar(\"asdf\"), CqlFloat(1.5)]",
+                "[CqlVarChar(\"qwer\"), CqlNull]",
+            ],
+            values
+        );
+    }
+
+    fn read_rows(mut frame: &[u8]) -> Rows {
+        let resp = frame.read_cql_response(CQL_VERSION).unwrap();
+        match resp.body {
+            ResponseBody::Result(ResponseResult::Rows(rows)) => rows,
+            body => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    const RESULT_SELECT: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 59, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 4, 114, 117, 115, 116,
+        0, 4, 116, 101, 115, 116, 0, 2, 105, 100, 0, 13, 0, 5, 118, 97, 108, 117, 101, 0, 8, 0, 0,
+        0, 1, 0, 0, 0, 4, 97, 115, 100, 102, 0, 0, 0, 4, 63, 158, 4, 25,
+    ];
+
+    #[test]
+    fn rows_clone() {
+        let rows = read_rows(RESULT_SELECT);
+
+        let cloned = rows.clone();
+        drop(rows);
+        assert_eq!(1, cloned.rows().len());
+        assert_eq!(Some("rust"), cloned.metadata().keyspace());
+        match cloned.rows()[0].get_column("id") {
+            Some(Value::CqlVarChar(ref s)) => assert_eq!("asdf", s),
+            col => panic!("unexpected column: {:?}", col),
+        }
+    }
+
+    #[test]
+    fn rows_send() {
+        let rows = read_rows(RESULT_SELECT);
+
+        let handle = std::thread::spawn(move || {
+            rows.rows()
+                .iter()
+                .filter_map(|row| row.get_column("value"))
+                .count()
+        });
+        assert_eq!(1, handle.join().unwrap());
+    }
+
+    fn round_trip(value: &Value, ty: ColumnType) -> Value {
+        let bytes = value.to_vec().unwrap();
+        assert_eq!(value.len_(), bytes.len());
+        let mut reader = bytes.as_slice();
+        let decoded = reader.read_cql_col(&CqlColDescr::Single(ty)).unwrap();
+        assert!(reader.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        let millis = [
+            0,
+            -1,
+            // 1969-07-20T20:17:40Z
+            -14_182_940_000,
+            // 9999-12-31T23:59:59Z
+            253_402_300_799_000,
+            i64::MIN,
+            i64::MAX,
+        ];
+        for &ms in millis.iter() {
+            let value = Value::timestamp_from_unix_millis(ms);
+            let decoded = round_trip(&value, ColumnType::Timestamp);
+            assert_eq!(Some(ms), decoded.to_unix_millis());
+        }
+        assert_eq!(None, Value::CqlBigint(1).to_unix_millis());
+    }
+
+    #[test]
+    fn value_encode_decode() {
+        let single = |ty| CqlColDescr::Single(ty);
+        let cases = vec![
+            (
+                single(ColumnType::Int),
+                Value::CqlInt(-2),
+                vec![255, 255, 255, 254],
+            ),
+            (
+                single(ColumnType::VarChar),
+                Value::from("ab"),
+                b"ab".to_vec(),
+            ),
+            (single(ColumnType::VarChar), Value::from(""), Vec::new()),
+            (
+                single(ColumnType::Boolean),
+                Value::CqlBoolean(true),
+                vec![1],
+            ),
+            (
+                single(ColumnType::VarInt),
+                Value::CqlVarInt(-129),
+                vec![255, 127],
+            ),
+            (
+                CqlColDescr::List(Box::new(single(ColumnType::Int))),
+                Value::CqlList(vec![Value::CqlInt(7)]),
+                vec![0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 7],
+            ),
+            (
+                CqlColDescr::Custom("org.example.Type".to_owned()),
+                Value::CqlCustom("org.example.Type".to_owned(), vec![1, 2]),
+                vec![1, 2],
+            ),
+        ];
+        for (ty, value, bytes) in cases {
+            assert_eq!(bytes, value.encode().unwrap(), "{:?}", value);
+            let decoded = Value::decode(&ty, &bytes).unwrap();
+            assert_eq!(format!("{:?}", value), format!("{:?}", decoded));
+        }
+
+        // Too short, too long, and null.
+        let int = single(ColumnType::Int);
+        match Value::decode(&int, &[0, 0, 1]) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let list = CqlColDescr::List(Box::new(single(ColumnType::Int)));
+        match Value::decode(&list, &[0, 0, 0, 0, 9]) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match Value::CqlNull.encode() {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        // Varints past 8 bytes, alone and as the unscaled part of a decimal.
+        let oversized = [0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        match Value::decode(&single(ColumnType::VarInt), &oversized) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let mut decimal = vec![0, 0, 0, 2];
+        decimal.extend_from_slice(&oversized[..9]);
+        match Value::decode(&single(ColumnType::Decimal), &decimal) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn v4_scalar_round_trip() {
+        for code in 0x0011..0x0016 {
+            let ty = column_type(code);
+            assert_eq!(code, ty as u16);
+            assert_ne!("unknown", ty.to_string());
+        }
+
+        let cases = vec![
+            (ColumnType::Date, Value::CqlDate(0), vec![0, 0, 0, 0]),
+            (
+                ColumnType::Date,
+                Value::CqlDate(1 << 31),
+                vec![128, 0, 0, 0],
+            ),
+            (
+                ColumnType::Time,
+                Value::CqlTime(1),
+                vec![0, 0, 0, 0, 0, 0, 0, 1],
+            ),
+            (ColumnType::SmallInt, Value::from(-2i16), vec![255, 254]),
+            (ColumnType::TinyInt, Value::from(-128i8), vec![128]),
+            (
+                ColumnType::Duration,
+                Value::duration(1, 2, 3),
+                vec![2, 4, 6],
+            ),
+            (
+                ColumnType::Duration,
+                Value::duration(-1, -2, -3),
+                vec![1, 3, 5],
+            ),
+        ];
+        for (ty, value, bytes) in cases {
+            assert_eq!(bytes, value.encode().unwrap(), "{:?}", value);
+            let decoded = round_trip(&value, ty);
+            assert_eq!(format!("{:?}", value), format!("{:?}", decoded));
+        }
+
+        let invalid: &[(ColumnType, &[u8])] = &[
+            (ColumnType::Date, &[0, 0, 0]),
+            // A day has fewer nanoseconds, and none are negative.
+            (ColumnType::Time, &[0, 0, 0x4e, 0x94, 0x91, 0x4f, 0, 0]),
+            (ColumnType::Time, &[255, 255, 255, 255, 255, 255, 255, 255]),
+            (ColumnType::SmallInt, &[0]),
+            (ColumnType::TinyInt, &[0, 0]),
+            // Months past i32, a vint cut short, and a byte left over.
+            (ColumnType::Duration, &[0xf8, 1, 0, 0, 0, 0, 0, 0]),
+            (ColumnType::Duration, &[0, 0, 0xc0, 0]),
+            (ColumnType::Duration, &[0, 0, 0, 0]),
+            // One month less a day.
+            (ColumnType::Duration, &[2, 1, 0]),
+        ];
+        for &(ty, bytes) in invalid {
+            match Value::decode(&CqlColDescr::Single(ty), bytes) {
+                Err(Error::Protocol) => (),
+                r => panic!("unexpected result for {} {:?}: {:?}", ty, bytes, r),
+            }
+        }
+    }
+
+    #[test]
+    fn vint_round_trip() {
+        let encoded = |v: i64| {
+            let mut buf = Vec::new();
+            write_vint(&mut buf, v).unwrap();
+            assert_eq!(vint_len(v), buf.len(), "{}", v);
+            buf
+        };
+        assert_eq!(vec![0], encoded(0));
+        assert_eq!(vec![1], encoded(-1));
+        assert_eq!(vec![0x7e], encoded(63));
+        assert_eq!(vec![0x80, 0x80], encoded(64));
+        assert_eq!(vec![0xff; 9], encoded(i64::MIN));
+
+        let mut values = vec![i64::MIN, i64::MAX, i64::from(i32::MIN), i64::from(i32::MAX)];
+        for bits in 0..63 {
+            let v = 1i64 << bits;
+            values.extend_from_slice(&[v - 1, v, -v, -v - 1]);
+        }
+        for v in values {
+            let bytes = encoded(v);
+            let mut reader = bytes.as_slice();
+            assert_eq!(v, read_vint(&mut reader).unwrap());
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn v4_scalar_conversions() {
+        assert_eq!(-3, i8::from_cql_value(Value::CqlTinyInt(-3)).unwrap());
+        assert_eq!(300, i16::from_cql_value(Value::CqlSmallInt(300)).unwrap());
+        match i16::from_cql_value(Value::CqlInt(300)) {
+            Err(Error::TypeMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let cases = vec![
+            (
+                Duration::from_millis(1500),
+                Value::duration(0, 0, 1_500_000_000),
+            ),
+            (
+                Duration::from_secs(3 * 86_400 + 1),
+                Value::duration(0, 0, 259_201_000_000_000),
+            ),
+            (
+                Duration::from_secs(u64::MAX),
+                Value::duration(0, 0, i64::MAX),
+            ),
+        ];
+        for (duration, expected) in cases {
+            let from = Value::from(duration);
+            assert_eq!(format!("{:?}", expected), format!("{:?}", from));
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_conversions() {
+        let id = uuid::Uuid::parse_str("123e4567-e89b-12d3-a456-426614174000").unwrap();
+        let value = Value::from(id);
+        assert_eq!("123e4567-e89b-12d3-a456-426614174000", value.to_string());
+        assert_eq!(id, uuid::Uuid::from_cql_value(value).unwrap());
+        let time = Value::CqlTimeUUID(*id.as_bytes());
+        assert_eq!(id, uuid::Uuid::from_cql_value(time).unwrap());
+        assert_eq!(
+            None,
+            Option::<uuid::Uuid>::from_cql_value(Value::CqlNull).unwrap()
+        );
+        match uuid::Uuid::from_cql_value(Value::CqlBlob(id.as_bytes().to_vec())) {
+            Err(Error::TypeMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_conversions() {
+        use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+
+        let time = Utc.with_ymd_and_hms(2023, 3, 1, 12, 34, 56).unwrap();
+        let value = Value::from(time + chrono::Duration::microseconds(789_999));
+        assert_eq!(Some(1_677_674_096_789), value.to_unix_millis());
+        let back = DateTime::<Utc>::from_cql_value(value).unwrap();
+        assert_eq!(time + chrono::Duration::milliseconds(789), back);
+
+        // Before the epoch, a partial millisecond still rounds towards the past.
+        let before = Utc.with_ymd_and_hms(1969, 7, 20, 20, 17, 40).unwrap();
+        let value = Value::from(before - chrono::Duration::microseconds(1));
+        assert_eq!(Some(-14_182_940_001), value.to_unix_millis());
+        assert_eq!(
+            before - chrono::Duration::milliseconds(1),
+            DateTime::<Utc>::from_cql_value(value).unwrap()
+        );
+        match DateTime::<Utc>::from_cql_value(Value::CqlTimestamp(i64::MAX)) {
+            Err(Error::TypeMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let dates = [
+            (NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(), 1 << 31),
+            (
+                NaiveDate::from_ymd_opt(2023, 3, 1).unwrap(),
+                (1 << 31) + 19_417,
+            ),
+            (
+                NaiveDate::from_ymd_opt(1969, 12, 31).unwrap(),
+                (1 << 31) - 1,
+            ),
+        ];
+        for &(date, days) in &dates {
+            let value = Value::from(date);
+            assert_eq!(
+                format!("{:?}", Value::CqlDate(days)),
+                format!("{:?}", value)
+            );
+            assert_eq!(date, NaiveDate::from_cql_value(value).unwrap());
+        }
+        match NaiveDate::from_cql_value(Value::CqlDate(0)) {
+            Err(Error::TypeMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let noon = NaiveTime::from_hms_nano_opt(12, 34, 56, 7).unwrap();
+        let value = Value::from(noon);
+        assert_eq!("'12:34:56.000000007'", value.to_string());
+        assert_eq!(noon, NaiveTime::from_cql_value(value).unwrap());
+        let leap = NaiveTime::from_hms_nano_opt(23, 59, 59, 1_500_000_000).unwrap();
+        assert_eq!("'23:59:59.999999999'", Value::from(leap).to_string());
+        match NaiveTime::from_cql_value(Value::CqlTime(NANOS_PER_DAY)) {
+            Err(Error::TypeMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_conversions() {
+        use std::net::Ipv4Addr;
+
+        let cases = vec![
+            (Value::CqlNull, "null"),
+            (Value::CqlTinyInt(-8), "-8"),
+            (Value::CqlSmallInt(300), "300"),
+            (Value::CqlInt(-70_000), "-70000"),
+            (Value::CqlBigint(1 << 40), "1099511627776"),
+            (Value::CqlBoolean(true), "true"),
+            (Value::CqlFloat(0.1), "0.1"),
+            (Value::CqlDouble(-2.5), "-2.5"),
+            (Value::CqlDouble(f64::NAN), "\"NaN\""),
+            (Value::CqlText("it's".to_string()), "\"it's\""),
+            (Value::CqlDecimal(2, -12345), "\"-123.45\""),
+            (Value::CqlDate(1 << 31), "\"1970-01-01\""),
+            (Value::CqlTime(3_723_000_000_001), "\"01:02:03.000000001\""),
+            (Value::duration(14, 3, 5_000_000_000), "\"1y2mo3d5s\""),
+            (Value::CqlTimestamp(0), "\"1970-01-01 00:00:00.000+0000\""),
+            (
+                Value::CqlInet(Ipv4Addr::new(10, 0, 0, 1).into()),
+                "\"10.0.0.1\"",
+            ),
+            (Value::CqlBlob(vec![0xca, 0xfe]), "\"0xcafe\""),
+            (
+                Value::CqlList(vec![Value::CqlInt(1), Value::CqlNull]),
+                "[1,null]",
+            ),
+            (
+                Value::CqlMap(vec![
+                    (Value::CqlText("a".to_string()), Value::CqlInt(1)),
+                    (Value::CqlInt(2), Value::CqlSmallInt(3)),
+                ]),
+                "{\"2\":3,\"a\":1}",
+            ),
+            (
+                Value::CqlUDT(vec![
+                    ("day".to_string(), Value::CqlDate(1 << 31)),
+                    ("n".to_string(), Value::CqlTinyInt(1)),
+                ]),
+                "{\"day\":\"1970-01-01\",\"n\":1}",
+            ),
+        ];
+        for (value, json) in cases {
+            assert_eq!(json, value.to_json().to_string(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn value_display() {
+        let uuid = [
+            0x12, 0x3e, 0x45, 0x67, 0xe8, 0x9b, 0x12, 0xd3, 0xa4, 0x56, 0x42, 0x66, 0x14, 0x17,
+            0x40, 0x00,
+        ];
+        let cases = vec![
+            (Value::CqlNull, "null"),
+            (Value::from("it's"), "'it''s'"),
+            (Value::from(vec![0xca, 0xfe]), "0xcafe"),
+            (Value::from(1.0f64), "1.0"),
+            (Value::from(1.2345f32), "1.2345"),
+            (Value::from(f64::NEG_INFINITY), "-Infinity"),
+            (Value::CqlDecimal(2, -12345), "-123.45"),
+            (Value::CqlDecimal(4, 5), "0.0005"),
+            (Value::CqlDecimal(-3, 12), "12E3"),
+            (Value::CqlDecimal(20, 1), "1E-20"),
+            (Value::CqlUUID(uuid), "123e4567-e89b-12d3-a456-426614174000"),
+            (
+                Value::CqlTimestamp(-14_182_940_000),
+                "'1969-07-20 20:17:40.000+0000'",
+            ),
+            (Value::CqlDate(1 << 31), "'1970-01-01'"),
+            (Value::CqlDate((1 << 31) + 19_417), "'2023-03-01'"),
+            (Value::CqlDate((1 << 31) - 1), "'1969-12-31'"),
+            (Value::CqlTime(45_296_000_000_007), "'12:34:56.000000007'"),
+            (
+                Value::duration(14, 3, 3_723_004_005_006),
+                "1y2mo3d1h2m3s4ms5us6ns",
+            ),
+            (Value::duration(0, -1, -1_000_000), "-1d1ms"),
+            (Value::duration(0, 0, 0), "0s"),
+            (Value::duration(1, -1, 0), "1mo-1d"),
+            (Value::duration(-14, 0, 1000), "-1y2mo1us"),
+            (
+                Value::CqlMap(vec![(
+                    Value::from(1),
+                    Value::CqlList(vec![Value::from(2i8)]),
+                )]),
+                "{1: [2]}",
+            ),
+            (
+                Value::CqlUDT(vec![("a".to_owned(), Value::CqlSet(Vec::new()))]),
+                "{a: {}}",
+            ),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(expected, value.to_string(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn float_round_trip() {
+        for &f in [1.2345f32, -0.0, f32::MIN_POSITIVE, f32::INFINITY].iter() {
+            match round_trip(&Value::CqlFloat(f), ColumnType::Float) {
+                Value::CqlFloat(decoded) => assert_eq!(f.to_bits(), decoded.to_bits()),
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+        for &f in [1.2345f64, -0.0, f64::MIN_POSITIVE, f64::NAN].iter() {
+            match round_trip(&Value::CqlDouble(f), ColumnType::Double) {
+                Value::CqlDouble(decoded) => assert_eq!(f.to_bits(), decoded.to_bits()),
+                v => panic!("unexpected value: {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn client_buffered_query() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .buffer_capacity(16)
+            .connect()
+            .unwrap();
+        for _ in 0..2 {
+            let resp = client
+                .query("select * from rust.test", Consistency::One, Vec::new())
+                .unwrap();
+            match resp.body {
+                ResponseBody::Result(ResponseResult::Void) => (),
+                body => panic!("unexpected body: {:?}", body),
+            }
+        }
+        drop(client);
+
+        let expected = |stream| {
+            query(
+                stream,
+                "select * from rust.test",
+                Consistency::One,
+                Vec::new(),
+            )
+            .to_vec()
+            .unwrap()
+        };
+        let frames = server.join().unwrap();
+        assert_eq!(3, frames.len());
+        assert_eq!(expected(1), frames[1]);
+        assert_eq!(expected(2), frames[2]);
+    }
+
+    #[test]
+    fn client_default_page_size() {
+        // The second SELECT, the first of `query_rows`, gets a page with
+        // more to come; every other one the last page.
+        let mut selects = 0;
+        let (addr, server) = mock_server(1, move |req| {
+            if !req.windows(6).any(|w| w == b"select") {
+                return frame(frame_stream(req), Opcode::Result, RESULT_VOID);
+            }
+            selects += 1;
+            match selects {
+                2 => respond(req, RESULT_SELECT_PAGED),
+                _ => respond(req, RESULT_SELECT),
+            }
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .default_page_size(100)
+            .connect()
+            .unwrap();
+        assert_eq!(Some(100), client.default_page_size());
+        client
+            .query("select * from rust.test", Consistency::One, Vec::new())
+            .unwrap();
+        let insert = "insert into rust.test (id) values ('a')";
+        client.query(insert, Consistency::One, Vec::new()).unwrap();
+        let rows = client
+            .query_rows("select * from rust.test", Consistency::One, Vec::new())
+            .unwrap()
+            .unwrap();
+        // Both pages, and no paging state left.
+        assert_eq!(3, rows.rows().len());
+        assert_eq!(None, rows.metadata().paging_state());
+        client
+            .query_paged(
+                "select * from rust.test",
+                Consistency::One,
+                Vec::new(),
+                10,
+                None,
+            )
+            .unwrap();
+        drop(client);
+
+        let expected = |stream, query_str, page_size, paging_state| {
+            let mut req = query(stream, query_str, Consistency::One, Vec::new());
+            req.body.params.page_size = page_size;
+            req.body.params.paging_state = paging_state;
+            req.to_vec().unwrap()
+        };
+        let frames = server.join().unwrap();
+        assert_eq!(6, frames.len());
+        let select = "select * from rust.test";
+        assert_eq!(expected(1, select, Some(100), None), frames[1]);
+        // Not a SELECT, so not paged.
+        assert_eq!(expected(2, insert, None, None), frames[2]);
+        assert_eq!(expected(3, select, Some(100), None), frames[3]);
+        let paging_state = Some(vec![1, 2, 3, 4]);
+        assert_eq!(expected(4, select, Some(100), paging_state), frames[4]);
+        // An explicit size wins.
+        assert_eq!(expected(5, select, Some(10), None), frames[5]);
+    }
+
+    #[test]
+    fn row_into_values() {
+        let row = read_rows(RESULT_SELECT).rows[0].clone();
+        let ptr = match row.cols[0] {
+            Value::CqlVarChar(ref s) => s.as_ptr(),
+            ref col => panic!("unexpected column: {:?}", col),
+        };
+
+        let values = row.clone().into_values();
+        assert_eq!(2, values.len());
+        match values[0] {
+            Value::CqlVarChar(ref s) => assert_eq!("asdf", s),
+            ref col => panic!("unexpected column: {:?}", col),
+        }
+        match values[1] {
+            Value::CqlFloat(f) => assert_eq!(1.2345, f),
+            ref col => panic!("unexpected column: {:?}", col),
+        }
+
+        match row.take("id") {
+            Some(Value::CqlVarChar(s)) => assert_eq!(ptr, s.as_ptr()),
+            col => panic!("unexpected column: {:?}", col),
+        }
+    }
+
+    #[test]
+    fn query_serial_consistency() {
+        let query_str = "select * from rust.test where v1 = 'asdf'";
+        let bytes = query(0, query_str, Consistency::Serial, Vec::new())
+            .to_vec()
+            .unwrap();
+        let offset = 9 + 4 + query_str.len();
+        assert_eq!(&[0x00, 0x08], &bytes[offset..offset + 2]);
+
+        assert!(Consistency::LocalSerial.is_serial());
+        assert!(!Consistency::Quorum.is_serial());
+        assert!(is_write("  INSERT into rust.test (v1) values ('a')"));
+        assert!(!is_write(query_str));
+    }
+
+    #[test]
+    fn monotonic_timestamps() {
+        let mut generator = MonotonicTimestampGenerator::new();
+        assert_eq!(100, generator.next_after(100));
+        assert_eq!(101, generator.next_after(100));
+        assert_eq!(102, generator.next_after(50));
+        assert_eq!(200, generator.next_after(200));
+    }
+
+    #[test]
+    fn query_default_timestamp() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+        let query_str = "insert into rust.test (v1) values ('asdf')";
+
+        let mut client = ClientBuilder::new(&addr)
+            .timestamp_generator(MonotonicTimestampGenerator::new())
+            .connect()
+            .unwrap();
+        for _ in 0..2 {
+            client
+                .query(query_str, Consistency::One, Vec::new())
+                .unwrap();
+        }
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let timestamps: Vec<i64> = frames[1..]
+            .iter()
+            .map(|req| {
+                let offset = 9 + 4 + query_str.len();
+                // consistency, flags, and no values
+                assert_eq!(0x20, req[offset + 2]);
+                (&req[offset + 3..]).read_i64::<BigEndian>().unwrap()
+            })
+            .collect();
+        assert!(timestamps[0] > 0);
+        assert!(timestamps[1] > timestamps[0]);
+    }
+
+    const RESULT_COUNTERS: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 74, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 4, 114, 117, 115, 116,
+        0, 8, 99, 111, 117, 110, 116, 101, 114, 115, 0, 4, 104, 105, 116, 115, 0, 5, 0, 6, 109,
+        105, 115, 115, 101, 115, 0, 5, 0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 8,
+        0, 0, 0, 0, 0, 0, 0, 7,
+    ];
+
+    #[test]
+    fn resp_result_counters() {
+        let rows = read_rows(RESULT_COUNTERS);
+        assert_eq!(1, rows.rows().len());
+        let row = &rows.rows()[0];
+        match row.get_column("hits") {
+            Some(Value::CqlCounter(42)) => (),
+            col => panic!("unexpected column: {:?}", col),
+        }
+        match row.get_column("misses") {
+            Some(Value::CqlCounter(7)) => (),
+            col => panic!("unexpected column: {:?}", col),
+        }
+        let hits = row.get_column("hits").unwrap();
+        assert_eq!(42, i64::from_cql_value(hits).unwrap());
+
+        let mut negative = RESULT_COUNTERS.to_vec();
+        let len = negative.len();
+        negative[len - 8..].copy_from_slice(&[0xff; 8]);
+        match read_rows(&negative).rows()[0].get_column("misses") {
+            Some(Value::CqlCounter(-1)) => (),
+            col => panic!("unexpected column: {:?}", col),
+        }
+
+        let short = [0, 0, 0, 4, 0, 0, 0, 7];
+        match short
+            .as_ref()
+            .read_cql_col(&CqlColDescr::Single(ColumnType::Counter))
+        {
+            Err(Error::Protocol) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn resp_version_mismatch() {
+        let v = vec![131, 0, 0, 1, 2, 0, 0, 0, 0];
+        match v.as_slice().read_cql_response(0x04) {
+            Err(Error::ProtocolVersion {
+                expected: 0x84,
+                actual: 0x83,
+            }) => (),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    }
+
+    #[test]
+    fn client_register() {
+        let (addr, server) = mock_server(1, |req| frame(frame_stream(req), Opcode::Ready, &[]));
+
+        let mut client = Client::new(&addr).unwrap();
+        client
+            .register(&[EventType::StatusChange, EventType::SchemaChange])
+            .unwrap();
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let mut expected = vec![
+            0x03, 0x00, 0x00, 0x01, 0x0B, 0x00, 0x00, 0x00, 0x20, 0x00, 0x02,
+        ];
+        expected.extend_from_slice(b"\x00\x0dSTATUS_CHANGE\x00\x0dSCHEMA_CHANGE");
+        assert_eq!(expected, frames[1]);
+    }
+
+    #[test]
+    fn client_reregisters_on_reconnect() {
+        let (addr, server) = mock_server(2, |req| frame(frame_stream(req), Opcode::Ready, &[]));
+
+        let mut client = Client::new(&addr).unwrap();
+        client
+            .register(&[EventType::StatusChange, EventType::SchemaChange])
+            .unwrap();
+        client.register(&[EventType::StatusChange]).unwrap();
+        assert_eq!(
+            &[EventType::StatusChange, EventType::SchemaChange],
+            client.registered_events()
+        );
+        client.reconnect().unwrap();
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let opcodes: Vec<_> = frames.iter().map(|f| f[4]).collect();
+        let (startup, reg) = (Opcode::Startup as u8, Opcode::Register as u8);
+        assert_eq!(vec![startup, reg, reg, startup, reg], opcodes);
+        // The stream ids start over on the new connection.
+        assert_eq!(&[0, 1], &frames[4][2..4]);
+        assert_eq!(frames[1][4..], frames[4][4..]);
+    }
+
+    const RESULT_NULL_SET_ELEMENT: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 55, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, 114, 117, 115, 116,
+        0, 4, 115, 101, 116, 115, 0, 1, 115, 0, 34, 0, 9, 0, 0, 0, 1, 0, 0, 0, 16, 0, 0, 0, 2, 0,
+        0, 0, 4, 0, 0, 0, 1, 255, 255, 255, 255,
+    ];
+
+    #[test]
+    fn strict_null_set_element() {
+        let rows = read_rows(RESULT_NULL_SET_ELEMENT);
+        match rows.validate_strict() {
+            Err(Error::Protocol) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(read_rows(RESULT_SELECT).validate_strict().is_ok());
+
+        let (addr, server) = mock_server(2, |req| respond(req, RESULT_NULL_SET_ELEMENT));
+        let mut lenient = Client::new(&addr).unwrap();
+        assert!(lenient.rows("select s from rust.sets").is_ok());
+        drop(lenient);
+
+        let mut strict = ClientBuilder::new(&addr).strict(true).connect().unwrap();
+        match strict.rows("select s from rust.sets") {
+            Err(Error::Protocol) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(strict);
+        server.join().unwrap();
+    }
+
+    const RESULT_PREPARED: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 56, 0, 0, 0, 4, 0, 16, 160, 161, 162, 163, 164, 165, 166, 167,
+        168, 169, 170, 171, 172, 173, 174, 175, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, 114, 117, 115, 116,
+        0, 4, 116, 101, 115, 116, 0, 2, 118, 50, 0, 8, 0, 0, 0, 4, 0, 0, 0, 0,
+    ];
+
+    #[test]
+    fn resp_result_prepared_id() {
+        // The id is a [short bytes], not the single byte older clients read.
+        let declared_len = (&RESULT_PREPARED[13..15]).read_u16::<BigEndian>().unwrap();
+        let mut frame = RESULT_PREPARED;
+        let resp = frame.read_cql_response(CQL_VERSION).unwrap();
+        match resp.body {
+            ResponseBody::Result(ResponseResult::Prepared(id, metadata, _)) => {
+                assert_eq!(usize::from(declared_len), id.len());
+                assert_eq!(&RESULT_PREPARED[15..31], id.as_slice());
+                assert_eq!("v2", metadata.columns()[0].name());
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    const RESULT_INTS: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 58, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, 114, 117, 115, 116,
+        0, 4, 105, 110, 116, 115, 0, 2, 105, 100, 0, 9, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0,
+        0, 4, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 3,
+    ];
+
+    #[test]
+    fn client_query_column() {
+        let (addr, server) = mock_server(1, |req| respond(req, RESULT_INTS));
+
+        let mut client = Client::new(&addr).unwrap();
+        let ids: Vec<i32> = client
+            .query_column("select id from rust.ints", Consistency::One, Vec::new())
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], ids);
+
+        let res: Result<Vec<String>> =
+            client.query_column("select id from rust.ints", Consistency::One, Vec::new());
+        match res {
+            Err(Error::TypeMismatch) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn client_orphan_frames() {
+        let (addr, server) = mock_server(1, |req| {
+            let mut resp = frame(-1, Opcode::Event, &[0, 0]);
+            resp.extend(frame(5, Opcode::Result, RESULT_VOID));
+            resp.extend(respond(req, RESULT_INTS));
+            resp
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let ids: Vec<i32> = client
+            .query_column("select id from rust.ints", Consistency::One, Vec::new())
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], ids);
+
+        let event = client.orphan_handler.next_event().unwrap();
+        assert_eq!(-1, event.header().stream());
+        assert_eq!(&[0, 0], event.body());
+        assert!(client.orphan_handler.next_event().is_none());
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn tuple_all_null_round_trip() {
+        let value = Value::CqlTuple(vec![Value::CqlNull, Value::CqlNull]);
+        let bytes = value.to_vec().unwrap();
+        assert_eq!(value.len_(), bytes.len());
+        // Every null field is still written, as a -1 length.
+        assert_eq!(
+            vec![0, 0, 0, 8, 255, 255, 255, 255, 255, 255, 255, 255],
+            bytes
+        );
+
+        let ty = CqlColDescr::Tuple(
+            vec![
+                CqlColDescr::Single(ColumnType::Int),
+                CqlColDescr::Single(ColumnType::Text),
+            ]
+            .into(),
+        );
+        match bytes.as_slice().read_cql_col(&ty).unwrap() {
+            Value::CqlTuple(ref v) => match v.as_slice() {
+                [Value::CqlNull, Value::CqlNull] => (),
+                fields => panic!("unexpected fields: {:?}", fields),
+            },
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn tuple_round_trip() {
+        let ty = CqlColDescr::Tuple(
+            vec![
+                CqlColDescr::Single(ColumnType::Int),
+                CqlColDescr::Single(ColumnType::Text),
+            ]
+            .into(),
+        );
+        let value = Value::CqlTuple(vec![Value::CqlInt(7), Value::CqlText("seven".to_owned())]);
+        let mut expected = vec![0, 0, 0, 17, 0, 0, 0, 4, 0, 0, 0, 7, 0, 0, 0, 5];
+        expected.extend_from_slice(b"seven");
+        assert_eq!(expected, value.to_vec().unwrap());
+        let decoded = expected.as_slice().read_cql_col(&ty).unwrap();
+        assert_eq!(format!("{:?}", value), format!("{:?}", decoded));
+        assert_eq!("(7, 'seven')", decoded.to_string());
+
+        // One field short, and one too many.
+        let short: &[u8] = &[0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0, 7];
+        match { short }.read_cql_col(&ty) {
+            Err(Error::Io(_)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        let mut long = vec![0, 0, 0, 20, 0, 0, 0, 4, 0, 0, 0, 7, 0, 0, 0, 0];
+        long.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 7]);
+        match long.as_slice().read_cql_col(&ty) {
+            Err(Error::Protocol) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn corrupt_collection_counts() {
+        let int = || Box::new(CqlColDescr::Single(ColumnType::Int));
+        let list = CqlColDescr::List(int());
+        let map = CqlColDescr::Map(Box::new((*int(), *int())));
+        // A negative count, then counts of more elements than bytes follow.
+        let cases: &[(&CqlColDescr, &[u8])] = &[
+            (&list, &[0, 0, 0, 4, 255, 255, 255, 255]),
+            (&list, &[0, 0, 0, 8, 127, 255, 255, 255, 0, 0, 0, 0]),
+            (&map, &[0, 0, 0, 12, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+        for &(ty, bytes) in cases {
+            let mut bytes = bytes;
+            match bytes.read_cql_col(ty) {
+                Err(Error::Protocol) => (),
+                v => panic!("unexpected value for {:?}: {:?}", ty, v),
+            }
+        }
+
+        // Empty strings are the smallest elements there are.
+        let texts = CqlColDescr::List(Box::new(CqlColDescr::Single(ColumnType::Text)));
+        let mut bytes: &[u8] = &[0, 0, 0, 12, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+        match bytes.read_cql_col(&texts).unwrap() {
+            Value::CqlList(ref l) => assert_eq!(2, l.len()),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn col_descr_eq_and_display() {
+        use ColumnType::*;
+
+        let single = |ty| CqlColDescr::Single(ty);
+        let address = CqlColDescr::Udt {
+            keyspace: "rust".to_owned(),
+            name: "address".to_owned(),
+            fields: vec![("street".to_owned(), single(Text))],
+        };
+        let map = CqlColDescr::Map(Box::new((single(Text), address.clone())));
+        // map<text, frozen<address>> as sent in column metadata.
+        let mut wire: &[u8] =
+            b"\x00\x21\x00\x0a\x00\x30\x00\x04rust\x00\x07address\x00\x01\x00\x06street\x00\x0a";
+        assert_eq!(map, wire.read_cql_col_type().unwrap());
+        assert_ne!(
+            map,
+            CqlColDescr::Map(Box::new((single(VarChar), address.clone())))
+        );
+
+        let tuple = CqlColDescr::Tuple(vec![single(Int), single(Text)].into());
+        let list = CqlColDescr::List(Box::new(tuple.clone()));
+        assert_eq!(list, CqlColDescr::List(Box::new(tuple.clone())));
+        assert_ne!(list, CqlColDescr::Set(Box::new(tuple.clone())));
+
+        assert_eq!("map<text, frozen<address>>", map.to_string());
+        assert_eq!("tuple<int, text>", tuple.to_string());
+        assert_eq!("list<frozen<tuple<int, text>>>", list.to_string());
+        let nested = CqlColDescr::Map(Box::new((
+            CqlColDescr::Set(Box::new(single(Int))),
+            CqlColDescr::Tuple(vec![list, address].into()),
+        )));
+        assert_eq!(
+            "map<frozen<set<int>>, frozen<tuple<frozen<list<frozen<tuple<int, text>>>>, \
+             frozen<address>>>>",
+            nested.to_string()
+        );
+        assert_eq!(
+            "set<timeuuid>",
+            CqlColDescr::Set(Box::new(single(TimeUUID))).to_string()
+        );
+        assert_eq!(
+            "'org.example.Point'",
+            CqlColDescr::Custom("org.example.Point".to_owned()).to_string()
+        );
+    }
+
+    #[test]
+    fn udt_columns() {
+        fn string(s: &str) -> Vec<u8> {
+            let mut v = vec![0, s.len() as u8];
+            v.extend_from_slice(s.as_bytes());
+            v
+        }
+        fn bytes(body: &[u8]) -> Vec<u8> {
+            let mut v = Vec::new();
+            v.write_i32::<BigEndian>(body.len() as i32).unwrap();
+            v.extend_from_slice(body);
+            v
+        }
+
+        // person (name text, home address (street varchar, zip int)) in a
+        // list.
+        let mut address = vec![0, 0x30];
+        address.extend(string("ks"));
+        address.extend(string("address"));
+        address.extend(&[0, 2]);
+        address.extend(string("street"));
+        address.extend(&[0, 0x0d]);
+        address.extend(string("zip"));
+        address.extend(&[0, 0x09]);
+        let mut person = vec![0, 0x20, 0, 0x30];
+        person.extend(string("ks"));
+        person.extend(string("person"));
+        person.extend(&[0, 2]);
+        person.extend(string("name"));
+        person.extend(&[0, 0x0a]);
+        person.extend(string("home"));
+        person.extend(&address);
+
+        let mut reader = person.as_slice();
+        let ty = reader.read_cql_col_type().unwrap();
+        assert!(reader.is_empty());
+        match ty {
+            CqlColDescr::List(ref ty) => match **ty {
+                CqlColDescr::Udt {
+                    ref keyspace,
+                    ref name,
+                    ref fields,
+                } => {
+                    assert_eq!("ks", keyspace);
+                    assert_eq!("person", name);
+                    assert_eq!("name", fields[0].0);
+                    assert_eq!("home", fields[1].0);
+                }
+                ref ty => panic!("unexpected type: {:?}", ty),
+            },
+            ref ty => panic!("unexpected type: {:?}", ty),
+        }
+
+        let mut home = Value::CqlVarChar("main".to_owned()).to_vec().unwrap();
+        home.extend(Value::CqlInt(12345).to_vec().unwrap());
+        let mut first = Value::CqlText("kim".to_owned()).to_vec().unwrap();
+        first.extend(bytes(&home));
+        // Written before `home` was added to the type.
+        let second = Value::CqlText("lee".to_owned()).to_vec().unwrap();
+        let mut list = vec![0, 0, 0, 2];
+        list.extend(bytes(&first));
+        list.extend(bytes(&second));
+
+        let people = match bytes(&list).as_slice().read_cql_col(&ty).unwrap() {
+            Value::CqlList(people) => people,
+            v => panic!("unexpected value: {:?}", v),
+        };
+        match people[0] {
+            Value::CqlUDT(ref fields) => match fields.as_slice() {
+                [(ref name, Value::CqlText(ref text)), (ref home, Value::CqlUDT(ref address))] => {
+                    assert_eq!(
+                        ("name", "kim", "home"),
+                        (name.as_str(), text.as_str(), home.as_str())
+                    );
+                    match address.as_slice() {
+                        [(_, Value::CqlVarChar(ref street)), (_, Value::CqlInt(12345))] => {
+                            assert_eq!("main", street)
+                        }
+                        address => panic!("unexpected address: {:?}", address),
+                    }
+                }
+                fields => panic!("unexpected fields: {:?}", fields),
+            },
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+        match people[1] {
+            Value::CqlUDT(ref fields) => match fields.as_slice() {
+                [(_, Value::CqlText(ref text)), (ref home, Value::CqlNull)] => {
+                    assert_eq!(("lee", "home"), (text.as_str(), home.as_str()))
+                }
+                fields => panic!("unexpected fields: {:?}", fields),
+            },
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+        assert_eq!("udt", people[0].type_name());
+
+        // More than the type declares is still an error.
+        let mut long = first.clone();
+        long.extend(Value::CqlInt(1).to_vec().unwrap());
+        let person = match ty {
+            CqlColDescr::List(ref ty) => ty,
+            _ => unreachable!(),
+        };
+        match bytes(&long).as_slice().read_cql_col(person) {
+            Err(Error::Protocol) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn udt_round_trip() {
+        let ty = CqlColDescr::Udt {
+            keyspace: "ks".to_owned(),
+            name: "address".to_owned(),
+            fields: vec![
+                (
+                    "street".to_owned(),
+                    CqlColDescr::Single(ColumnType::VarChar),
+                ),
+                ("unit".to_owned(), CqlColDescr::Single(ColumnType::Int)),
+                ("zip".to_owned(), CqlColDescr::Single(ColumnType::Int)),
+            ],
+        };
+        let value = Value::CqlUDT(vec![
+            ("street".to_owned(), Value::CqlVarChar("main".to_owned())),
+            ("unit".to_owned(), Value::CqlNull),
+            ("zip".to_owned(), Value::CqlInt(12345)),
+        ]);
+        let bytes = value.to_vec().unwrap();
+        assert_eq!(value.len_(), bytes.len());
+        assert_eq!(
+            vec![
+                0, 0, 0, 20, 0, 0, 0, 4, b'm', b'a', b'i', b'n', 255, 255, 255, 255, 0, 0, 0, 4, 0,
+                0, 0x30, 0x39
+            ],
+            bytes
+        );
+        assert_eq!(
+            format!("{:?}", value),
+            format!("{:?}", bytes.as_slice().read_cql_col(&ty).unwrap())
+        );
+
+        let bad = Value::CqlUDT(vec![
+            ("ok".to_owned(), Value::CqlInt(1)),
+            ("f".to_owned(), Value::CqlCounter(1)),
+        ]);
+        match bad.to_vec() {
+            Err(Error::UdtField(ref name, ref e)) => match **e {
+                Error::Unimplemented => assert_eq!("f", name),
+                ref e => panic!("unexpected error: {:?}", e),
+            },
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match Value::CqlUnknown.to_vec() {
+            Err(Error::Unimplemented) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn client_tcp_keepalive() {
+        let (addr, server) = mock_server(5, |_| Vec::new());
+
+        let client = Client::new(&addr).unwrap();
+        let socket = socket2::SockRef::from(client.writer.get_ref());
+        assert!(!socket.keepalive().unwrap());
+        drop(client);
+
+        let idle = Duration::from_secs(45);
+        let mut client = ClientBuilder::new(&addr)
+            .tcp_keepalive(Some(idle))
+            .connect()
+            .unwrap();
+        // Kept across a reconnect, too.
+        client.reconnect().unwrap();
+        let socket = socket2::SockRef::from(client.writer.get_ref());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(idle, socket.keepalive_time().unwrap());
+        assert_eq!(idle, socket.keepalive_interval().unwrap());
+        drop(client);
+
+        for &(idle, secs) in &[
+            (Duration::from_millis(500), 1),
+            (Duration::from_millis(1500), 2),
+        ] {
+            let client = ClientBuilder::new(&addr)
+                .tcp_keepalive(Some(idle))
+                .connect()
+                .unwrap();
+            let socket = socket2::SockRef::from(client.writer.get_ref());
+            assert_eq!(Duration::from_secs(secs), socket.keepalive_time().unwrap());
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn client_keepalive() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Supported, &[0, 0])
+        });
+        let interval = Duration::from_secs(30);
+
+        let mut client = Client::new(&addr).unwrap();
+        let connected = Instant::now();
+        assert!(!client.should_ping(connected + interval));
+
+        client.enable_keepalive(interval);
+        assert!(!client.should_ping(client.last_activity));
+        assert!(client.should_ping(connected + interval));
+
+        thread::sleep(Duration::from_millis(10));
+        client.ping().unwrap();
+        assert!(client.last_activity > connected);
+        assert!(!client.should_ping(connected + interval));
+        assert!(client.should_ping(client.last_activity + interval));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn map_tuple_keys() {
+        let ty = CqlColDescr::Map(Box::new((
+            CqlColDescr::Tuple(
+                vec![
+                    CqlColDescr::Single(ColumnType::Int),
+                    CqlColDescr::Single(ColumnType::Int),
+                ]
+                .into(),
+            ),
+            CqlColDescr::Single(ColumnType::Text),
+        )));
+        let key = |a, b| Value::CqlTuple(vec![Value::CqlInt(a), Value::CqlInt(b)]);
+        let value = Value::CqlMap(vec![
+            (key(1, 2), Value::CqlText("a".to_owned())),
+            (key(3, 4), Value::CqlText("b".to_owned())),
+        ]);
+
+        let mut bytes = value.to_vec().unwrap();
+        bytes.extend(Value::CqlInt(5).to_vec().unwrap());
+        let mut reader = bytes.as_slice();
+        let decoded = reader.read_cql_col(&ty).unwrap();
+        assert_eq!(format!("{:?}", value), format!("{:?}", decoded));
+        // The following column is untouched.
+        match reader.read_cql_col(&CqlColDescr::Single(ColumnType::Int)) {
+            Ok(Value::CqlInt(5)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+
+        // A key which does not fill its [bytes] region exactly.
+        let bad = vec![
+            0, 0, 0, 33, 0, 0, 0, 1, 0, 0, 0, 20, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 2,
+            0, 0, 0, 0, 0, 0, 0, 1, b'a',
+        ];
+        match bad.as_slice().read_cql_col(&ty) {
+            Err(Error::Protocol) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn value_heap_size() {
+        use std::mem::size_of;
+
+        assert_eq!(0, Value::CqlInt(1).heap_size());
+        assert_eq!(0, Value::CqlNull.heap_size());
+        assert_eq!(5, Value::CqlText("hello".to_owned()).heap_size());
+        assert_eq!(3, Value::CqlBlob(vec![1, 2, 3]).heap_size());
+
+        let list = Value::CqlList(vec![Value::CqlText("ab".to_owned()), Value::CqlInt(1)]);
+        assert_eq!(2 * size_of::<Value>() + 2, list.heap_size());
+        let map = Value::CqlMap(vec![(Value::CqlAscii("k".to_owned()), list)]);
+        assert_eq!(
+            size_of::<(Value, Value)>() + 1 + 2 * size_of::<Value>() + 2,
+            map.heap_size()
+        );
+    }
+
+    #[test]
+    fn rows_heap_size() {
+        use std::mem::size_of;
+
+        // "rust", "test", then the "id" and "value" columns, which are
+        // indexed by name too.
+        let rows = read_rows(RESULT_SELECT);
+        let indexes = rows.metadata.column_indexes.capacity() * size_of::<(String, usize)>() + 7;
+        let metadata = size_of::<Metadata>() + 15 + 2 * size_of::<CqlColMetadata>() + indexes;
+        // "asdf" and a float.
+        let row = 2 * size_of::<Value>() + 4;
+
+        assert_eq!(1, rows.rows.capacity());
+        assert_eq!(size_of::<Row>() + row + metadata, rows.heap_size());
+
+        // Further rows don't count the shared metadata again.
+        let mut more = rows.clone();
+        more.rows.push(rows.rows[0].clone());
+        more.rows.shrink_to_fit();
+        assert_eq!(2 * (size_of::<Row>() + row) + metadata, more.heap_size());
+    }
+
+    #[test]
+    fn empty_boolean() {
+        let ty = CqlColDescr::Single(ColumnType::Boolean);
+        let mut reader: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 1, 1];
+        match reader.read_cql_col(&ty) {
+            Ok(Value::CqlBoolean(false)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        match reader.read_cql_col(&ty) {
+            Ok(Value::CqlBoolean(true)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        match [0, 0, 0, 2, 0, 1].as_ref().read_cql_col(&ty) {
+            Err(Error::Protocol) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    fn read_result(mut frame: &[u8]) -> ResponseResult {
+        frame
+            .read_cql_response(CQL_VERSION)
+            .unwrap()
+            .into_result()
+            .unwrap()
+    }
+
+    #[test]
+    fn query_result_accessors() {
+        let result = read_result(RESULT_SELECT);
+        assert_eq!(1, result.as_rows().unwrap().rows().len());
+        assert!(result.clone().into_prepared().is_none());
+        assert_eq!(1, result.rows().unwrap().rows().len());
+
+        let result = read_result(RESULT_PREPARED);
+        assert!(result.as_rows().is_none());
+        assert!(result.clone().rows().is_none());
+        let prepared = result.into_prepared().unwrap();
+        assert_eq!(&(0xa0..0xb0).collect::<Vec<u8>>()[..], prepared.id());
+        assert_eq!("v2", prepared.metadata().columns()[0].name());
+
+        let result = read_result(&frame(0, Opcode::Result, RESULT_VOID));
+        assert!(result.as_rows().is_none());
+        assert!(result.clone().rows().is_none());
+        assert!(result.into_prepared().is_none());
+    }
+
+    #[test]
+    fn retryable_error_codes() {
+        use ErrorCode::*;
+
+        let retryable = [
+            Overloaded,
+            IsBootstrapping,
+            ServerError,
+            TruncateError,
+            Unavailable,
+        ];
+        let fatal = [
+            SyntaxError,
+            Invalid,
+            Unauthorized,
+            ConfigError,
+            AlreadyExists,
+            BadCredentials,
+            ProtocolError,
+            Unprepared,
+            WriteTimeout,
+            ReadTimeout,
+            ReadFailure,
+            FunctionFailure,
+            WriteFailure,
+            Unknown,
+        ];
+        for code in retryable.iter() {
+            assert!(code.is_retryable_on_next_host(), "{:?}", code);
+            assert_eq!(*code, error_code(*code as u32));
+        }
+        for code in fatal.iter() {
+            assert!(!code.is_retryable_on_next_host(), "{:?}", code);
+            assert_eq!(*code, error_code(*code as u32));
+        }
+        assert_eq!(Unknown, error_code(0xffff));
+
+        let err = Error::Cql {
+            code: 0x1001,
+            message: String::new(),
+            details: ErrorDetail::None,
+        };
+        assert_eq!(Some(Overloaded), err.error_code());
+        assert_eq!(None, Error::Protocol.error_code());
+    }
+
+    #[test]
+    fn test_count_placeholders() {
+        assert_eq!(0, count_placeholders("select * from t"));
+        assert_eq!(2, count_placeholders("insert into t (a, b) values (?, ?)"));
+        assert_eq!(
+            1,
+            count_placeholders("select * from t where a = '?' and b = ?")
+        );
+        assert_eq!(
+            1,
+            count_placeholders("select * from t where a = 'it''s?' and b = ?")
+        );
+        assert_eq!(1, count_placeholders("select \"?\" from t where a = ?"));
+        assert_eq!(
+            1,
+            count_placeholders("select * from t where a = $$?$$ and b = ?")
+        );
+        assert_eq!(
+            1,
+            count_placeholders("select * from t -- a = ?\n// b = ?\nwhere c = ?")
+        );
+        assert_eq!(
+            2,
+            count_placeholders("select /* ? */ * from t where a = ? and b = ?")
+        );
+        assert_eq!(0, count_placeholders("select * from t where a = '?"));
+    }
+
+    #[test]
+    fn client_check_bind_count() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .check_bind_count(true)
+            .connect()
+            .unwrap();
+        let query_str = "update t set a = ? where b = '?' -- and c = ?";
+        match client.query(query_str, Consistency::One, vec![]) {
+            Err(Error::BindCount {
+                expected: 1,
+                actual: 0,
+            }) => (),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        client
+            .query(query_str, Consistency::One, vec![Value::CqlInt(1)])
+            .unwrap();
+        drop(client);
+
+        // Only the valid query was sent.
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn client_pipelined_frames() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let insert = query(
+            0,
+            "insert into t (a) values (1)",
+            Consistency::One,
+            Vec::new(),
+        );
+        let select = query(0, "select * from rust.ints", Consistency::One, Vec::new());
+        let first = client.send_frame(&insert.to_vec().unwrap()).unwrap();
+        let second = client.send_frame(&select.to_vec().unwrap()).unwrap();
+        assert_ne!(first, second);
+        client.flush().unwrap();
+
+        // Received out of order; the first response is kept meanwhile.
+        client.recv_frame(second).unwrap();
+        assert!(client.received.contains_key(&first));
+        match client.recv_frame(first).unwrap().body {
+            ResponseBody::Result(ResponseResult::Void) => (),
+            body => panic!("unexpected body: {:?}", body),
+        }
+        assert!(client.recv_frame(first).is_err());
+        drop(client);
+
+        let frames = server.join().unwrap();
+        assert_eq!(first, frame_stream(&frames[1]));
+        assert_eq!(second, frame_stream(&frames[2]));
+    }
+
+    #[test]
+    fn client_max_in_flight() {
+        let (addr, server) = mock_server(2, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+        let req = query(0, "select * from rust.ints", Consistency::One, Vec::new())
+            .to_vec()
+            .unwrap();
+
+        let mut client = ClientBuilder::new(&addr)
+            .max_in_flight(2, false)
+            .connect()
+            .unwrap();
+        let first = client.send_frame(&req).unwrap();
+        let second = client.send_frame(&req).unwrap();
+        match client.send_frame(&req) {
+            Err(Error::InFlightLimit(2)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // A finished request frees its place.
+        client.recv_frame(first).unwrap();
+        let third = client.send_frame(&req).unwrap();
+        client.recv_frame(second).unwrap();
+        client.recv_frame(third).unwrap();
+        drop(client);
+
+        let mut client = ClientBuilder::new(&addr)
+            .max_in_flight(2, true)
+            .connect()
+            .unwrap();
+        let streams: Vec<i16> = (0..3).map(|_| client.send_frame(&req).unwrap()).collect();
+        // The third waited for the first response, which is kept.
+        assert_eq!(2, client.in_flight.len());
+        assert!(client.received.contains_key(&streams[0]));
+        for stream in streams {
+            client.recv_frame(stream).unwrap();
+        }
+        drop(client);
+
+        assert_eq!(2 * 4, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn client_streams_exhausted() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+        let req = query(0, "select * from rust.ints", Consistency::One, Vec::new())
+            .to_vec()
+            .unwrap();
+
+        let mut client = Client::new(&addr).unwrap();
+        // Every id holds a response nobody asked for yet.
+        for stream in 0..=i16::MAX {
+            let frame = RawFrame {
+                header: FrameHeader::new(stream, Opcode::Result),
+                body: RESULT_VOID.to_vec(),
+            };
+            client.received.insert(stream, frame);
+        }
+        match client.send_frame(&req) {
+            Err(Error::InFlightLimit(MAX_STREAMS)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // Taking one frees its id.
+        client.recv_frame(7).unwrap();
+        assert_eq!(7, client.send_frame(&req).unwrap());
+        client.recv_frame(7).unwrap();
+        drop(client);
+
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn inet_column_and_node() {
+        // An inet column holds the address only.
+        let mut column: &[u8] = &[0, 0, 0, 4, 127, 0, 0, 1];
+        match column.read_cql_col(&CqlColDescr::Single(ColumnType::Inet)) {
+            Ok(Value::CqlInet(addr)) => assert_eq!("127.0.0.1".parse::<IpAddr>().unwrap(), addr),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        assert!(column.is_empty());
+
+        let mut column: &[u8] = &[
+            0, 0, 0, 16, 0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        match column.read_cql_col(&CqlColDescr::Single(ColumnType::Inet)) {
+            Ok(Value::CqlInet(addr)) => assert_eq!("fe80::1".parse::<IpAddr>().unwrap(), addr),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        // Only the two address sizes are valid.
+        match [0, 0, 0, 6, 127, 0, 0, 1, 0x23, 0x52]
+            .as_ref()
+            .read_cql_col(&CqlColDescr::Single(ColumnType::Inet))
+        {
+            Err(Error::Protocol) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+
+        // The node of a STATUS_CHANGE event carries a port.
+        let mut event: &[u8] =
+            b"\x00\x0dSTATUS_CHANGE\x00\x02UP\x04\x0a\x00\x00\x01\x00\x00\x23\x52";
+        assert_eq!("STATUS_CHANGE", event.read_cql_str().unwrap());
+        assert_eq!("UP", event.read_cql_str().unwrap());
+        assert_eq!(
+            "10.0.0.1:9042".parse::<SocketAddr>().unwrap(),
+            event.read_cql_inet_node().unwrap()
+        );
+        assert!(event.is_empty());
+
+        let mut node: &[u8] = &[
+            16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0x23, 0x52,
+        ];
+        assert_eq!(
+            "[::1]:9042".parse::<SocketAddr>().unwrap(),
+            node.read_cql_inet_node().unwrap()
+        );
+    }
+
+    #[test]
+    fn prepared_ensure_valid() {
+        let mut prepares = 0;
+        let (addr, server) = mock_server(1, move |req| {
+            if req[4] == Opcode::Prepare as u8 {
+                // The node forgets the first id, and hands out another.
+                prepares += 1;
+                let mut resp = respond(req, RESULT_PREPARED);
+                resp[15] = prepares;
+                return resp;
+            }
+            if req[11] == 1 {
+                let mut body = vec![0, 0, 0x25, 0, 0, 0];
+                body.extend_from_slice(&req[9..11 + 16]);
+                return frame(frame_stream(req), Opcode::Error, &body);
+            }
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let mut statement = client.prepare("insert into t (v2) values (?)").unwrap();
+        assert_eq!(Some("insert into t (v2) values (?)"), statement.query());
+        assert_eq!(1, statement.id()[0]);
+        // The bind marker types come along with the id.
+        let markers = statement.metadata().columns();
+        assert_eq!(
+            ("v2", &CqlColDescr::Single(ColumnType::Float)),
+            (markers[0].name(), markers[0].col_type())
+        );
+        assert!(statement.result_columns().is_empty());
+        let values = vec![Value::CqlFloat(1.0)];
+        // The client prepares the query again by itself.
+        match client
+            .execute(&statement, Consistency::One, values.clone())
+            .unwrap()
+            .body
+        {
+            ResponseBody::Result(ResponseResult::Void) => (),
+            body => panic!("unexpected body: {:?}", body),
+        }
+
+        statement.ensure_valid(&mut client).unwrap();
+        assert_eq!(3, statement.id()[0]);
+        match client
+            .execute(&statement, Consistency::One, values)
+            .unwrap()
+            .body
+        {
+            ResponseBody::Result(ResponseResult::Void) => (),
+            body => panic!("unexpected body: {:?}", body),
+        }
+
+        let mut unnamed = read_result(RESULT_PREPARED).into_prepared().unwrap();
+        match unnamed.ensure_valid(&mut client) {
+            Err(Error::Protocol) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn client_reprepares_once() {
+        // A node that never keeps anything prepared.
+        let (addr, server) = mock_server(1, |req| {
+            if req[4] == Opcode::Prepare as u8 {
+                return respond(req, RESULT_PREPARED);
+            }
+            let mut body = vec![0, 0, 0x25, 0, 0, 0];
+            body.extend_from_slice(&req[9..11 + 16]);
+            frame(frame_stream(req), Opcode::Error, &body)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let prepared = client.prepare("insert into t (v2) values (?)").unwrap();
+        let values = vec![Value::CqlFloat(1.0)];
+        match client.execute(&prepared, Consistency::One, values.clone()) {
+            Err(Error::Cql { code: 0x2500, .. }) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // Not prepared through the client, so there's no query to prepare.
+        match client.execute(&statement(vec![9; 16]), Consistency::One, values) {
+            Err(Error::Cql { code: 0x2500, .. }) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let opcodes: Vec<_> = frames[1..].iter().map(|f| f[4]).collect();
+        let (prepare, execute) = (Opcode::Prepare as u8, Opcode::Execute as u8);
+        assert_eq!(vec![prepare, execute, prepare, execute, execute], opcodes);
+    }
+
+    #[test]
+    fn client_forgets_oldest_queries() {
+        // Every PREPARE gets a new id.
+        let mut next_id = 0u32;
+        let (addr, server) = mock_server(1, move |req| {
+            if req[4] != Opcode::Prepare as u8 {
+                return frame(frame_stream(req), Opcode::Result, RESULT_VOID);
+            }
+            let mut resp = respond(req, RESULT_PREPARED);
+            next_id += 1;
+            resp[15..19].copy_from_slice(&next_id.to_be_bytes());
+            resp
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .cache_prepared(true)
+            .connect()
+            .unwrap();
+        client
+            .prepare_and_execute("select * from t0", Consistency::One, Vec::new())
+            .unwrap();
+        let first = client.query_ids[0].clone();
+        for i in 1..=MAX_PREPARED_QUERIES {
+            client.prepare(&format!("select * from t{}", i)).unwrap();
+        }
+        assert_eq!(MAX_PREPARED_QUERIES, client.queries.len());
+        assert_eq!(MAX_PREPARED_QUERIES, client.query_ids.len());
+        assert!(!client.queries.contains_key(&first));
+        assert!(client.prepared.as_ref().unwrap().is_empty());
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn null_and_empty_paging_state() {
+        // Has more pages and no metadata, then the paging state.
+        let metadata = |state: &[u8]| {
+            let mut bytes = vec![0, 0, 0, 6, 0, 0, 0, 0];
+            bytes.extend_from_slice(state);
+            bytes.as_slice().read_cql_metadata()
+        };
+        assert_eq!(
+            None,
+            metadata(&[0xff, 0xff, 0xff, 0xff]).unwrap().paging_state()
+        );
+        assert_eq!(
+            Some(&[][..]),
+            metadata(&[0, 0, 0, 0]).unwrap().paging_state()
+        );
+        assert_eq!(
+            Some(&[7][..]),
+            metadata(&[0, 0, 0, 1, 7]).unwrap().paging_state()
+        );
+        match metadata(&[0xff, 0xff, 0xff, 0xfe]) {
+            Err(Error::Protocol) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn prepared_insert_result_columns() {
+        // Servers may send an empty result metadata block with or without
+        // the no-metadata flag.
+        let mut no_flags = RESULT_PREPARED.to_vec();
+        let len = no_flags.len();
+        no_flags[len - 5] = 0;
+        for frame in [RESULT_PREPARED, &no_flags[..]].iter() {
+            let statement = read_result(frame).into_prepared().unwrap();
+            assert_eq!(1, statement.metadata().columns().len());
+            assert_eq!("v2", statement.metadata().columns()[0].name());
+            assert!(statement.result_columns().is_empty());
+            assert_eq!(0, statement.result_metadata().column_count());
+        }
+    }
+
+    #[test]
+    fn client_default_consistency() {
+        let (addr, server) = mock_server(2, |req| {
+            if req[4] == Opcode::Prepare as u8 {
+                return respond(req, RESULT_PREPARED);
+            }
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+        let query_str = "insert into t (v2) values (?)";
+        let values = || vec![Value::CqlFloat(1.0)];
+
+        let mut client = Client::new(&addr).unwrap();
+        assert_eq!(&Consistency::One, client.default_consistency());
+        client.query_default(query_str, values()).unwrap();
+        drop(client);
+
+        let mut client = ClientBuilder::new(&addr)
+            .default_consistency(Consistency::LocalQuorum)
+            .connect()
+            .unwrap();
+        client.query_default(query_str, values()).unwrap();
+        client.query(query_str, Consistency::All, values()).unwrap();
+        let statement = client.prepare(query_str).unwrap();
+        client.execute_default(&statement, values()).unwrap();
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let offset = 9 + 4 + query_str.len();
+        let con = |frame: &[u8], offset: usize| (&frame[offset..]).read_u16::<BigEndian>().unwrap();
+        assert_eq!(Consistency::One as u16, con(&frames[1], offset));
+        assert_eq!(Consistency::LocalQuorum as u16, con(&frames[3], offset));
+        assert_eq!(Consistency::All as u16, con(&frames[4], offset));
+        // EXECUTE starts with the 16-byte id as [short bytes].
+        assert_eq!(Consistency::LocalQuorum as u16, con(&frames[6], 9 + 2 + 16));
+    }
+
+    #[test]
+    fn metadata_combined_flags() {
+        // Global spec and more pages: the paging state, then "ks"."t".
+        let mut global: &[u8] = b"\x00\x00\x00\x03\x00\x00\x00\x01\x00\x00\x00\x01\x07\x00\x02ks\x00\x01t\x00\x02id\x00\x09";
+        let metadata = global.read_cql_metadata().unwrap();
+        assert!(global.is_empty());
+        assert_eq!(Some("ks"), metadata.keyspace());
+        assert_eq!(Some(&[7][..]), metadata.paging_state());
+        assert_eq!(None, metadata.columns()[0].keyspace());
+        assert_eq!("id", metadata.columns()[0].name());
+
+        // More pages without a global spec: each column has its own.
+        let mut per_column: &[u8] = b"\x00\x00\x00\x02\x00\x00\x00\x01\x00\x00\x00\x01\x07\x00\x02ks\x00\x01t\x00\x02id\x00\x09";
+        let metadata = per_column.read_cql_metadata().unwrap();
+        assert!(per_column.is_empty());
+        assert_eq!(None, metadata.keyspace());
+        assert_eq!(Some("ks"), metadata.columns()[0].keyspace());
+        assert_eq!(Some("t"), metadata.columns()[0].table());
+        assert_eq!("id", metadata.columns()[0].name());
+    }
+
+    #[test]
+    fn metadata_no_metadata_flags() {
+        // With no metadata, neither the global spec nor columns follow, even
+        // when the global spec flag is also set.
+        for flags in [NO_METADATA, NO_METADATA | GLOBAL_TABLE_SPEC].iter() {
+            let mut bytes = vec![];
+            bytes.write_u32::<BigEndian>(*flags).unwrap();
+            bytes.extend_from_slice(&[0, 0, 0, 2]);
+            let mut reader = bytes.as_slice();
+            let metadata = reader.read_cql_metadata().unwrap();
+            assert!(reader.is_empty());
+            assert_eq!(2, metadata.column_count());
+            assert!(metadata.columns().is_empty());
+            assert_eq!(None, metadata.keyspace());
+        }
+
+        let mut bytes = vec![];
+        bytes
+            .write_u32::<BigEndian>(NO_METADATA | HAS_MORE_PAGES | GLOBAL_TABLE_SPEC)
+            .unwrap();
+        bytes.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 1, 9]);
+        let metadata = bytes.as_slice().read_cql_metadata().unwrap();
+        assert_eq!(Some(&[9][..]), metadata.paging_state());
+        assert!(metadata.flags() & METADATA_CHANGED == 0);
+    }
+
+    #[test]
+    fn client_throw_on_overload() {
+        let (addr, server) = mock_server(1, |req| {
+            let mut body = vec![0, 0, 0x10, 0x01, 0, 10];
+            body.extend_from_slice(b"overloaded");
+            frame(frame_stream(req), Opcode::Error, &body)
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .throw_on_overload(true)
+            .connect()
+            .unwrap();
+        match client.rows("select * from rust.test") {
+            Err(ref e @ Error::Cql { .. }) => {
+                assert_eq!(Some(ErrorCode::Overloaded), e.error_code());
+                assert!(e.error_code().unwrap().is_retryable_on_next_host());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let mut expected = frame(
+            0,
+            Opcode::Startup,
+            b"\x00\x02\x00\x0bCQL_VERSION\x00\x053.0.0\x00\x11THROW_ON_OVERLOAD\x00\x011",
+        );
+        expected[0] = CQL_VERSION;
+        assert_eq!(expected, frames[0]);
+    }
+
+    #[test]
+    fn custom_startup_options() {
+        let overrides = vec![
+            ("CQL_VERSION".to_owned(), None),
+            ("DRIVER_NAME".to_owned(), Some("rust-cql".to_owned())),
+            ("THROW_ON_OVERLOAD".to_owned(), Some("0".to_owned())),
+        ];
+        let mut expected = frame(
+            1,
+            Opcode::Startup,
+            b"\x00\x02\x00\x0bDRIVER_NAME\x00\x08rust-cql\x00\x11THROW_ON_OVERLOAD\x00\x010",
+        );
+        expected[0] = CQL_VERSION;
+        assert_eq!(
+            expected,
+            startup(true, Compression::None, &overrides)
+                .to_vec()
+                .unwrap()
+        );
+
+        let (addr, server) = mock_server(1, |_| unreachable!());
+        let builder = || {
+            ClientBuilder::new(&addr)
+                .protocol_version(CQL_VERSION)
+                .remove_startup_option("CQL_VERSION")
+                .startup_option("DRIVER_NAME", "rust-cql")
+        };
+        match builder().connect() {
+            Err(Error::MissingStartupOption(ref key)) => assert_eq!("CQL_VERSION", key),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        // Only algorithms the client has can be asked for.
+        let snappy = builder()
+            .nonstandard_startup(true)
+            .startup_option("COMPRESSION", "snappy");
+        match snappy.connect() {
+            Err(Error::UnsupportedCompression(ref name)) => assert_eq!("snappy", name),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        let client = builder().nonstandard_startup(true).connect().unwrap();
+        drop(client);
+        let mut expected = frame(
+            0,
+            Opcode::Startup,
+            b"\x00\x01\x00\x0bDRIVER_NAME\x00\x08rust-cql",
+        );
+        expected[0] = CQL_VERSION;
+        assert_eq!(vec![expected], server.join().unwrap());
+    }
+
+    #[test]
+    fn query_params_flags() {
+        let bytes = |params: QueryParams| {
+            let v = params.to_vec().unwrap();
+            assert_eq!(params.len_(), v.len());
+            v
+        };
+        // Without values, nothing but the consistency and empty flags.
+        assert_eq!(
+            vec![0, 4, 0],
+            bytes(QueryParamsBuilder::new(Consistency::Quorum).build())
+        );
+
+        let params = QueryParamsBuilder::new(Consistency::One)
+            .values(vec![7.into()])
+            .skip_metadata(true)
+            .page_size(100)
+            .paging_state(vec![9])
+            .serial_consistency(Consistency::LocalSerial)
+            .timestamp(1)
+            .build();
+        let mut expected = vec![0, 1, 0x3f, 0, 1, 0, 0, 0, 4, 0, 0, 0, 7];
+        expected.extend(&[0, 0, 0, 100, 0, 0, 0, 1, 9, 0, 9, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(expected, bytes(params));
+
+        let named = QueryParamsBuilder::new(Consistency::One)
+            .named_values(NamedValues(vec![("k".to_owned(), 7.into())]))
+            .unwrap()
+            .build();
+        assert_eq!(
+            vec![0, 1, 0x41, 0, 1, 0, 1, b'k', 0, 0, 0, 4, 0, 0, 0, 7],
+            bytes(named)
+        );
+    }
+
+    #[test]
+    fn client_query_with() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .default_page_size(50)
+            .connect()
+            .unwrap();
+        let query_str = "update t set v = 1 where k = 0 if v = 0";
+        let params = QueryParamsBuilder::new(Consistency::Quorum)
+            .serial_consistency(Consistency::Serial)
+            .build();
+        client.query_with(query_str, params).unwrap();
+        let select = "select v from t where k = 0";
+        let params = QueryParamsBuilder::new(Consistency::Serial).build();
+        client.query_with(select, params).unwrap();
+        drop(client);
+
+        let frames = server.join().unwrap();
+        // Not a SELECT, so the default page size doesn't apply.
+        let offset = 9 + 4 + query_str.len();
+        assert_eq!(&[0, 4, 0x10, 0, 8], &frames[1][offset..]);
+        // The client's default page size fills in.
+        let offset = 9 + 4 + select.len();
+        assert_eq!(&[0, 8, 0x04, 0, 0, 0, 50], &frames[2][offset..]);
+    }
+
+    #[test]
+    fn client_execute_skip_metadata() {
+        // One (id int, name varchar) row, without its metadata.
+        let mut rows = vec![0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 2, 0, 0, 0, 1];
+        rows.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 7, 0, 0, 0, 5]);
+        rows.extend_from_slice(b"seven");
+        let (addr, server) = mock_server(1, move |req| {
+            frame(frame_stream(req), Opcode::Result, &rows)
+        });
+
+        let mut known = vec![0, 0, 0, 1, 0, 0, 0, 2, 0, 2, b'k', b's', 0, 1, b't'];
+        known.extend_from_slice(&[0, 2, b'i', b'd', 0, 0x09, 0, 4]);
+        known.extend_from_slice(b"name\x00\x0d");
+        let mut prepared = statement(vec![1; 16]);
+        prepared.result_metadata = known.as_slice().read_cql_metadata().unwrap();
+
+        let mut client = Client::new(&addr).unwrap();
+        let params = QueryParamsBuilder::new(Consistency::One)
+            .skip_metadata(true)
+            .build();
+        let rows = client
+            .execute_with(&prepared, params)
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .rows()
+            .unwrap();
+        assert_eq!(1, rows.rows().len());
+        assert_eq!(7, rows.rows()[0].get::<i32>("id").unwrap());
+        assert_eq!("seven", rows.rows()[0].get::<String>("name").unwrap());
+        assert_eq!(Some("t"), rows.metadata().table());
+
+        // Without the statement's columns the rows can't be read.
+        let params = QueryParamsBuilder::new(Consistency::One).build();
+        match client.execute_with(&statement(vec![1; 16]), params) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        drop(client);
+
+        let frames = server.join().unwrap();
+        // The flags follow the id and the consistency.
+        assert_eq!(0x02, frames[1][9 + 18 + 2] & 0x02);
+    }
+
+    #[test]
+    fn client_serial_read_and_write() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let write = "update t set v = ? where k = 0 if v = 0";
+        client
+            .query_with_serial(
+                write,
+                Consistency::Quorum,
+                vec![1.into()],
+                Consistency::LocalSerial,
+            )
+            .unwrap();
+        let read = "select v from t where k = 0";
+        client.query(read, Consistency::Serial, Vec::new()).unwrap();
+        drop(client);
+
+        let frames = server.join().unwrap();
+        // Quorum, then values and LocalSerial as the serial consistency.
+        assert_eq!(
+            &[0, 4, 0x11, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1, 0, 9],
+            &frames[1][9 + 4 + write.len()..]
+        );
+        // Serial as the consistency itself, with no serial consistency.
+        assert_eq!(&[0, 8, 0], &frames[2][9 + 4 + read.len()..]);
+    }
+
+    #[test]
+    fn client_conditional_delete() {
+        // [applied] boolean, then v int of rust.test.
+        let mut rows = vec![
+            0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 4, b'r', b'u', b's', b't', 0, 4, b't', b'e',
+            b's', b't', 0, 9, b'[', b'a', b'p', b'p', b'l', b'i', b'e', b'd', b']', 0, 4, 0, 1,
+            b'v', 0, 9, 0, 0, 0, 1,
+        ];
+        rows.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 4, 0, 0, 0, 7]);
+        let applied: &[u8] = &[
+            0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, b'r', b'u', b's', b't', 0, 4, b't', b'e',
+            b's', b't', 0, 9, b'[', b'a', b'p', b'p', b'l', b'i', b'e', b'd', b']', 0, 4, 0, 0, 0,
+            1, 0, 0, 0, 1, 1,
+        ];
+        let mut responses = vec![RESULT_VOID.to_vec(), applied.to_vec(), rows].into_iter();
+        let (addr, server) = mock_server(1, move |req| {
+            frame(
+                frame_stream(req),
+                Opcode::Result,
+                &responses.next_back().unwrap(),
+            )
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let delete = statement(vec![0xab]);
+        let (applied, row) = client
+            .execute_cas(&delete, Consistency::Quorum, vec![1.into()])
+            .unwrap();
+        assert!(!applied);
+        let row = row.unwrap();
+        assert_eq!(7, row.get::<i32>("v").unwrap());
+        assert!(!row.get::<bool>("[applied]").unwrap());
+
+        let (applied, row) = client
+            .query_cas(
+                "delete from rust.test where k = 0 if exists",
+                Consistency::Quorum,
+                Vec::new(),
+            )
+            .unwrap();
+        assert!(applied);
+        assert!(row.is_none());
+
+        match client.query_cas(
+            "delete from rust.test where k = 0",
+            Consistency::One,
+            Vec::new(),
+        ) {
+            Err(Error::ColumnNotFound(ref name)) if name == "[applied]" => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn client_query_named() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let values = || {
+            NamedValues(vec![
+                ("id".to_owned(), "a".into()),
+                ("v".to_owned(), 1.into()),
+            ])
+        };
+        let query_str = "update t set v = :v where id = :id and v < :v";
+        client
+            .query_named(query_str, Consistency::One, values())
+            .unwrap();
+        client
+            .execute_named(&statement(vec![0xab]), Consistency::One, values())
+            .unwrap();
+
+        let mut twice = values();
+        twice.0.push(("v".to_owned(), 2.into()));
+        match client.query_named(query_str, Consistency::One, twice) {
+            Err(Error::DuplicateName(ref name)) => assert_eq!("v", name),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(client);
+
+        // One, the VALUES and names flags, then each name before its value.
+        let params: &[u8] =
+            b"\x00\x01\x41\x00\x02\x00\x02id\x00\x00\x00\x01a\x00\x01v\x00\x00\x00\x04\x00\x00\x00\x01";
+        let frames = server.join().unwrap();
+        assert_eq!(3, frames.len());
+        let mut query_body = vec![0, 0, 0, query_str.len() as u8];
+        query_body.extend_from_slice(query_str.as_bytes());
+        query_body.extend_from_slice(params);
+        assert_eq!(&query_body[..], &frames[1][9..]);
+        let mut execute_body = vec![0, 1, 0xab];
+        execute_body.extend_from_slice(params);
+        assert_eq!(&execute_body[..], &frames[2][9..]);
+    }
+
+    #[test]
+    fn rows_applied() {
+        // An INSERT ... IF NOT EXISTS that found ('a', 7) already there.
+        let mut body = vec![
+            0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 4, b'r', b'u', b's', b't', 0, 4, b't', b'e',
+            b's', b't', 0, 9, b'[', b'a', b'p', b'p', b'l', b'i', b'e', b'd', b']', 0, 4, 0, 2,
+            b'i', b'd', 0, 13, 0, 1, b'v', 0, 9, 0, 0, 0, 1,
+        ];
+        body.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 1, b'a', 0, 0, 0, 4, 0, 0, 0, 7]);
+        let rows = read_rows(&frame(0, Opcode::Result, &body));
+        assert_eq!(Some(false), rows.applied());
+        let current = &rows.rows()[0];
+        assert_eq!("a", current.get::<String>("id").unwrap());
+        assert_eq!(7, current.get::<i32>("v").unwrap());
+
+        let body = [
+            0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, b'r', b'u', b's', b't', 0, 4, b't', b'e',
+            b's', b't', 0, 9, b'[', b'a', b'p', b'p', b'l', b'i', b'e', b'd', b']', 0, 4, 0, 0, 0,
+            1, 0, 0, 0, 1, 1,
+        ];
+        assert_eq!(
+            Some(true),
+            read_rows(&frame(0, Opcode::Result, &body)).applied()
+        );
+        assert_eq!(None, read_rows(RESULT_INTS).applied());
+    }
+
+    #[test]
+    fn rows_column_iter() {
+        let mut rows = read_rows(RESULT_INTS);
+        rows.rows[1].cols[0] = Value::CqlNull;
+
+        let ids: Vec<Option<i32>> = rows.column_iter("id").collect::<Result<_>>().unwrap();
+        assert_eq!(vec![Some(1), None, Some(3)], ids);
+
+        let ids: Vec<Result<i32>> = rows.column_iter("id").collect();
+        assert_eq!(3, ids.len());
+        assert_eq!(1, *ids[0].as_ref().unwrap());
+        match ids[1] {
+            Err(Error::TypeMismatch) => (),
+            ref id => panic!("unexpected id: {:?}", id),
+        }
+        assert_eq!(
+            4,
+            rows.column_iter::<i32>("id")
+                .filter_map(|id| id.ok())
+                .sum::<i32>()
+        );
+
+        let missing: Vec<Result<i32>> = rows.column_iter("nope").collect();
+        match missing.as_slice() {
+            [Err(Error::ColumnNotFound(ref name))] => assert_eq!("nope", name),
+            missing => panic!("unexpected result: {:?}", missing),
+        }
+    }
+
+    #[test]
+    fn value_null_and_type_name() {
+        assert!(Value::CqlNull.is_null());
+        assert!(!Value::CqlInt(0).is_null());
+        assert!(!Value::CqlList(vec![Value::CqlNull]).is_null());
+
+        assert_eq!("null", Value::CqlNull.type_name());
+        assert_eq!("int", Value::CqlInt(1).type_name());
+        assert_eq!("text", Value::CqlText(String::new()).type_name());
+        assert_eq!("varchar", Value::CqlVarChar(String::new()).type_name());
+        assert_eq!("timeuuid", Value::CqlTimeUUID([0; 16]).type_name());
+        assert_eq!("list", Value::CqlList(vec![]).type_name());
+        assert_eq!("map", Value::CqlMap(vec![]).type_name());
+        assert_eq!("tuple", Value::CqlTuple(vec![]).type_name());
+    }
+
+    #[test]
+    fn value_from() {
+        let ip: IpAddr = "::1".parse().unwrap();
+        let cases: Vec<(Value, Value)> = vec![
+            (1.into(), Value::CqlInt(1)),
+            (2i64.into(), Value::CqlBigint(2)),
+            (1.2f32.into(), Value::CqlFloat(1.2)),
+            (1.5f64.into(), Value::CqlDouble(1.5)),
+            (false.into(), Value::CqlBoolean(false)),
+            ("a".into(), Value::CqlVarChar("a".to_owned())),
+            ("b".to_owned().into(), Value::CqlVarChar("b".to_owned())),
+            (vec![1u8, 2].into(), Value::CqlBlob(vec![1, 2])),
+            (ip.into(), Value::CqlInet(ip)),
+            ([7; 16].into(), Value::CqlUUID([7; 16])),
+            (Some(3).into(), Value::CqlInt(3)),
+            (None::<i32>.into(), Value::CqlNull),
+        ];
+        for (from, expected) in cases {
+            assert_eq!(format!("{:?}", expected), format!("{:?}", from));
+        }
+    }
+
+    #[test]
+    fn client_password_auth() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut tokens = Vec::new();
+            let mut served = 0;
+            while served < 3 {
+                let (mut socket, _) = listener.accept().unwrap();
+                served += 1;
+                while let Ok(mut req) = socket.read_bytes(9) {
+                    let len = (&req[5..9]).read_u32::<BigEndian>().unwrap();
+                    req.extend(socket.read_bytes(len as usize).unwrap());
+                    if refuse_version(&mut socket, &req) {
+                        served -= 1;
+                        break;
+                    }
+                    let stream = frame_stream(&req);
+                    let resp = match opcode(req[4]) {
+                        Opcode::Startup => {
+                            let mut body = vec![0, 47];
+                            body.extend_from_slice(
+                                b"org.apache.cassandra.auth.PasswordAuthenticator",
+                            );
+                            frame(stream, Opcode::Auth, &body)
+                        }
+                        Opcode::AuthResponse => {
+                            let token = req[13..].to_vec();
+                            let ok = token == b"\0cassandra\0secret";
+                            tokens.push(token);
+                            if ok {
+                                frame(stream, Opcode::AuthSuccess, &[0xff, 0xff, 0xff, 0xff])
+                            } else {
+                                let mut body = vec![0, 0, 1, 0, 0, 3];
+                                body.extend_from_slice(b"bad");
+                                frame(stream, Opcode::Error, &body)
+                            }
+                        }
+                        _ => frame(stream, Opcode::Result, RESULT_VOID),
+                    };
+                    socket.write_all(&resp).unwrap();
+                }
+            }
+            tokens
+        });
+
+        let mut client = Client::with_credentials(&addr, "cassandra", "secret").unwrap();
+        client.rows("select * from rust.test").unwrap();
+        drop(client);
+
+        match Client::with_credentials(&addr, "cassandra", "wrong") {
+            Err(ref e @ Error::Cql { .. }) => {
+                assert_eq!(Some(ErrorCode::BadCredentials), e.error_code())
+            }
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        match Client::new(&addr) {
+            Err(Error::AuthRequired(ref authenticator)) => {
+                assert!(authenticator.ends_with("PasswordAuthenticator"))
+            }
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+
+        let tokens = server.join().unwrap();
+        assert_eq!(
+            vec![
+                b"\0cassandra\0secret".to_vec(),
+                b"\0cassandra\0wrong".to_vec()
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn client_batch() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        match client.batch(BatchType::Logged, Vec::new(), Consistency::One) {
+            Err(Error::EmptyBatch) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        let queries = vec![
+            BatchQuery::Query(
+                "insert into t (a) values (?)".to_owned(),
+                vec![Value::CqlInt(1)],
+            ),
+            BatchQuery::Prepared(vec![0xa0, 0xa1], Vec::new()),
+        ];
+        match client.batch(BatchType::Unlogged, queries, Consistency::Quorum) {
+            Ok(ResponseResult::Void) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(client);
+
+        let frames = server.join().unwrap();
+        assert_eq!(2, frames.len());
+        let mut body = vec![1, 0, 2, 0, 0, 0, 0, 28];
+        body.extend_from_slice(b"insert into t (a) values (?)");
+        body.extend_from_slice(&[0, 1, 0, 0, 0, 4, 0, 0, 0, 1]);
+        body.extend_from_slice(&[1, 0, 2, 0xa0, 0xa1, 0, 0]);
+        body.extend_from_slice(&[0, 4, 0]);
+        let mut expected = frame(1, Opcode::Batch, &body);
+        expected[0] = CQL_VERSION;
+        assert_eq!(expected, frames[1]);
+    }
+
+    #[test]
+    fn client_query_unpaged() {
+        let (addr, server) = mock_server(1, |req| match opcode(req[4]) {
+            Opcode::Query => respond(req, RESULT_SELECT),
+            _ => respond(req, RESULT_SELECT_PAGED),
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .default_page_size(100)
+            .connect()
+            .unwrap();
+        let rows = client
+            .query_unpaged("select * from rust.test", Consistency::One, Vec::new())
+            .unwrap();
+        assert_eq!(1, rows.unwrap().rows().len());
+        let mut select = statement(vec![0xa0]);
+        select.result_metadata = read_rows(RESULT_SELECT).metadata().clone();
+        match client.execute_unpaged(&select, Consistency::One, Vec::new()) {
+            Err(Error::MorePages) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        drop(client);
+
+        // Neither request has a page size, despite the default.
+        let frames = server.join().unwrap();
+        let query_flags = 9 + 4 + "select * from rust.test".len() + 2;
+        assert_eq!(Opcode::Query as u8, frames[1][4]);
+        assert_eq!(0, frames[1][query_flags] & 0x04);
+        assert_eq!(Opcode::Execute as u8, frames[2][4]);
+        assert_eq!(0, frames[2][9 + 3 + 2] & 0x04);
+    }
+
+    // `select v from rust.custom`, with v of class org.example.Point.
+    const RESULT_CUSTOM: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 63, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 4, 114, 117, 115, 116,
+        0, 6, 99, 117, 115, 116, 111, 109, 0, 1, 118, 0, 0, 0, 17, 111, 114, 103, 46, 101, 120, 97,
+        109, 112, 108, 101, 46, 80, 111, 105, 110, 116, 0, 0, 0, 1, 0, 0, 0, 5, 1, 2, 3, 4, 5,
+    ];
+
+    #[test]
+    fn custom_round_trip() {
+        let rows = read_rows(RESULT_CUSTOM);
+        let value = rows.rows()[0].get_column("v").unwrap();
+        match value {
+            Value::CqlCustom(ref name, ref data) => {
+                assert_eq!("org.example.Point", name);
+                assert_eq!(&[1, 2, 3, 4, 5], data.as_slice());
+            }
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+
+        // Bound again, it's the same [bytes] as was read.
+        let column = &RESULT_CUSTOM[RESULT_CUSTOM.len() - 9..];
+        assert_eq!(column, value.to_vec().unwrap().as_slice());
+        let req = query(
+            0,
+            "insert into rust.custom (v) values (?)",
+            Consistency::One,
+            vec![value],
+        );
+        assert!(req.to_vec().unwrap().ends_with(column));
+    }
+
+    #[test]
+    fn client_query_paged() {
+        let mut pages = vec![RESULT_SELECT_PAGED, RESULT_SELECT].into_iter();
+        let (addr, server) = mock_server(1, move |req| respond(req, pages.next().unwrap()));
+
+        let mut client = Client::new(&addr).unwrap();
+        let query_str = "select * from rust.test";
+        let (rows, paging_state) = client
+            .query_paged(query_str, Consistency::One, Vec::new(), 2, None)
+            .unwrap();
+        assert_eq!(2, rows.rows().len());
+        assert_eq!(Some(vec![1, 2, 3, 4]), paging_state);
+        let (rows, paging_state) = client
+            .query_paged(query_str, Consistency::One, Vec::new(), 2, paging_state)
+            .unwrap();
+        assert_eq!(1, rows.rows().len());
+        assert_eq!(None, paging_state);
+        drop(client);
+
+        let frames = server.join().unwrap();
+        let params = 9 + 4 + query_str.len();
+        // No values, so page size and then paging state.
+        assert_eq!(&[0, 1, 0x04, 0, 0, 0, 2], &frames[1][params..]);
+        assert_eq!(
+            &[0, 1, 0x0c, 0, 0, 0, 2, 0, 0, 0, 4, 1, 2, 3, 4],
+            &frames[2][params..]
+        );
+    }
+
+    #[test]
+    fn metadata_column_index() {
+        let rows = read_rows(RESULT_SELECT);
+        assert_eq!(Some(0), rows.metadata().column_index("id"));
+        assert_eq!(Some(1), rows.metadata().column_index("value"));
+        assert_eq!(None, rows.metadata().column_index("nope"));
+
+        let row = &rows.rows()[0];
+        match row.get_column("value") {
+            Some(Value::CqlFloat(_)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        assert!(row.get_column("nope").is_none());
+        match row.clone().take("id") {
+            Some(Value::CqlVarChar(ref id)) => assert_eq!("asdf", id),
+            v => panic!("unexpected value: {:?}", v),
+        }
+
+        // A name selected twice finds the first column.
+        let mut columns = rows.metadata().columns().to_vec();
+        columns.push(columns[0].clone());
+        assert_eq!(Some(&0), index_columns(&columns).get("id"));
+    }
+
+    #[test]
+    fn client_prepare_and_execute() {
+        let mut prepares = 0;
+        let (addr, server) = mock_server(2, move |req| {
+            if req[4] == Opcode::Prepare as u8 {
+                prepares += 1;
+                let mut resp = respond(req, RESULT_PREPARED);
+                resp[15] = prepares;
+                return resp;
+            }
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let query_str = "insert into t (v2) values (?)";
+        let values = vec![Value::CqlFloat(1.0)];
+        for &cache in &[false, true] {
+            let mut client = ClientBuilder::new(&addr)
+                .cache_prepared(cache)
+                .connect()
+                .unwrap();
+            for _ in 0..2 {
+                match client.prepare_and_execute(query_str, Consistency::One, values.clone()) {
+                    Ok(ResponseResult::Void) => (),
+                    result => panic!("unexpected result: {:?}", result),
+                }
+            }
+        }
+
+        let frames = server.join().unwrap();
+        let opcodes: Vec<u8> = frames.iter().map(|req| req[4]).collect();
+        let (startup, prepare, execute) = (
+            Opcode::Startup as u8,
+            Opcode::Prepare as u8,
+            Opcode::Execute as u8,
+        );
+        assert_eq!(
+            vec![startup, prepare, execute, prepare, execute, startup, prepare, execute, execute],
+            opcodes
+        );
+        // The cached id is the one prepared on that connection.
+        assert_eq!(3, frames[7][11]);
+        assert_eq!(3, frames[8][11]);
+    }
+
+    // RESULT_SELECT with its body compressed by lz4.
+    const RESULT_SELECT_LZ4: &[u8] = &[
+        131, 1, 0, 0, 8, 0, 0, 0, 59, 0, 0, 0, 59, 129, 0, 0, 0, 2, 0, 0, 0, 1, 8, 0, 243, 11, 4,
+        114, 117, 115, 116, 0, 4, 116, 101, 115, 116, 0, 2, 105, 100, 0, 13, 0, 5, 118, 97, 108,
+        117, 101, 0, 8, 35, 0, 208, 4, 97, 115, 100, 102, 0, 0, 0, 4, 63, 158, 4, 25,
+    ];
+
+    #[test]
+    fn resp_compressed_without_compression() {
+        let mut frame = RESULT_SELECT_LZ4;
+        match frame.read_cql_response(CQL_VERSION) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected response: {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4_length_limit() {
+        // A few bytes claiming to inflate past the frame limit.
+        for len in &[MAX_FRAME_BODY as i32 + 1, i32::MAX, -1] {
+            let mut body = len.to_be_bytes().to_vec();
+            body.extend_from_slice(&[0x10, 0]);
+            match Compression::Lz4.decompress(&body) {
+                Err(Error::Protocol) => (),
+                r => panic!("unexpected result for {}: {:?}", len, r),
+            }
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn resp_result_select_lz4() {
+        let mut frame = RESULT_SELECT_LZ4;
+        let resp = frame
+            .read_cql_response_compressed(CQL_VERSION, Compression::Lz4)
+            .unwrap();
+        assert_eq!(0, resp.header.flags() & COMPRESSED);
+        let rows = match resp.body {
+            ResponseBody::Result(ResponseResult::Rows(rows)) => rows,
+            body => panic!("unexpected body: {:?}", body),
+        };
+        assert_eq!(
+            format!(
+                "{:?}",
+                read_rows(RESULT_SELECT).rows()[0].clone().into_values()
+            ),
+            format!("{:?}", rows.rows()[0].clone().into_values())
+        );
+
+        // The uncompressed size must match the block.
+        let mut bad = RESULT_SELECT_LZ4.to_vec();
+        bad[12] = 60;
+        match bad
+            .as_slice()
+            .read_cql_response_compressed(CQL_VERSION, Compression::Lz4)
+        {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected response: {:?}", r),
+        }
+    }
+
+    // `select v, writetime(v), ttl(v) from rust.test`: ("a", 1500000000000000,
+    // 3600), then ("b", 1500000000000001, null).
+    const RESULT_WRITETIME_TTL: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 105, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 4, 114, 117, 115,
+        116, 0, 4, 116, 101, 115, 116, 0, 1, 118, 0, 13, 0, 12, 119, 114, 105, 116, 101, 116, 105,
+        109, 101, 40, 118, 41, 0, 2, 0, 6, 116, 116, 108, 40, 118, 41, 0, 9, 0, 0, 0, 2, 0, 0, 0,
+        1, 97, 0, 0, 0, 8, 0, 5, 84, 61, 247, 41, 192, 0, 0, 0, 0, 4, 0, 0, 14, 16, 0, 0, 0, 1, 98,
+        0, 0, 0, 8, 0, 5, 84, 61, 247, 41, 192, 1, 255, 255, 255, 255,
+    ];
+
+    // `select id, a, b, c from rust.wide` of int, varchar, bigint and
+    // boolean columns: (1, "x", 10, true), then (2, null, 20, false).
+    const RESULT_WIDE: &[u8] = &[
+        131, 0, 0, 0, 8, 0, 0, 0, 108, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 4, 0, 4, 114, 117, 115,
+        116, 0, 4, 119, 105, 100, 101, 0, 2, 105, 100, 0, 9, 0, 1, 97, 0, 13, 0, 1, 98, 0, 2, 0, 1,
+        99, 0, 4, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 1, 120, 0, 0, 0, 8, 0, 0, 0, 0, 0,
+        0, 0, 10, 0, 0, 0, 1, 1, 0, 0, 0, 4, 0, 0, 0, 2, 255, 255, 255, 255, 0, 0, 0, 8, 0, 0, 0,
+        0, 0, 0, 0, 20, 0, 0, 0, 1, 0,
+    ];
+
+    #[test]
+    fn rows_project() {
+        let rows = read_rows(RESULT_WIDE);
+        let projected = rows.project(&["a", "id"]).unwrap();
+
+        let metadata = projected.metadata();
+        assert_eq!(2, metadata.column_count());
+        let names: Vec<&str> = metadata.columns().iter().map(|c| c.name()).collect();
+        assert_eq!(vec!["a", "id"], names);
+        assert_eq!(Some(0), metadata.column_index("a"));
+        assert_eq!(None, metadata.column_index("b"));
+        assert_eq!(Some("wide"), metadata.table());
+
+        let values: Vec<(Option<String>, i32)> = projected
+            .rows()
+            .iter()
+            .map(|row| (row.get("a").unwrap(), row.get("id").unwrap()))
+            .collect();
+        assert_eq!(vec![(Some("x".to_owned()), 1), (None, 2)], values);
+        // The original is left as it was.
+        assert_eq!(4, rows.rows()[1].clone().into_values().len());
+
+        match rows.project(&["id", "nope"]) {
+            Err(Error::ColumnNotFound(ref name)) => assert_eq!("nope", name),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn row_writetime_and_ttl() {
+        let rows = read_rows(RESULT_WRITETIME_TTL);
+        let (first, second) = (&rows.rows()[0], &rows.rows()[1]);
+        match first.get_column("writetime(v)") {
+            Some(Value::CqlBigint(1_500_000_000_000_000)) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        assert_eq!(Some(1_500_000_000_000_000), first.writetime("v"));
+        assert_eq!(Some(3600), first.ttl("v"));
+        assert_eq!(Some(1_500_000_000_000_001), second.writetime("v"));
+        assert_eq!(None, second.ttl("v"));
+        assert_eq!(None, first.writetime("w"));
+    }
+
+    #[test]
+    fn row_get() {
+        let rows = read_rows(RESULT_SELECT);
+        let row = &rows.rows()[0];
+        let id: String = row.get("id").unwrap();
+        assert_eq!("asdf", id);
+        assert_eq!(1.2345, row.get::<f32>("value").unwrap());
+        assert_eq!(Some(1.2345), row.get::<Option<f32>>("value").unwrap());
+        match row.get::<f64>("value") {
+            Err(Error::TypeMismatch) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        match row.get::<f32>("nope") {
+            Err(Error::ColumnNotFound(ref name)) => assert_eq!("nope", name),
+            v => panic!("unexpected value: {:?}", v),
+        }
+
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(ip, IpAddr::from_cql_value(Value::CqlInet(ip)).unwrap());
+        assert_eq!(1.5, f64::from_cql_value(Value::CqlDouble(1.5)).unwrap());
+        assert!(bool::from_cql_value(Value::CqlBoolean(true)).unwrap());
+        assert_eq!(
+            vec![1, 2],
+            Vec::<u8>::from_cql_value(Value::CqlBlob(vec![1, 2])).unwrap()
+        );
+        match bool::from_cql_value(Value::CqlNull) {
+            Err(Error::TypeMismatch) => (),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    // Answers OPTIONS with lz4 among the compression algorithms, and
+    // anything else with a compressed RESULT_SELECT.
+    fn compression_server() -> (String, thread::JoinHandle<Vec<Vec<u8>>>) {
+        mock_server(1, |req| {
+            if req[4] != Opcode::Opts as u8 {
+                return respond(req, RESULT_SELECT_LZ4);
+            }
+            let mut body = vec![0, 1];
+            ShortString("COMPRESSION").serialize(&mut body).unwrap();
+            body.extend(&[0, 2]);
+            ShortString("snappy").serialize(&mut body).unwrap();
+            ShortString("lz4").serialize(&mut body).unwrap();
+            frame(frame_stream(req), Opcode::Supported, &body)
+        })
+    }
+
+    #[cfg(not(feature = "lz4"))]
+    #[test]
+    fn client_compression_unavailable() {
+        let (addr, server) = compression_server();
+        let client = ClientBuilder::new(&addr)
+            .compression(true)
+            .connect()
+            .unwrap();
+        assert_eq!(Compression::None, client.compression());
+        drop(client);
+
+        let frames = server.join().unwrap();
+        assert_eq!(Opcode::Opts as u8, frames[0][4]);
+        assert!(!String::from_utf8_lossy(&frames[1]).contains("COMPRESSION"));
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn client_compression_lz4() {
+        let (addr, server) = compression_server();
+        let mut client = ClientBuilder::new(&addr)
+            .compression(true)
+            .compression_threshold(64)
+            .connect()
+            .unwrap();
+        assert_eq!(Compression::Lz4, client.compression());
+        let short = "select * from rust.test".to_owned();
+        let long = format!(
+            "select * from rust.test where id in ({})",
+            vec!["'a'"; 32].join(", ")
+        );
+        for query_str in &[&short, &long] {
+            let rows = client
+                .query_rows(query_str, Consistency::One, Vec::new())
+                .unwrap()
+                .unwrap();
+            assert_eq!(1, rows.rows().len());
+        }
+        drop(client);
+
+        let frames = server.join().unwrap();
+        assert_eq!(Opcode::Opts as u8, frames[0][4]);
+        assert!(String::from_utf8_lossy(&frames[1]).contains("COMPRESSION\0\x03lz4"));
+        // Only the long query is compressed, and inflates to the original.
+        assert_eq!(0, frames[2][1] & COMPRESSED);
+        assert_eq!(COMPRESSED, frames[3][1] & COMPRESSED);
+        let req = query(0, &long, Consistency::One, Vec::new())
+            .to_vec()
+            .unwrap();
+        let body = Compression::Lz4.decompress(&frames[3][9..]).unwrap();
+        // The timestamp differs.
+        assert_eq!(&req[9..req.len() - 8], &body[..body.len() - 8]);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn client_compression_from_startup_options() {
+        // Compression asked for in the startup options alone, without
+        // OPTIONS, is used from the first response on.
+        let (addr, server) = compression_server();
+        let mut client = ClientBuilder::new(&addr)
+            .remove_startup_option("CQL_VERSION")
+            .nonstandard_startup(true)
+            .startup_option("COMPRESSION", "lz4")
+            .connect()
+            .unwrap();
+        assert_eq!(Compression::Lz4, client.compression());
+        let rows = client
+            .query_rows("select * from rust.test", Consistency::One, Vec::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, rows.rows().len());
+        drop(client);
+        let frames = server.join().unwrap();
+        assert_eq!(Opcode::Startup as u8, frames[0][4]);
+
+        // Left out of STARTUP, nothing is compressed, though the server
+        // supports it.
+        let (addr, server) = mock_server(1, |req| {
+            if req[4] != Opcode::Opts as u8 {
+                return frame(frame_stream(req), Opcode::Result, RESULT_VOID);
+            }
+            let mut body = vec![0, 1];
+            ShortString("COMPRESSION").serialize(&mut body).unwrap();
+            body.extend(&[0, 1]);
+            ShortString("lz4").serialize(&mut body).unwrap();
+            frame(frame_stream(req), Opcode::Supported, &body)
+        });
+        let mut client = ClientBuilder::new(&addr)
+            .compression(true)
+            .compression_threshold(0)
+            .remove_startup_option("COMPRESSION")
+            .connect()
+            .unwrap();
+        assert_eq!(Compression::None, client.compression());
+        client
+            .query("insert into t (a) values (1)", Consistency::One, Vec::new())
+            .unwrap();
+        drop(client);
+        let frames = server.join().unwrap();
+        assert!(!String::from_utf8_lossy(&frames[1]).contains("COMPRESSION"));
+        assert_eq!(0, frames[2][1] & COMPRESSED);
+    }
+
+    #[test]
+    fn client_too_many_values() {
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let values = vec![Value::CqlInt(1); 70000];
+        match client.query(
+            "select * from t where id in ?",
+            Consistency::One,
+            values.clone(),
+        ) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let queries = vec![BatchQuery::Query(
+            "insert into t (id) values (?)".to_owned(),
+            values,
+        )];
+        match client.batch(BatchType::Logged, queries, Consistency::One) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // Nothing was sent, so the connection is still good.
+        let values = vec![Value::CqlInt(1); usize::from(u16::MAX)];
+        client
+            .query("select * from t where id in ?", Consistency::One, values)
+            .unwrap();
+        drop(client);
+
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    const EVENT_STATUS_CHANGE: &[u8] =
+        b"\x00\x0dSTATUS_CHANGE\x00\x04DOWN\x04\x0a\x00\x00\x01\x00\x00\x23\x52";
+    const EVENT_SCHEMA_CHANGE: &[u8] =
+        b"\x00\x0dSCHEMA_CHANGE\x00\x07CREATED\x00\x05TABLE\x00\x04rust\x00\x04test";
+
+    #[test]
+    fn resp_events() {
+        let mut topology = b"\x00\x0fTOPOLOGY_CHANGE\x00\x08NEW_NODE\x10".to_vec();
+        topology.extend(&[0; 15]);
+        topology.extend(&[1, 0, 0, 0x23, 0x52]);
+        let event = |body: &[u8]| {
+            let frame = frame(-1, Opcode::Event, body);
+            match frame
+                .as_slice()
+                .read_cql_response(CQL_VERSION)
+                .unwrap()
+                .body
+            {
+                ResponseBody::Event(event) => event,
+                body => panic!("unexpected body: {:?}", body),
+            }
+        };
+
+        assert_eq!(
+            ServerEvent::TopologyChange {
+                change: TopologyChangeType::NewNode,
+                node: "[::1]:9042".parse().unwrap(),
+            },
+            event(&topology)
+        );
+        let moved =
+            b"\x00\x0fTOPOLOGY_CHANGE\x00\x0aMOVED_NODE\x04\x0a\x00\x00\x02\x00\x00\x23\x52";
+        assert_eq!(
+            ServerEvent::TopologyChange {
+                change: TopologyChangeType::MovedNode,
+                node: "10.0.0.2:9042".parse().unwrap(),
+            },
+            event(moved)
+        );
+        assert_eq!(
+            ServerEvent::StatusChange {
+                change: StatusChangeType::Down,
+                node: "10.0.0.1:9042".parse().unwrap(),
+            },
+            event(EVENT_STATUS_CHANGE)
+        );
+        assert_eq!(
+            ServerEvent::SchemaChange(SchemaChange {
+                change: SchemaChangeType::Created,
+                target: "TABLE".to_owned(),
+                keyspace: "rust".to_owned(),
+                name: Some("test".to_owned()),
+                arg_types: Vec::new(),
+            }),
+            event(EVENT_SCHEMA_CHANGE)
+        );
+        let dropped = b"\x00\x0dSCHEMA_CHANGE\x00\x07DROPPED\x00\x08KEYSPACE\x00\x04rust";
+        match event(dropped) {
+            ServerEvent::SchemaChange(SchemaChange {
+                change: SchemaChangeType::Dropped,
+                name: None,
+                ..
+            }) => (),
+            e => panic!("unexpected event: {:?}", e),
+        }
+        let function = b"\x00\x0dSCHEMA_CHANGE\x00\x07CREATED\x00\x08FUNCTION\x00\x04rust\x00\x03add\x00\x02\x00\x03int\x00\x06bigint";
+        assert_eq!(
+            ServerEvent::SchemaChange(SchemaChange {
+                change: SchemaChangeType::Created,
+                target: "FUNCTION".to_owned(),
+                keyspace: "rust".to_owned(),
+                name: Some("add".to_owned()),
+                arg_types: vec!["int".to_owned(), "bigint".to_owned()],
+            }),
+            event(function)
+        );
+        let aggregate =
+            b"\x00\x0dSCHEMA_CHANGE\x00\x07DROPPED\x00\x09AGGREGATE\x00\x04rust\x00\x03sum\x00\x00";
+        match event(aggregate) {
+            ServerEvent::SchemaChange(SchemaChange {
+                change: SchemaChangeType::Dropped,
+                ref target,
+                ref name,
+                ref arg_types,
+                ..
+            }) => {
+                assert_eq!("AGGREGATE", target);
+                assert_eq!(&Some("sum".to_owned()), name);
+                assert!(arg_types.is_empty());
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+        assert_eq!(
+            ServerEvent::Other("NOPE".to_owned()),
+            event(b"\x00\x04NOPE\x00\x01?")
+        );
+
+        // Changes, and schema change targets, newer than the client.
+        let unknown: &[(&str, &[u8])] = &[
+            ("STATUS_CHANGE", b"\x00\x0dSTATUS_CHANGE\x00\x04GONE"),
+            (
+                "TOPOLOGY_CHANGE",
+                b"\x00\x0fTOPOLOGY_CHANGE\x00\x0aSPLIT_NODE",
+            ),
+            (
+                "SCHEMA_CHANGE",
+                b"\x00\x0dSCHEMA_CHANGE\x00\x07RENAMED\x00\x08KEYSPACE\x00\x04rust",
+            ),
+            (
+                "SCHEMA_CHANGE",
+                b"\x00\x0dSCHEMA_CHANGE\x00\x07CREATED\x00\x04VIEW\x00\x04rust\x00\x01v",
+            ),
+        ];
+        for &(event_type, body) in unknown {
+            assert_eq!(ServerEvent::Other(event_type.to_owned()), event(body));
+        }
+
+        // A RESULT has no Other to fall back on, so an unknown target there
+        // is still an error.
+        let mut result = vec![0, 0, 0, 5];
+        result.extend_from_slice(b"\x00\x07CREATED\x00\x04VIEW\x00\x04rust\x00\x01v");
+        let result = frame(0, Opcode::Result, &result);
+        match result.as_slice().read_cql_response(CQL_VERSION) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected response: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn client_poll_event() {
+        let (addr, server) = mock_server(1, |req| {
+            if req[4] == Opcode::Register as u8 {
+                let mut resp = frame(frame_stream(req), Opcode::Ready, &[]);
+                resp.extend(frame(-1, Opcode::Event, EVENT_STATUS_CHANGE));
+                return resp;
+            }
+            let mut resp = frame(-1, Opcode::Event, EVENT_SCHEMA_CHANGE);
+            resp.extend(frame(frame_stream(req), Opcode::Result, RESULT_VOID));
+            resp
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        client
+            .register(&[EventType::StatusChange, EventType::SchemaChange])
+            .unwrap();
+        // Read off the socket.
+        match client.poll_event().unwrap() {
+            ServerEvent::StatusChange { change, .. } => {
+                assert_eq!(StatusChangeType::Down, change)
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+        // Kept while waiting for the query's response.
+        client
+            .query("insert into t (a) values (1)", Consistency::One, Vec::new())
+            .unwrap();
+        match client.poll_event().unwrap() {
+            ServerEvent::SchemaChange(ref change) => {
+                assert_eq!(SchemaChangeType::Created, change.change)
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn too_long_strings() {
+        let long = "k".repeat(70000);
+        match ShortString(&long).to_vec() {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // Fits a [long string].
+        assert_eq!(4 + 70000, LongString(&long).to_vec().unwrap().len());
+
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+        let mut client = Client::new(&addr).unwrap();
+        match client.execute(&statement(vec![0; 70000]), Consistency::One, Vec::new()) {
+            Err(Error::Protocol) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let values = vec![Value::CqlBlob(vec![0; 70000])];
+        client
+            .query("insert into t (b) values (?)", Consistency::One, values)
+            .unwrap();
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    // `frame` in protocol v4.
+    fn frame_v4(stream: i16, opcode: Opcode, flags: u8, body: &[u8]) -> Vec<u8> {
+        let mut v = frame(stream, opcode, body);
+        v[0] = 0x80 | CQL_VERSION_V4;
+        v[1] = flags;
+        v
+    }
+
+    #[test]
+    fn resp_v4_warnings() {
+        let mut body = vec![0, 1];
+        ShortString("Batch too large").serialize(&mut body).unwrap();
+        body.extend(&[0, 1]);
+        ShortString("k").serialize(&mut body).unwrap();
+        body.extend(&[0, 0, 0, 1, b'v']);
+        body.extend(RESULT_VOID);
+
+        let frame = frame_v4(0, Opcode::Result, WARNING | CUSTOM_PAYLOAD, &body);
+        let resp = frame.as_slice().read_cql_response(CQL_VERSION_V4).unwrap();
+        assert_eq!(4, resp.header().protocol_version());
+        assert_eq!(&["Batch too large".to_owned()], resp.warnings());
+        match resp.body {
+            ResponseBody::Result(ResponseResult::Void) => (),
+            body => panic!("unexpected body: {:?}", body),
+        }
+
+        // The same flags mean nothing to v3.
+        let mut frame = frame_v4(0, Opcode::Result, WARNING, RESULT_VOID);
+        frame[0] = 0x80 | CQL_VERSION;
+        let resp = frame.as_slice().read_cql_response(CQL_VERSION).unwrap();
+        assert!(resp.warnings().is_empty());
+    }
+
+    #[test]
+    fn resp_traced() {
+        let trace_id = [0x5a; 16];
+        let mut body = trace_id.to_vec();
+        body.extend_from_slice(&RESULT_SELECT[9..]);
+        let mut frame = frame(0, Opcode::Result, &body);
+        frame[1] = TRACING;
+        let resp = frame.as_slice().read_cql_response(CQL_VERSION).unwrap();
+        assert_eq!(Some(trace_id), resp.tracing_id());
+        let rows = resp.into_result().unwrap().rows().unwrap();
+        assert_eq!(1, rows.rows().len());
+        assert_eq!("asdf", rows.rows()[0].get::<String>("id").unwrap());
+
+        // In v4 the trace id comes before the warnings.
+        let mut body = trace_id.to_vec();
+        body.extend(&[0, 1]);
+        ShortString("slow").serialize(&mut body).unwrap();
+        body.extend(RESULT_VOID);
+        let frame = frame_v4(0, Opcode::Result, TRACING | WARNING, &body);
+        let resp = frame.as_slice().read_cql_response(CQL_VERSION_V4).unwrap();
+        assert_eq!(Some(trace_id), resp.tracing_id());
+        assert_eq!(&["slow".to_owned()], resp.warnings());
+
+        let untraced = RESULT_SELECT.to_vec();
+        let resp = untraced.as_slice().read_cql_response(CQL_VERSION).unwrap();
+        assert_eq!(None, resp.tracing_id());
+    }
+
+    #[test]
+    fn resp_v4_prepared_pk_indexes() {
+        // RESULT_PREPARED with its one bind marker listed as partition key.
+        let mut body = RESULT_PREPARED[9..39].to_vec();
+        body.extend(&[0, 0, 0, 1, 0, 0]);
+        body.extend(&RESULT_PREPARED[39..]);
+        let frame = frame_v4(0, Opcode::Result, 0, &body);
+        match frame
+            .as_slice()
+            .read_cql_response(CQL_VERSION_V4)
+            .unwrap()
+            .body
+        {
+            ResponseBody::Result(ResponseResult::Prepared(id, metadata, _)) => {
+                assert_eq!(&RESULT_PREPARED[15..31], id.as_slice());
+                assert_eq!("v2", metadata.columns()[0].name());
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn client_negotiates_version() {
+        // A v3-only server refuses v4, so the client reconnects with v3.
+        let (addr, server) = mock_server(1, |req| {
+            frame(frame_stream(req), Opcode::Result, RESULT_VOID)
+        });
+        let mut client = Client::new(&addr).unwrap();
+        assert_eq!(CQL_VERSION, client.protocol_version());
+        client
+            .query("use rust", Consistency::One, Vec::new())
+            .unwrap();
+        drop(client);
+        let frames = server.join().unwrap();
+        assert!(frames.iter().all(|req| req[0] == CQL_VERSION));
+
+        // Asked for one version, it doesn't try another.
+        let (addr, server) = mock_server(1, |_| unreachable!());
+        match ClientBuilder::new(&addr)
+            .protocol_version(CQL_VERSION_V4)
+            .connect()
+        {
+            Err(Error::ProtocolVersion {
+                expected: 0x84,
+                actual: 0x83,
+            }) => (),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        let client = ClientBuilder::new(&addr)
+            .protocol_version(CQL_VERSION)
+            .connect()
+            .unwrap();
+        drop(client);
+        assert_eq!(1, server.join().unwrap().len());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut versions = Vec::new();
+            while let Ok(mut req) = socket.read_bytes(9) {
+                let len = (&req[5..9]).read_u32::<BigEndian>().unwrap();
+                req.extend(socket.read_bytes(len as usize).unwrap());
+                versions.push(req[0]);
+                let resp = match opcode(req[4]) {
+                    Opcode::Startup => frame_v4(frame_stream(&req), Opcode::Ready, 0, &[]),
+                    _ => {
+                        let mut body = vec![0, 1];
+                        ShortString("slow").serialize(&mut body).unwrap();
+                        body.extend(RESULT_VOID);
+                        frame_v4(frame_stream(&req), Opcode::Result, WARNING, &body)
+                    }
+                };
+                socket.write_all(&resp).unwrap();
+            }
+            versions
+        });
+        let mut client = Client::new(&addr).unwrap();
+        assert_eq!(CQL_VERSION_V4, client.protocol_version());
+        client
+            .query("use rust", Consistency::One, Vec::new())
+            .unwrap();
+        drop(client);
+        assert_eq!(vec![CQL_VERSION_V4; 2], server.join().unwrap());
     }
 }
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use super::{
+    query, Client, Consistency, CqlColDescr, CqlReader, Error, Metadata, Opcode, Result, Value,
+};
+
+// Rows with each column left as the bytes on the wire, for callers doing
+// their own decoding. A null column is None.
+#[derive(Clone, Debug)]
+pub struct RawRows {
+    metadata: Arc<Metadata>,
+    rows: Vec<Vec<Option<Vec<u8>>>>,
+}
+
+impl RawRows {
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn rows(&self) -> &[Vec<Option<Vec<u8>>>] {
+        &self.rows
+    }
+
+    // The columns of row `index` along with their declared types.
+    pub fn columns(&self, index: usize) -> impl Iterator<Item = (&CqlColDescr, Option<&[u8]>)> {
+        self.metadata
+            .row_metadata
+            .iter()
+            .zip(self.rows[index].iter())
+            .map(|(meta, col)| (&meta.col_type, col.as_deref()))
+    }
+
+    pub fn into_rows(self) -> Vec<Vec<Option<Vec<u8>>>> {
+        self.rows
+    }
+}
+
+pub(crate) fn query_raw_bytes(
+    client: &mut Client,
+    query_str: &str,
+    con: Consistency,
+    values: Vec<Value>,
+) -> Result<RawRows> {
+    let mut req = query(0, query_str, con, values);
+    req.body.params.timestamp = client.next_timestamp();
+    let (header, length) = client.send_header(req)?;
+    let frame = client.read_frame(header, length)?;
+
+    let mut body = frame.body();
+    if let Opcode::Result = frame.header().opcode {
+        body.read_cql_prelude(frame.header())?;
+        if body.read_u32::<BigEndian>()? == 0x0002 {
+            return read_raw_rows(&mut body);
+        }
+    }
+    // Most likely an ERROR, which becomes Error::Cql.
+    frame.decode()?.into_result()?;
+    Err(Error::Protocol)
+}
+
+fn read_raw_rows(body: &mut &[u8]) -> Result<RawRows> {
+    let metadata = Arc::new(body.read_cql_metadata()?);
+    let rows_count = body.read_u32::<BigEndian>()?;
+    let col_count = metadata.row_metadata.len();
+
+    let mut rows = Vec::with_capacity(rows_count as usize);
+    for _ in 0..rows_count {
+        let mut cols = Vec::with_capacity(col_count);
+        for _ in 0..col_count {
+            cols.push(body.read_cql_bytes()?);
+        }
+        rows.push(cols);
+    }
+    Ok(RawRows { metadata, rows })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::{frame, frame_stream, mock_server};
+    use ColumnType;
+
+    #[test]
+    fn query_raw_bytes_rows() {
+        // id varchar and value float: ("asdf", 1.5), then (null, 1.5).
+        let mut body = vec![
+            0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 4, b'r', b'u', b's', b't', 0, 4, b't', b'e',
+            b's', b't', 0, 2, b'i', b'd', 0, 13, 0, 5, b'v', b'a', b'l', b'u', b'e', 0, 8, 0, 0, 0,
+            2,
+        ];
+        let cells: &[u8] = &[
+            0, 0, 0, 4, b'a', b's', b'd', b'f', 0, 0, 0, 4, 63, 192, 0, 0, 255, 255, 255, 255, 0,
+            0, 0, 4, 63, 192, 0, 0,
+        ];
+        body.extend_from_slice(cells);
+        let mut answered = false;
+        let (addr, server) = mock_server(1, move |req| {
+            if answered {
+                return frame(frame_stream(req), Opcode::Error, b"\0\0\x22\0\0\x04oops");
+            }
+            answered = true;
+            frame(frame_stream(req), Opcode::Result, &body)
+        });
+
+        let mut client = Client::new(&addr).unwrap();
+        let rows = client
+            .query_raw_bytes("select * from rust.test", Consistency::One, Vec::new())
+            .unwrap();
+        assert_eq!(2, rows.rows().len());
+        let first: Vec<_> = rows.columns(0).collect();
+        match first.as_slice() {
+            [(&CqlColDescr::Single(ColumnType::VarChar), Some(id)), (&CqlColDescr::Single(ColumnType::Float), Some(value))] =>
+            {
+                assert_eq!(b"asdf", id);
+                assert_eq!(&[63, 192, 0, 0], value);
+            }
+            cols => panic!("unexpected columns: {:?}", cols),
+        }
+        assert_eq!(
+            vec![None, Some(vec![63, 192, 0, 0])],
+            rows.into_rows().pop().unwrap()
+        );
+
+        match client.query_raw_bytes("select * from nope", Consistency::One, Vec::new()) {
+            Err(Error::Cql { code: 0x2200, .. }) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        drop(client);
+        server.join().unwrap();
+    }
+}
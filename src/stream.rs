@@ -0,0 +1,412 @@
+use std::io;
+use std::io::Read;
+use std::sync::Arc;
+use std::vec;
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use super::{
+    decode_response, query, Client, Consistency, CqlReader, Error, Metadata, Opcode, Result, Row,
+    Value, COMPRESSED,
+};
+
+// Rows requested per page by `Client::query_stream` and `query_iter`.
+pub const STREAM_PAGE_SIZE: i32 = 5000;
+
+// The part of a ROWS response still on the socket, or in `inflated` when
+// the response was compressed.
+struct Page {
+    metadata: Arc<Metadata>,
+    rows_left: u32,
+    body_left: u64,
+    inflated: Option<io::Cursor<Vec<u8>>>,
+}
+
+// Rows of a query, decoded one at a time from the socket. Dropping the
+// stream early discards the rest of the current page; no further pages are
+// requested.
+pub struct RowStream<'a> {
+    client: &'a mut Client,
+    query_str: String,
+    con: Consistency,
+    values: Vec<Value>,
+    page: Page,
+    done: bool,
+}
+
+impl<'a> RowStream<'a> {
+    pub(crate) fn new(
+        client: &'a mut Client,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+    ) -> Result<RowStream<'a>> {
+        let page = fetch(client, query_str, &con, &values, None)?;
+        Ok(RowStream {
+            client,
+            query_str: query_str.to_owned(),
+            con,
+            values,
+            page,
+            done: false,
+        })
+    }
+
+    // Metadata of the page being read.
+    pub fn metadata(&self) -> &Metadata {
+        &self.page.metadata
+    }
+
+    fn read_row(&mut self) -> Result<Option<Row>> {
+        while self.page.rows_left == 0 {
+            let inflated_left = self
+                .page
+                .inflated
+                .as_ref()
+                .map_or(0, |body| body.get_ref().len() as u64 - body.position());
+            if self.page.body_left != 0 || inflated_left != 0 {
+                return Err(Error::Protocol);
+            }
+            let paging_state = match self.page.metadata.paging_state {
+                Some(ref state) => state.clone(),
+                None => return Ok(None),
+            };
+            self.page = fetch(
+                self.client,
+                &self.query_str,
+                &self.con,
+                &self.values,
+                Some(paging_state),
+            )?;
+        }
+
+        let metadata = &self.page.metadata;
+        let cols = match self.page.inflated {
+            Some(ref mut body) => read_cols(body, metadata),
+            None => {
+                let mut body = (&mut self.client.reader).take(self.page.body_left);
+                let cols = read_cols(&mut body, metadata);
+                self.page.body_left = body.limit();
+                cols
+            }
+        };
+        self.page.rows_left -= 1;
+
+        let cols = cols?;
+        if self.client.strict {
+            cols.iter().try_for_each(|col| col.validate_strict())?;
+        }
+        Ok(Some(Row {
+            cols,
+            metadata: metadata.clone(),
+        }))
+    }
+}
+
+impl<'a> Iterator for RowStream<'a> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        if self.done {
+            return None;
+        }
+        match self.read_row() {
+            Ok(Some(row)) => Some(Ok(row)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a> Drop for RowStream<'a> {
+    fn drop(&mut self) {
+        // Keep the connection in sync for the client's next request.
+        let mut body = (&mut self.client.reader).take(self.page.body_left);
+        let _ = io::copy(&mut body, &mut io::sink());
+    }
+}
+
+// Rows of a query, a page at a time. Each page is decoded in full before
+// its rows are handed out; the next one is requested once they run out.
+pub struct RowIterator<'a> {
+    client: &'a mut Client,
+    query_str: String,
+    con: Consistency,
+    values: Vec<Value>,
+    page_size: i32,
+    rows: vec::IntoIter<Row>,
+    paging_state: Option<Vec<u8>>,
+}
+
+impl<'a> RowIterator<'a> {
+    pub(crate) fn new(
+        client: &'a mut Client,
+        query_str: &str,
+        con: Consistency,
+        values: Vec<Value>,
+        page_size: i32,
+    ) -> Result<RowIterator<'a>> {
+        let (rows, paging_state) =
+            client.query_paged(query_str, con.clone(), values.clone(), page_size, None)?;
+        Ok(RowIterator {
+            client,
+            query_str: query_str.to_owned(),
+            con,
+            values,
+            page_size,
+            rows: rows.rows.into_iter(),
+            paging_state,
+        })
+    }
+
+    fn next_page(&mut self, paging_state: Vec<u8>) -> Result<()> {
+        let (rows, paging_state) = self.client.query_paged(
+            &self.query_str,
+            self.con.clone(),
+            self.values.clone(),
+            self.page_size,
+            Some(paging_state),
+        )?;
+        self.rows = rows.rows.into_iter();
+        self.paging_state = paging_state;
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for RowIterator<'a> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Some(Ok(row));
+            }
+            // A page may come back empty with more to follow.
+            let paging_state = self.paging_state.take()?;
+            if let Err(e) = self.next_page(paging_state) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+fn read_cols<R: io::Read>(body: &mut R, metadata: &Metadata) -> Result<Vec<Value>> {
+    metadata
+        .row_metadata
+        .iter()
+        .map(|meta| body.read_cql_col(&meta.col_type))
+        .collect()
+}
+
+fn fetch(
+    client: &mut Client,
+    query_str: &str,
+    con: &Consistency,
+    values: &[Value],
+    paging_state: Option<Vec<u8>>,
+) -> Result<Page> {
+    let mut req = query(0, query_str, con.clone(), values.to_vec());
+    req.body.params.page_size = Some(STREAM_PAGE_SIZE);
+    req.body.params.paging_state = paging_state;
+    req.body.params.timestamp = client.next_timestamp();
+    let (header, length) = client.send_header(req)?;
+    trace!("streaming response: {:?}", header);
+
+    if header.flags() & COMPRESSED != 0 {
+        // Rows can only be decoded once the whole body is inflated.
+        let frame = client.read_frame(header, length)?;
+        if let Opcode::Result = frame.header().opcode {
+            let mut body = io::Cursor::new(frame.body().to_vec());
+            body.read_cql_prelude(frame.header())?;
+            let mut page = read_page(&mut body)?;
+            page.inflated = Some(body);
+            return Ok(page);
+        }
+        frame.decode()?.into_result()?;
+        return Err(Error::Protocol);
+    }
+
+    let mut body = (&mut client.reader).take(u64::from(length));
+    if let Opcode::Result = header.opcode {
+        let page = body
+            .read_cql_prelude(&header)
+            .and_then(|_| read_page(&mut body))
+            .map(|mut page| {
+                page.body_left = body.limit();
+                page
+            });
+        if page.is_err() {
+            let _ = io::copy(&mut body, &mut io::sink());
+        }
+        return page;
+    }
+
+    // Most likely an ERROR, which becomes Error::Cql.
+    let data = body.read_bytes(length as usize)?;
+    decode_response(header, &data, None)?.into_result()?;
+    Err(Error::Protocol)
+}
+
+// The body is left for the caller to account for.
+fn read_page<R: io::Read>(body: &mut R) -> Result<Page> {
+    if body.read_u32::<BigEndian>()? != 0x0002 {
+        return Err(Error::Protocol);
+    }
+    let metadata = Arc::new(body.read_cql_metadata()?);
+    let rows_left = body.read_u32::<BigEndian>()?;
+    Ok(Page {
+        metadata,
+        rows_left,
+        body_left: 0,
+        inflated: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use tests::{frame, frame_stream, mock_server};
+    use ClientBuilder;
+
+    // A page of `select id from ks.t` with the given int ids, followed by
+    // `paging_state` if there are more pages.
+    fn page(ids: &[i32], paging_state: Option<&[u8]>) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 2];
+        let flags = if paging_state.is_some() { 3 } else { 1 };
+        body.write_u32::<BigEndian>(flags).unwrap();
+        body.write_u32::<BigEndian>(1).unwrap();
+        if let Some(state) = paging_state {
+            body.write_i32::<BigEndian>(state.len() as i32).unwrap();
+            body.extend_from_slice(state);
+        }
+        for s in &["ks", "t", "id"] {
+            body.write_u16::<BigEndian>(s.len() as u16).unwrap();
+            body.extend_from_slice(s.as_bytes());
+        }
+        body.write_u16::<BigEndian>(0x0009).unwrap();
+        body.write_u32::<BigEndian>(ids.len() as u32).unwrap();
+        for id in ids {
+            body.write_i32::<BigEndian>(4).unwrap();
+            body.write_i32::<BigEndian>(*id).unwrap();
+        }
+        body
+    }
+
+    fn ids(stream: RowStream) -> Vec<i32> {
+        stream
+            .map(|row| match row.unwrap().into_values().as_slice() {
+                [Value::CqlInt(id)] => *id,
+                v => panic!("unexpected row: {:?}", v),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn query_iter_pages() {
+        let mut pages = vec![
+            page(&[1, 2], Some(b"one")),
+            page(&[], Some(b"two")),
+            page(&[3], None),
+        ]
+        .into_iter();
+        let (addr, server) = mock_server(1, move |req| {
+            frame(frame_stream(req), Opcode::Result, &pages.next().unwrap())
+        });
+
+        let mut client = ClientBuilder::new(&addr).connect().unwrap();
+        let ids: Vec<i32> = client
+            .query_iter("select id from ks.t", Consistency::One, Vec::new())
+            .unwrap()
+            .map(|row| match row.unwrap().into_values().as_slice() {
+                [Value::CqlInt(id)] => *id,
+                v => panic!("unexpected row: {:?}", v),
+            })
+            .collect();
+        assert_eq!(vec![1, 2, 3], ids);
+        drop(client);
+
+        let frames = server.join().unwrap();
+        assert_eq!(4, frames.len());
+        // Each request after the first sends back the last paging state.
+        assert_eq!(0x04, frames[1][frames[1].len() - 5]);
+        assert_eq!(b"one", &frames[2][frames[2].len() - 3..]);
+        assert_eq!(b"two", &frames[3][frames[3].len() - 3..]);
+    }
+
+    #[test]
+    fn query_stream_pages() {
+        let mut pages = vec![
+            page(&[1, 2], Some(b"next")),
+            page(&[3], None),
+            page(&[4, 5], Some(b"next")),
+        ]
+        .into_iter();
+        let (addr, server) = mock_server(1, move |req| {
+            if req[4] == Opcode::Opts as u8 {
+                return frame(frame_stream(req), Opcode::Supported, &[0, 0]);
+            }
+            frame(frame_stream(req), Opcode::Result, &pages.next().unwrap())
+        });
+
+        let mut client = ClientBuilder::new(&addr).connect().unwrap();
+        let stream = client
+            .query_stream("select id from ks.t", Consistency::One, Vec::new())
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], ids(stream));
+
+        // A stream dropped midway leaves the connection usable.
+        let mut stream = client
+            .query_stream("select id from ks.t", Consistency::One, Vec::new())
+            .unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        drop(stream);
+        client.ping().unwrap();
+        drop(client);
+
+        let frames = server.join().unwrap();
+        assert_eq!(5, frames.len());
+        // The first request asks for a page size, the second also sends back
+        // the paging state.
+        assert_eq!(0x04, frames[1][frames[1].len() - 5]);
+        assert_eq!(b"next", &frames[2][frames[2].len() - 4..]);
+        assert_eq!(0x0c, frames[2][frames[2].len() - 13]);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn query_stream_compressed_pages() {
+        use {Compression, COMPRESSED};
+
+        let mut pages = vec![page(&[1, 2], Some(b"next")), page(&[3], None)].into_iter();
+        let (addr, server) = mock_server(1, move |req| {
+            if req[4] == Opcode::Opts as u8 {
+                let mut body = vec![0, 1, 0, 11];
+                body.extend_from_slice(b"COMPRESSION");
+                body.extend_from_slice(b"\0\x01\0\x03lz4");
+                return frame(frame_stream(req), Opcode::Supported, &body);
+            }
+            let body = Compression::Lz4.compress(&pages.next().unwrap());
+            let mut resp = frame(frame_stream(req), Opcode::Result, &body);
+            resp[1] = COMPRESSED;
+            resp
+        });
+
+        let mut client = ClientBuilder::new(&addr)
+            .compression(true)
+            .connect()
+            .unwrap();
+        let stream = client
+            .query_stream("select id from ks.t", Consistency::One, Vec::new())
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], ids(stream));
+        drop(client);
+        server.join().unwrap();
+    }
+}